@@ -0,0 +1,525 @@
+//! Agent keeping private keys in memory and signing with them for the
+//! clients, so that passphrases are typed once.
+//!
+//! The agent listens on a Unix socket whose path is in the `NSSH_AUTH_SOCK`
+//! variable. Every message is a u32 length followed by a string naming the
+//! request or the response and its fields, in the [`crate::wire`] encoding:
+//!
+//! | request                                        | response                  |
+//! |------------------------------------------------|---------------------------|
+//! | `add` key blob, comment, certificate or empty  | `success`                 |
+//! | `list`                                         | `identities` u32 n, blobs |
+//! | `remove` public key blob                       | `success`                 |
+//! | `remove-all`                                   | `success`                 |
+//! | `lock` passphrase, `unlock` passphrase         | `success`                 |
+//! | `sign` public key or certificate blob, data    | `signature` signature     |
+//!
+//! Any request can also be answered by `failure` with a message. A locked
+//! agent lists no keys and refuses to sign until it is unlocked.
+
+use crate::keys::{Certificate, PrivateKey, PublicKey};
+use crate::server::PasswordHash;
+use crate::wire::{Reader, Writer};
+use std::env;
+use std::io::{self, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Variable holding the path of the socket of the agent
+pub const AUTH_SOCK_ENV: &str = "NSSH_AUTH_SOCK";
+
+/// Longest message accepted, requests only carry keys and small data
+const MAX_MESSAGE_LEN: usize = 256 * 1024;
+
+/// The lock passphrase only lives as long as the agent, it does not need a
+/// slow hash
+const LOCK_ITERATIONS: u32 = 10_000;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Write a message prefixed by its length
+pub(crate) fn write_message(stream: &mut impl Write, message: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(4 + message.len());
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(message);
+    stream.write_all(&frame)
+}
+
+/// Read a message written by [`write_message`]
+pub(crate) fn read_message(stream: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut length = [0; 4];
+    stream.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_MESSAGE_LEN {
+        return Err(invalid("agent message too long"));
+    }
+    let mut message = vec![0; length];
+    stream.read_exact(&mut message)?;
+    Ok(message)
+}
+
+/// Answer of an agent refusing a request, for those relaying its messages
+pub(crate) fn failure(message: &str) -> Vec<u8> {
+    Response::Failure(message.to_string()).to_bytes()
+}
+
+/// A key of the agent as listed to its clients
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    /// Blob of the public key or of a certificate of the key
+    pub blob: Vec<u8>,
+    pub comment: String,
+}
+
+impl Identity {
+    /// The certificate, if the identity is one
+    pub fn certificate(&self) -> Option<Certificate> {
+        if Certificate::is_certificate(&self.blob) {
+            Certificate::from_blob(&self.blob).ok()
+        } else {
+            None
+        }
+    }
+
+    /// The public key, the certified one for a certificate
+    pub fn public_key(&self) -> io::Result<PublicKey> {
+        if Certificate::is_certificate(&self.blob) {
+            Ok(Certificate::from_blob(&self.blob)?.key)
+        } else {
+            PublicKey::from_blob(&self.blob)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Request {
+    Add {
+        key: PrivateKey,
+        comment: String,
+        certificate: Option<Box<Certificate>>,
+    },
+    List,
+    Remove(PublicKey),
+    RemoveAll,
+    Lock(String),
+    Unlock(String),
+    Sign {
+        blob: Vec<u8>,
+        data: Vec<u8>,
+    },
+}
+
+impl Request {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        match self {
+            Request::Add {
+                key,
+                comment,
+                certificate,
+            } => {
+                let certificate = certificate.as_ref().map(|certificate| certificate.to_blob());
+                writer
+                    .string("add")
+                    .string(key.to_blob())
+                    .string(comment)
+                    .string(certificate.unwrap_or_default())
+            }
+            Request::List => writer.string("list"),
+            Request::Remove(key) => writer.string("remove").string(key.to_blob()),
+            Request::RemoveAll => writer.string("remove-all"),
+            Request::Lock(passphrase) => writer.string("lock").string(passphrase),
+            Request::Unlock(passphrase) => writer.string("unlock").string(passphrase),
+            Request::Sign { blob, data } => writer.string("sign").string(blob).string(data),
+        };
+        writer.into_bytes()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        let request = match reader.str()? {
+            "add" => {
+                let key = PrivateKey::from_blob(reader.string()?)?;
+                let comment = reader.str()?.to_string();
+                let certificate = match reader.string()? {
+                    [] => None,
+                    blob => Some(Box::new(Certificate::from_blob(blob)?)),
+                };
+                Request::Add {
+                    key,
+                    comment,
+                    certificate,
+                }
+            }
+            "list" => Request::List,
+            "remove" => Request::Remove(PublicKey::from_blob(reader.string()?)?),
+            "remove-all" => Request::RemoveAll,
+            "lock" => Request::Lock(reader.str()?.to_string()),
+            "unlock" => Request::Unlock(reader.str()?.to_string()),
+            "sign" => Request::Sign {
+                blob: reader.string()?.to_vec(),
+                data: reader.string()?.to_vec(),
+            },
+            other => return Err(invalid(&format!("unknown agent request {other}"))),
+        };
+        reader.finish()?;
+        Ok(request)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Response {
+    Success,
+    Failure(String),
+    Identities(Vec<Identity>),
+    Signature(Vec<u8>),
+}
+
+impl Response {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        match self {
+            Response::Success => writer.string("success"),
+            Response::Failure(message) => writer.string("failure").string(message),
+            Response::Identities(identities) => {
+                writer.string("identities").u32(identities.len() as u32);
+                for identity in identities {
+                    writer.string(&identity.blob).string(&identity.comment);
+                }
+                &mut writer
+            }
+            Response::Signature(signature) => writer.string("signature").string(signature),
+        };
+        writer.into_bytes()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        let response = match reader.str()? {
+            "success" => Response::Success,
+            "failure" => Response::Failure(reader.str()?.to_string()),
+            "identities" => {
+                let count = reader.u32()?;
+                let mut identities = Vec::new();
+                for _ in 0..count {
+                    identities.push(Identity {
+                        blob: reader.string()?.to_vec(),
+                        comment: reader.str()?.to_string(),
+                    });
+                }
+                Response::Identities(identities)
+            }
+            "signature" => Response::Signature(reader.string()?.to_vec()),
+            other => return Err(invalid(&format!("unknown agent response {other}"))),
+        };
+        reader.finish()?;
+        Ok(response)
+    }
+}
+
+struct AgentKey {
+    key: PrivateKey,
+    comment: String,
+    certificate: Option<Certificate>,
+}
+
+/// The keys held by an agent, see the module documentation.
+#[derive(Default)]
+pub struct Agent {
+    keys: Vec<AgentKey>,
+    lock: Option<PasswordHash>,
+}
+
+impl Agent {
+    pub fn new() -> Self {
+        Agent::default()
+    }
+
+    fn handle(&mut self, request: Request) -> Response {
+        let locked = || Response::Failure(String::from("the agent is locked"));
+        match (request, &self.lock) {
+            (Request::Unlock(passphrase), Some(lock)) => {
+                if !lock.verify(&passphrase) {
+                    return Response::Failure(String::from("wrong passphrase"));
+                }
+                self.lock = None;
+            }
+            (Request::Unlock(_), None) => {
+                return Response::Failure(String::from("the agent is not locked"))
+            }
+            (Request::List, Some(_)) => return Response::Identities(Vec::new()),
+            (_, Some(_)) => return locked(),
+            (
+                Request::Add {
+                    key,
+                    comment,
+                    certificate,
+                },
+                None,
+            ) => {
+                // adding a key again replaces its comment and certificate
+                self.keys.retain(|entry| entry.key != key);
+                self.keys.push(AgentKey {
+                    key,
+                    comment,
+                    certificate: certificate.map(|certificate| *certificate),
+                });
+            }
+            (Request::List, None) => {
+                // certificates first, a server trusting their CA may not
+                // know the key itself
+                let mut identities = Vec::new();
+                for entry in &self.keys {
+                    if let Some(certificate) = &entry.certificate {
+                        identities.push(Identity {
+                            blob: certificate.to_blob(),
+                            comment: entry.comment.clone(),
+                        });
+                    }
+                }
+                for entry in &self.keys {
+                    identities.push(Identity {
+                        blob: entry.key.public_key().to_blob(),
+                        comment: entry.comment.clone(),
+                    });
+                }
+                return Response::Identities(identities);
+            }
+            (Request::Remove(key), None) => {
+                let count = self.keys.len();
+                self.keys.retain(|entry| entry.key.public_key() != key);
+                if self.keys.len() == count {
+                    return Response::Failure(String::from("unknown key"));
+                }
+            }
+            (Request::RemoveAll, None) => self.keys.clear(),
+            (Request::Lock(passphrase), None) => {
+                self.lock = Some(PasswordHash::new(&passphrase, LOCK_ITERATIONS));
+            }
+            (Request::Sign { blob, data }, None) => {
+                let entry = self.keys.iter().find(|entry| {
+                    entry.key.public_key().to_blob() == blob
+                        || entry
+                            .certificate
+                            .as_ref()
+                            .is_some_and(|certificate| certificate.to_blob() == blob)
+                });
+                return match entry.map(|entry| entry.key.sign(&data)) {
+                    Some(Ok(signature)) => Response::Signature(signature),
+                    Some(Err(e)) => Response::Failure(e.to_string()),
+                    None => Response::Failure(String::from("unknown key")),
+                };
+            }
+        }
+        Response::Success
+    }
+
+    /// Answer the requests of one client until it disconnects
+    fn serve_client(agent: &Mutex<Agent>, mut stream: UnixStream) -> io::Result<()> {
+        loop {
+            let message = match read_message(&mut stream) {
+                Ok(message) => message,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            let response = match Request::from_bytes(&message) {
+                Ok(request) => agent.lock().unwrap().handle(request),
+                Err(e) => Response::Failure(e.to_string()),
+            };
+            write_message(&mut stream, &response.to_bytes())?;
+        }
+    }
+
+    /// Accept clients on `listener` forever, each one in its own thread
+    pub fn serve(self, listener: UnixListener) {
+        let agent = Arc::new(Mutex::new(self));
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let agent = Arc::clone(&agent);
+                    thread::spawn(move || {
+                        if let Err(e) = Agent::serve_client(&agent, stream) {
+                            eprintln!("Error handling agent client: {e}");
+                        }
+                    });
+                }
+                Err(e) => eprintln!("Failed to accept an agent client: {e}"),
+            }
+        }
+    }
+}
+
+/// A connection to an agent.
+pub struct AgentClient {
+    stream: UnixStream,
+}
+
+impl AgentClient {
+    /// Connect to the agent listening at `path`
+    pub fn connect(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(AgentClient {
+            stream: UnixStream::connect(path)?,
+        })
+    }
+
+    /// Connect to the agent of `NSSH_AUTH_SOCK`, `None` if it is not set
+    pub fn from_env() -> Option<io::Result<Self>> {
+        env::var_os(AUTH_SOCK_ENV).map(AgentClient::connect)
+    }
+
+    /// Send a message as it is and return the answer, to relay the
+    /// messages of a forwarded agent
+    pub(crate) fn forward(&mut self, message: &[u8]) -> io::Result<Vec<u8>> {
+        write_message(&mut self.stream, message)?;
+        read_message(&mut self.stream)
+    }
+
+    fn request(&mut self, request: &Request) -> io::Result<Response> {
+        write_message(&mut self.stream, &request.to_bytes())?;
+        match Response::from_bytes(&read_message(&mut self.stream)?)? {
+            Response::Failure(message) => Err(io::Error::other(message)),
+            response => Ok(response),
+        }
+    }
+
+    /// Send a request answered by `success`
+    fn command(&mut self, request: &Request) -> io::Result<()> {
+        match self.request(request)? {
+            Response::Success => Ok(()),
+            _ => Err(invalid("unexpected agent response")),
+        }
+    }
+
+    /// Give `key` to the agent, with its certificate if it has one
+    pub fn add(
+        &mut self,
+        key: &PrivateKey,
+        comment: &str,
+        certificate: Option<&Certificate>,
+    ) -> io::Result<()> {
+        self.command(&Request::Add {
+            key: key.clone(),
+            comment: comment.to_string(),
+            certificate: certificate.cloned().map(Box::new),
+        })
+    }
+
+    /// The keys and certificates of the agent, certificates first
+    pub fn identities(&mut self) -> io::Result<Vec<Identity>> {
+        match self.request(&Request::List)? {
+            Response::Identities(identities) => Ok(identities),
+            _ => Err(invalid("unexpected agent response")),
+        }
+    }
+
+    /// Forget `key` and its certificate
+    pub fn remove(&mut self, key: &PublicKey) -> io::Result<()> {
+        self.command(&Request::Remove(key.clone()))
+    }
+
+    pub fn remove_all(&mut self) -> io::Result<()> {
+        self.command(&Request::RemoveAll)
+    }
+
+    /// Refuse to list or use the keys until [`AgentClient::unlock`] is
+    /// called with the same passphrase
+    pub fn lock(&mut self, passphrase: &str) -> io::Result<()> {
+        self.command(&Request::Lock(passphrase.to_string()))
+    }
+
+    pub fn unlock(&mut self, passphrase: &str) -> io::Result<()> {
+        self.command(&Request::Unlock(passphrase.to_string()))
+    }
+
+    /// Sign `data` with the key of `blob`, a public key or certificate blob
+    /// as listed by [`AgentClient::identities`]
+    pub fn sign(&mut self, blob: &[u8], data: &[u8]) -> io::Result<Vec<u8>> {
+        let request = Request::Sign {
+            blob: blob.to_vec(),
+            data: data.to_vec(),
+        };
+        match self.request(&request)? {
+            Response::Signature(signature) => Ok(signature),
+            _ => Err(invalid("unexpected agent response")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{test_ed25519_key, test_rsa_key, CertificateType};
+
+    /// Send `request` through its encoding, like a client would
+    fn send(agent: &mut Agent, request: Request) -> Response {
+        let request = Request::from_bytes(&request.to_bytes()).unwrap();
+        Response::from_bytes(&agent.handle(request).to_bytes()).unwrap()
+    }
+
+    #[test]
+    fn keys() {
+        let mut agent = Agent::new();
+        let key = test_ed25519_key();
+        let ca = test_rsa_key();
+        let mut certificate = Certificate::new(key.public_key(), CertificateType::User);
+        certificate.sign(&ca).unwrap();
+
+        let add = Request::Add {
+            key: key.clone(),
+            comment: String::from("alice@laptop"),
+            certificate: Some(Box::new(certificate.clone())),
+        };
+        assert_eq!(send(&mut agent, add), Response::Success);
+        let Response::Identities(identities) = send(&mut agent, Request::List) else {
+            panic!("expected identities");
+        };
+        assert_eq!(identities.len(), 2);
+        assert_eq!(identities[0].certificate(), Some(certificate.clone()));
+        assert_eq!(identities[1].public_key().unwrap(), key.public_key());
+        assert_eq!(identities[1].comment, "alice@laptop");
+
+        let sign = Request::Sign {
+            blob: certificate.to_blob(),
+            data: b"data".to_vec(),
+        };
+        let Response::Signature(signature) = send(&mut agent, sign.clone()) else {
+            panic!("expected a signature");
+        };
+        assert!(key.public_key().verify(b"data", &signature));
+
+        // nothing but unlocking works while the agent is locked
+        let lock = Request::Lock(String::from("secret"));
+        assert_eq!(send(&mut agent, lock), Response::Success);
+        assert_eq!(
+            send(&mut agent, Request::List),
+            Response::Identities(Vec::new())
+        );
+        assert!(matches!(
+            send(&mut agent, sign.clone()),
+            Response::Failure(_)
+        ));
+        let unlock = |passphrase: &str| Request::Unlock(passphrase.to_string());
+        assert!(matches!(
+            send(&mut agent, unlock("wrong")),
+            Response::Failure(_)
+        ));
+        assert_eq!(send(&mut agent, unlock("secret")), Response::Success);
+        assert!(matches!(
+            send(&mut agent, sign.clone()),
+            Response::Signature(_)
+        ));
+
+        let remove = Request::Remove(key.public_key());
+        assert_eq!(send(&mut agent, remove.clone()), Response::Success);
+        assert!(matches!(send(&mut agent, remove), Response::Failure(_)));
+        assert!(matches!(send(&mut agent, sign), Response::Failure(_)));
+        assert_eq!(
+            send(&mut agent, Request::List),
+            Response::Identities(Vec::new())
+        );
+    }
+}
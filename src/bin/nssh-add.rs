@@ -0,0 +1,185 @@
+//! Manage the keys of the NSSH agent.
+
+use nssh::agent::{AgentClient, AUTH_SOCK_ENV};
+use nssh::client::load_certificate;
+use nssh::keys::{PrivateKey, PublicKey};
+use nssh::terminal;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "usage: nssh-add [<key file>...]
+       nssh-add -l | -d <key file>... | -D | -x | -X
+
+Add private keys to the agent of NSSH_AUTH_SOCK, with their certificate
+<file>-cert.pub if there is one. Without file, ~/.nssh/id_ed25519 and
+~/.nssh/id_rsa are added.
+  -l  list the keys of the agent
+  -d  remove keys from the agent
+  -D  remove every key from the agent
+  -x  lock the agent with a passphrase
+  -X  unlock the agent";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Add,
+    List,
+    Remove,
+    RemoveAll,
+    Lock,
+    Unlock,
+}
+
+/// Print an error and quit
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("nssh-add: {message}");
+    process::exit(1);
+}
+
+/// The default keys that exist
+fn default_keys() -> Vec<PathBuf> {
+    let Some(home) = env::var_os("HOME") else {
+        return Vec::new();
+    };
+    ["id_ed25519", "id_rsa"]
+        .iter()
+        .map(|name| PathBuf::from(&home).join(".nssh").join(name))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+fn add(agent: &mut AgentClient, path: &Path) -> io::Result<()> {
+    let text = fs::read_to_string(path)?;
+    let passphrase = match PrivateKey::is_encrypted(&text) {
+        true => Some(terminal::read_password(&format!(
+            "Enter passphrase for {}: ",
+            path.display()
+        ))?),
+        false => None,
+    };
+    let (key, comment) = PrivateKey::parse_with_comment(&text, passphrase.as_deref())?;
+    let comment = match comment.is_empty() {
+        true => path.display().to_string(),
+        false => comment,
+    };
+    let certificate = load_certificate(path)?;
+    agent.add(&key, &comment, certificate.as_ref())?;
+
+    println!("Identity added: {} ({comment})", path.display());
+    if let Some(certificate) = certificate {
+        println!(
+            "Certificate added: {} (serial {})",
+            certificate.key_id, certificate.serial
+        );
+    }
+    Ok(())
+}
+
+/// The public key of a key file, read from `<file>.pub` for a private key
+fn public_key(path: &Path) -> io::Result<PublicKey> {
+    let text = fs::read_to_string(path)?;
+    if let Ok((key, _)) = PublicKey::parse(&text) {
+        return Ok(key);
+    }
+    let mut public = path.as_os_str().to_owned();
+    public.push(".pub");
+    match fs::read_to_string(PathBuf::from(public)) {
+        Ok(text) => Ok(PublicKey::parse(&text)?.0),
+        Err(_) => Ok(PrivateKey::parse(&text)?.public_key()),
+    }
+}
+
+fn list(agent: &mut AgentClient) -> io::Result<()> {
+    let identities = agent.identities()?;
+    if identities.is_empty() {
+        println!("The agent has no identities.");
+    }
+    for identity in identities {
+        let key = identity.public_key()?;
+        let kind = match identity.certificate() {
+            Some(_) => format!("{}-CERT", key.type_name()),
+            None => key.type_name().to_string(),
+        };
+        println!(
+            "{} {} {} ({kind})",
+            key.bits(),
+            key.fingerprint(),
+            identity.comment
+        );
+    }
+    Ok(())
+}
+
+/// Read the lock passphrase, twice when locking
+fn lock_passphrase(confirm: bool) -> io::Result<String> {
+    let passphrase = terminal::read_password("Enter lock password: ")?;
+    if confirm && terminal::read_password("Again: ")? != passphrase {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "passwords do not match",
+        ));
+    }
+    Ok(passphrase)
+}
+
+fn main() {
+    let mut action = Action::Add;
+    let mut files = Vec::new();
+
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "-l" => action = Action::List,
+            "-d" => action = Action::Remove,
+            "-D" => action = Action::RemoveAll,
+            "-x" => action = Action::Lock,
+            "-X" => action = Action::Unlock,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return;
+            }
+            _ if !arg.starts_with('-') => files.push(PathBuf::from(arg)),
+            _ => fail(USAGE),
+        }
+    }
+    if files.is_empty() {
+        files = default_keys();
+    }
+
+    let mut agent = match AgentClient::from_env() {
+        Some(Ok(agent)) => agent,
+        Some(Err(e)) => fail(format!("failed to connect to the agent: {e}")),
+        None => fail(format!(
+            "{AUTH_SOCK_ENV} is not set, is nssh-agent running?"
+        )),
+    };
+
+    let result = match action {
+        Action::Add if files.is_empty() => fail("no key to add"),
+        Action::Add => files.iter().try_for_each(|path| {
+            add(&mut agent, path)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))
+        }),
+        Action::List => list(&mut agent),
+        Action::Remove => files.iter().try_for_each(|path| {
+            let key = public_key(path)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+            agent.remove(&key)?;
+            println!("Identity removed: {}", path.display());
+            Ok(())
+        }),
+        Action::RemoveAll => agent
+            .remove_all()
+            .map(|()| println!("All identities removed.")),
+        Action::Lock => lock_passphrase(true)
+            .and_then(|passphrase| agent.lock(&passphrase))
+            .map(|()| println!("Agent locked.")),
+        Action::Unlock => lock_passphrase(false)
+            .and_then(|passphrase| agent.unlock(&passphrase))
+            .map(|()| println!("Agent unlocked.")),
+    };
+    if let Err(e) = result {
+        fail(e);
+    }
+}
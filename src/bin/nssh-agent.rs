@@ -0,0 +1,118 @@
+//! Keep private keys in memory for the NSSH client.
+
+use nssh::agent::{Agent, AUTH_SOCK_ENV};
+use std::env;
+use std::fs;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::process;
+
+const USAGE: &str = "usage: nssh-agent [-a <socket>] [-d]
+       nssh-agent -k
+
+Start an agent holding the keys added with nssh-add and print the commands
+setting its environment, to be run with eval \"$(nssh-agent)\".
+  -a <socket>  path of the socket, in a new temporary directory by default
+  -d           stay in the foreground
+  -k           kill the agent of NSSH_AGENT_PID";
+
+const AGENT_PID_ENV: &str = "NSSH_AGENT_PID";
+
+/// Print an error and quit
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("nssh-agent: {message}");
+    process::exit(1);
+}
+
+/// `<tmp>/nssh-<random>/agent.<pid>`, in a directory only the user can open
+fn default_socket() -> PathBuf {
+    let dir = env::temp_dir().join(format!("nssh-{:016x}", rand::random::<u64>()));
+    fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&dir)
+        .unwrap_or_else(|e| fail(format!("{}: {e}", dir.display())));
+    dir.join(format!("agent.{}", process::id()))
+}
+
+/// Kill the agent of the environment and remove its socket
+fn kill() {
+    let pid: i32 = env::var(AGENT_PID_ENV)
+        .ok()
+        .and_then(|pid| pid.parse().ok())
+        .unwrap_or_else(|| fail(format!("{AGENT_PID_ENV} is not set")));
+    if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+        fail(format!("kill {pid}: {}", std::io::Error::last_os_error()));
+    }
+    if let Some(socket) = env::var_os(AUTH_SOCK_ENV).map(PathBuf::from) {
+        let _ = fs::remove_file(&socket);
+        if let Some(dir) = socket.parent() {
+            let _ = fs::remove_dir(dir);
+        }
+    }
+    println!("unset {AUTH_SOCK_ENV};");
+    println!("unset {AGENT_PID_ENV};");
+    println!("echo Agent pid {pid} killed;");
+}
+
+/// Leave the terminal and run in the background, returns in the child
+fn daemonize(socket: &str) {
+    match unsafe { libc::fork() } {
+        -1 => fail(format!("fork: {}", std::io::Error::last_os_error())),
+        0 => {}
+        pid => {
+            println!("{AUTH_SOCK_ENV}={socket}; export {AUTH_SOCK_ENV};");
+            println!("{AGENT_PID_ENV}={pid}; export {AGENT_PID_ENV};");
+            println!("echo Agent pid {pid};");
+            process::exit(0);
+        }
+    }
+
+    // SAFETY: only the main thread exists, the descriptors are replaced by
+    // /dev/null so that the agent keeps no terminal open
+    unsafe {
+        libc::setsid();
+        let null = libc::open(c"/dev/null".as_ptr(), libc::O_RDWR);
+        if null >= 0 {
+            for fd in 0..3 {
+                libc::dup2(null, fd);
+            }
+        }
+    }
+    let _ = env::set_current_dir("/");
+}
+
+fn main() {
+    let mut socket = None;
+    let mut foreground = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-a" => socket = Some(PathBuf::from(args.next().unwrap_or_else(|| fail(USAGE)))),
+            "-d" => foreground = true,
+            "-k" => return kill(),
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return;
+            }
+            _ => fail(USAGE),
+        }
+    }
+
+    let socket = socket.unwrap_or_else(default_socket);
+    let socket = std::path::absolute(&socket).unwrap_or(socket);
+    let listener =
+        UnixListener::bind(&socket).unwrap_or_else(|e| fail(format!("{}: {e}", socket.display())));
+    fs::set_permissions(&socket, fs::Permissions::from_mode(0o600))
+        .unwrap_or_else(|e| fail(format!("{}: {e}", socket.display())));
+
+    let path = socket.display().to_string();
+    if foreground {
+        println!("{AUTH_SOCK_ENV}={path}; export {AUTH_SOCK_ENV};");
+        println!("echo Agent pid {};", process::id());
+    } else {
+        daemonize(&path);
+    }
+    Agent::new().serve(listener);
+}
@@ -0,0 +1,71 @@
+//! Manage the users of a NSSH password file.
+
+use nssh::server::{PasswdFile, DEFAULT_ITERATIONS, DEFAULT_PASSWORD_FILE};
+use nssh::terminal;
+use std::env;
+use std::process;
+
+const USAGE: &str = "usage: nssh-passwd [-f <file>] [-i <iterations>] [-d] <login>
+
+Set the password of <login>, or remove it with -d.
+  -f <file>        password file, /etc/nssh/passwd by default
+  -i <iterations>  PBKDF2 iterations of the new hash";
+
+/// Print an error and quit
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("nssh-passwd: {message}");
+    process::exit(1);
+}
+
+fn main() {
+    let mut path = String::from(DEFAULT_PASSWORD_FILE);
+    let mut iterations = DEFAULT_ITERATIONS;
+    let mut delete = false;
+    let mut login = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-f" => path = args.next().unwrap_or_else(|| fail(USAGE)),
+            "-i" => {
+                iterations = args
+                    .next()
+                    .and_then(|i| i.parse().ok())
+                    .filter(|&i| i > 0)
+                    .unwrap_or_else(|| fail("-i expects a positive number"))
+            }
+            "-d" => delete = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return;
+            }
+            _ if login.is_none() && !arg.starts_with('-') => login = Some(arg),
+            _ => fail(USAGE),
+        }
+    }
+    let login = login.unwrap_or_else(|| fail(USAGE));
+    let passwd = PasswdFile::new(&path);
+
+    if delete {
+        match passwd.remove_user(&login) {
+            Ok(true) => println!("{login} removed from {path}"),
+            Ok(false) => fail(format!("{login} is not in {path}")),
+            Err(e) => fail(format!("{path}: {e}")),
+        }
+        return;
+    }
+
+    let password = terminal::read_password("New password: ").unwrap_or_else(|e| fail(e));
+    let again = terminal::read_password("Retype new password: ").unwrap_or_else(|e| fail(e));
+    if password != again {
+        fail("passwords do not match");
+    }
+    if password.is_empty() {
+        fail("empty password");
+    }
+
+    match passwd.set_password(&login, &password, iterations) {
+        Ok(()) => println!("password of {login} updated in {path}"),
+        Err(e) => fail(format!("{path}: {e}")),
+    }
+}
@@ -0,0 +1,96 @@
+//! Manage a NSSH revocation file.
+
+use nssh::keys::{Certificate, PrivateKey, PublicKey, Revocation, RevokedKeys};
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+
+const USAGE: &str = "usage: nssh-revoke [-f <file>] [-s <serial>] [<key>...]
+       nssh-revoke [-f <file>] -l
+
+Revoke keys, given as files (public key, private key or certificate) or as
+SHA256 fingerprints, and certificates by serial. A certificate file revokes
+its serial, or its key if it has no serial.
+  -f <file>    revocation file, /etc/nssh/revoked_keys by default
+  -s <serial>  revoke the certificates with this serial
+  -l           list the revoked keys and serials";
+
+const DEFAULT_REVOKED_KEYS: &str = "/etc/nssh/revoked_keys";
+
+/// Print an error and quit
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("nssh-revoke: {message}");
+    process::exit(1);
+}
+
+/// What revokes the key given on the command line, with a comment
+fn revocation_of(arg: &str) -> io::Result<(Revocation, String)> {
+    if arg.starts_with("SHA256:") {
+        return Ok((Revocation::Key(arg.to_string()), String::new()));
+    }
+
+    let text = fs::read_to_string(arg)?;
+    if let Ok((certificate, _)) = Certificate::parse_line(&text) {
+        let comment = format!("certificate {}", certificate.key_id);
+        return Ok(match certificate.serial {
+            0 => (Revocation::Key(certificate.key.fingerprint()), comment),
+            serial => (Revocation::Serial(serial), comment),
+        });
+    }
+    let (key, comment) = match PublicKey::parse(&text) {
+        Ok(key) => key,
+        Err(_) => PrivateKey::parse_with_comment(&text, None)
+            .map(|(key, comment)| (key.public_key(), comment))
+            .map_err(|e| io::Error::new(e.kind(), format!("{arg}: {e}")))?,
+    };
+    Ok((Revocation::Key(key.fingerprint()), comment))
+}
+
+fn main() {
+    let mut path = String::from(DEFAULT_REVOKED_KEYS);
+    let mut list = false;
+    let mut revocations = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-f" => path = args.next().unwrap_or_else(|| fail(USAGE)),
+            "-l" => list = true,
+            "-s" => {
+                let serial = args
+                    .next()
+                    .and_then(|serial| serial.parse().ok())
+                    .unwrap_or_else(|| fail("-s expects a number"));
+                revocations.push((Revocation::Serial(serial), String::new()));
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return;
+            }
+            _ if !arg.starts_with('-') => {
+                revocations.push(revocation_of(&arg).unwrap_or_else(|e| fail(e)))
+            }
+            _ => fail(USAGE),
+        }
+    }
+
+    let mut revoked = RevokedKeys::load(&path).unwrap_or_else(|e| fail(format!("{path}: {e}")));
+    if list {
+        for (revocation, comment) in revoked.entries() {
+            println!("{revocation} {comment}");
+        }
+        return;
+    }
+    if revocations.is_empty() {
+        fail(USAGE);
+    }
+
+    for (revocation, comment) in revocations {
+        match revoked.revoke(revocation.clone(), &comment) {
+            Ok(true) => println!("{revocation} revoked in {path}"),
+            Ok(false) => println!("{revocation} was already revoked"),
+            Err(e) => fail(format!("{path}: {e}")),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! Manage the TOTP secrets of the users of a NSSH server.
+
+use nssh::crypto::totp;
+use nssh::encoding::to_base32;
+use nssh::server::TotpFile;
+use std::env;
+use std::process;
+
+const USAGE: &str = "usage: nssh-totp [-f <file>] [-d] <login>
+
+Give a new TOTP secret to <login> and print it, to be entered in an
+authenticator application, or remove it with -d.
+  -f <file>  secrets file, /etc/nssh/totp by default";
+
+const DEFAULT_TOTP_FILE: &str = "/etc/nssh/totp";
+
+/// Print an error and quit
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("nssh-totp: {message}");
+    process::exit(1);
+}
+
+fn main() {
+    let mut path = String::from(DEFAULT_TOTP_FILE);
+    let mut delete = false;
+    let mut login = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-f" => path = args.next().unwrap_or_else(|| fail(USAGE)),
+            "-d" => delete = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return;
+            }
+            _ if login.is_none() && !arg.starts_with('-') => login = Some(arg),
+            _ => fail(USAGE),
+        }
+    }
+    let login = login.unwrap_or_else(|| fail(USAGE));
+    let totp_file = TotpFile::new(&path);
+
+    if delete {
+        match totp_file.remove_user(&login) {
+            Ok(true) => println!("secret of {login} removed from {path}"),
+            Ok(false) => fail(format!("{login} is not in {path}")),
+            Err(e) => fail(format!("{path}: {e}")),
+        }
+        return;
+    }
+
+    let secret = totp_file
+        .generate(&login)
+        .unwrap_or_else(|e| fail(format!("{path}: {e}")));
+    println!("secret of {login} written to {path}");
+    println!("secret: {} (SHA256, 6 digits, 30 s)", to_base32(&secret));
+    println!("{}", totp::uri(&secret, "NSSH", &login));
+}
@@ -0,0 +1,103 @@
+//! Networks written `address/prefix` (CIDR), to restrict the addresses a key
+//! can be used from.
+
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// An IPv4 or IPv6 network, a single address when written without prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    address: IpAddr,
+    prefix: u32,
+}
+
+impl Cidr {
+    /// Whether `ip` is in the network
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // an IPv4 client of an IPv6 socket is seen as ::ffff:a.b.c.d
+        match (self.address, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => same_prefix(
+                network.to_bits().into(),
+                ip.to_bits().into(),
+                32,
+                self.prefix,
+            ),
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                same_prefix(network.to_bits(), ip.to_bits(), 128, self.prefix)
+            }
+            _ => false,
+        }
+    }
+
+    /// Parse a comma separated list of networks, like `10.0.0.0/8,::1`
+    pub fn parse_list(list: &str) -> io::Result<Vec<Cidr>> {
+        list.split(',').map(|cidr| cidr.trim().parse()).collect()
+    }
+}
+
+/// Whether the first `prefix` bits of two addresses of `bits` bits are equal
+fn same_prefix(network: u128, ip: u128, bits: u32, prefix: u32) -> bool {
+    prefix == 0 || (network ^ ip) >> (bits - prefix) == 0
+}
+
+impl FromStr for Cidr {
+    type Err = io::Error;
+
+    fn from_str(text: &str) -> io::Result<Self> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid network {text}"),
+            )
+        };
+
+        let (address, prefix) = match text.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (text, None),
+        };
+        let address: IpAddr = address.parse().map_err(|_| invalid())?;
+        let bits = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().ok().filter(|&prefix| prefix <= bits),
+            None => Some(bits),
+        }
+        .ok_or_else(invalid)?;
+        Ok(Cidr { address, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn networks() {
+        let ip = |text: &str| text.parse::<IpAddr>().unwrap();
+        let network: Cidr = "192.168.1.0/24".parse().unwrap();
+        assert!(network.contains(ip("192.168.1.42")));
+        assert!(network.contains(ip("::ffff:192.168.1.42")));
+        assert!(!network.contains(ip("192.168.2.42")));
+        assert!(!network.contains(ip("::1")));
+        assert_eq!(network.to_string(), "192.168.1.0/24");
+
+        let single: Cidr = "::1".parse().unwrap();
+        assert!(single.contains(ip("::1")));
+        assert!(!single.contains(ip("::2")));
+        let any: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains(ip("8.8.8.8")));
+
+        let list = Cidr::parse_list("10.0.0.0/8, fe80::/10").unwrap();
+        assert!(list[1].contains(ip("fe80::1")));
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Cidr>().is_err());
+        assert!(Cidr::parse_list("10.0.0.0/8,").is_err());
+    }
+}
@@ -0,0 +1,157 @@
+//! The servers trusted by the client, `~/.nssh/known_hosts`.
+//!
+//! One entry per line, empty lines and lines starting with `#` are ignored:
+//!
+//! ```text
+//! 127.0.0.1 ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAI...
+//! @cert-authority *.example.com,10.0.0.1 ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAI... ca
+//! ```
+//!
+//! The first field is a comma separated list of host names, `*` matches any
+//! host and `*.example.com` any host ending with `.example.com`. A line
+//! starting with `@cert-authority` is a CA whose host certificates are
+//! trusted for those hosts.
+
+use crate::keys::PublicKey;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+const CERT_AUTHORITY: &str = "@cert-authority";
+
+/// Whether `host` is in a comma separated list of host patterns
+fn host_matches(patterns: &str, host: &str) -> bool {
+    patterns.split(',').any(|pattern| match pattern {
+        "*" => true,
+        pattern => match pattern.strip_prefix('*') {
+            Some(suffix) => host.ends_with(suffix),
+            None => pattern == host,
+        },
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    hosts: String,
+    key: PublicKey,
+    cert_authority: bool,
+}
+
+/// Parse one line of the file
+fn parse_entry(line: &str) -> Option<Entry> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let (cert_authority, line) = match line.strip_prefix(CERT_AUTHORITY) {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, line),
+    };
+    let (hosts, key) = line.split_once(char::is_whitespace)?;
+    let (key, _comment) = PublicKey::parse_line(key).ok()?;
+    Some(Entry {
+        hosts: hosts.to_string(),
+        key,
+        cert_authority,
+    })
+}
+
+/// A known hosts file, lines that can not be parsed are skipped.
+#[derive(Debug, Clone)]
+pub struct KnownHosts {
+    path: PathBuf,
+    entries: Vec<Entry>,
+}
+
+impl KnownHosts {
+    /// Read the file at `path`, empty if it does not exist yet
+    pub fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let entries = content.lines().filter_map(parse_entry).collect();
+        Ok(KnownHosts { path, entries })
+    }
+
+    fn matching(&self, host: &str, cert_authority: bool) -> Vec<PublicKey> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.cert_authority == cert_authority)
+            .filter(|entry| host_matches(&entry.hosts, host))
+            .map(|entry| entry.key.clone())
+            .collect()
+    }
+
+    /// Keys of `host`
+    pub fn host_keys(&self, host: &str) -> Vec<PublicKey> {
+        self.matching(host, false)
+    }
+
+    /// CAs trusted to sign the certificate of `host`
+    pub fn cert_authorities(&self, host: &str) -> Vec<PublicKey> {
+        self.matching(host, true)
+    }
+
+    /// Trust `key` for `host` from now on, the line is added to the file
+    pub fn add(&mut self, host: &str, key: &PublicKey) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{} {}", host, key.to_line(""))?;
+        self.entries.push(Entry {
+            hosts: host.to_string(),
+            key: key.clone(),
+            cert_authority: false,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys;
+    use std::env;
+
+    #[test]
+    fn patterns() {
+        assert!(host_matches("*", "example.com"));
+        assert!(host_matches("a.com,*.example.com", "www.example.com"));
+        assert!(host_matches("a.com,10.0.0.1", "10.0.0.1"));
+        assert!(!host_matches("*.example.com", "example.org"));
+        assert!(!host_matches("10.0.0.1", "10.0.0.10"));
+    }
+
+    #[test]
+    fn known_hosts_file() {
+        let path = env::temp_dir().join(format!("nssh-known-hosts-{}", std::process::id()));
+        let ca = keys::test_ed25519_key().public_key();
+        fs::write(
+            &path,
+            format!(
+                "# comment\n@cert-authority *.example.com {}\nbad line\n",
+                ca.to_line("ca")
+            ),
+        )
+        .unwrap();
+
+        let mut known = KnownHosts::load(&path).unwrap();
+        assert_eq!(known.cert_authorities("www.example.com"), vec![ca.clone()]);
+        assert!(known.cert_authorities("127.0.0.1").is_empty());
+        assert!(known.host_keys("www.example.com").is_empty());
+
+        let key = keys::test_rsa_key().public_key();
+        known.add("127.0.0.1", &key).unwrap();
+        assert_eq!(known.host_keys("127.0.0.1"), vec![key.clone()]);
+        let known = KnownHosts::load(&path).unwrap();
+        assert_eq!(known.host_keys("127.0.0.1"), vec![key]);
+        fs::remove_file(path).unwrap();
+    }
+}
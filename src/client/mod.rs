@@ -0,0 +1,783 @@
+//! NSSH client.
+//!
+//! ```no_run
+//! use nssh::client::{Config, Session};
+//!
+//! let mut session = Session::connect("127.0.0.1:7878", Config::default())?;
+//! if session.authenticate_password("admin", "admin")? {
+//!     let output = session.exec("uname -a")?;
+//!     print!("{}", String::from_utf8_lossy(&output.stdout));
+//!     session.close()?;
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+mod known_hosts;
+
+pub use known_hosts::KnownHosts;
+
+use crate::agent::{self, AgentClient, Identity};
+use crate::connection::{
+    Channel, ChannelMessage, Connection, Incoming, EXTENDED_DATA_STDERR, SESSION,
+};
+use crate::encoding::{from_hex, to_hex};
+use crate::keys::{Certificate, CertificateType, PrivateKey, PublicKey, RevokedKeys};
+use crate::server::{Conversation, Prompt};
+use crate::session::{env_matches, ExitStatus, ENV, SIGNAL};
+use crate::terminal::{self, WindowSize};
+use crate::transport::{
+    hostkey_auth_data, publickey_auth_data, Transport, AGENT_CHANNEL, AGENT_FORWARDING_REQUEST,
+    INFO_REQUEST, INFO_RESPONSE, KEYBOARD_INTERACTIVE, TOO_MANY_FAILURES,
+};
+use crate::wire::Writer;
+use std::cell::Cell;
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Exit code of [`connect_and_communicate`] when that of the command is
+/// unknown
+const UNKNOWN_EXIT_CODE: i32 = 255;
+
+/// How the client decides to trust the server it connects to.
+pub struct Config {
+    /// Fingerprints of the host keys already trusted
+    pub known_hosts: Vec<String>,
+    /// CAs trusted to sign host certificates, a server with a valid
+    /// certificate for `host_name` signed by one of them is trusted
+    pub host_cert_authorities: Vec<PublicKey>,
+    /// Name of the server, it must be a principal of its certificate
+    pub host_name: String,
+    /// Host keys and certificates refused even if they are trusted
+    pub revoked_keys: RevokedKeys,
+    /// Called with the fingerprint of a server that is not in `known_hosts`,
+    /// the connection goes on only if it returns true. Unknown servers are
+    /// refused by default.
+    pub accept_unknown_host: Box<dyn FnMut(&str) -> bool>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            known_hosts: Vec::new(),
+            host_cert_authorities: Vec::new(),
+            host_name: String::new(),
+            revoked_keys: RevokedKeys::default(),
+            accept_unknown_host: Box::new(|_| false),
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Read the `hostkey\n<key or certificate blob>\n<signature>` message of
+/// the server and check that it owns the key
+fn read_host_key(
+    message: &str,
+    session_id: &[u8; 32],
+) -> io::Result<(PublicKey, Option<Certificate>)> {
+    let fields: Vec<&str> = message.split('\n').collect();
+    let ["hostkey", blob, signature] = fields.as_slice() else {
+        return Err(invalid("expected the host key of the server"));
+    };
+    let (Some(blob), Some(signature)) = (from_hex(blob), from_hex(signature)) else {
+        return Err(invalid("bad host key message"));
+    };
+
+    let (key, certificate) = if Certificate::is_certificate(&blob) {
+        let certificate = Certificate::from_blob(&blob)?;
+        (certificate.key.clone(), Some(certificate))
+    } else {
+        (PublicKey::from_blob(&blob)?, None)
+    };
+    if !key.verify(&hostkey_auth_data(session_id, &blob), &signature) {
+        return Err(invalid("bad host key signature"));
+    }
+    Ok((key, certificate))
+}
+
+/// What a command run with [`Session::exec`] produced.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Output {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// How the command ended, `None` when the server did not send it
+    pub exit_status: Option<ExitStatus>,
+}
+
+/// Answer the channels the server opens to the forwarded agent, the others
+/// are refused
+fn serve_server_channels(connection: Connection, agent: Arc<Mutex<Option<AgentClient>>>) {
+    while let Some(incoming) = connection.next_incoming() {
+        match incoming {
+            Incoming::Open(open) if open.kind() == AGENT_CHANNEL => {
+                let Ok(channel) = open.accept() else { break };
+                let agent = Arc::clone(&agent);
+                thread::spawn(move || relay_agent(channel, agent));
+            }
+            Incoming::Open(open) => {
+                let _ = open.reject("unknown channel type");
+            }
+            Incoming::Request(request) => {
+                let _ = request.reply(false);
+            }
+        }
+    }
+}
+
+/// Pass the messages of an agent channel to the agent and its answers back
+fn relay_agent(mut channel: Channel, agent: Arc<Mutex<Option<AgentClient>>>) {
+    while let Ok(message) = agent::read_message(&mut channel) {
+        let answer = match agent.lock().unwrap().as_mut() {
+            Some(agent) => agent
+                .forward(&message)
+                .unwrap_or_else(|e| agent::failure(&e.to_string())),
+            None => agent::failure("the agent is not forwarded"),
+        };
+        if agent::write_message(&mut channel, &answer).is_err() {
+            break;
+        }
+    }
+    let _ = channel.close();
+}
+
+/// A connection to a NSSH server.
+pub struct Session {
+    transport: Transport,
+    host_key: PublicKey,
+    /// Channels carried by the connection once authenticated
+    connection: Option<Connection>,
+    /// The agent forwarded to the server, used by the threads answering its
+    /// channels
+    agent: Arc<Mutex<Option<AgentClient>>>,
+    /// Answers the questions of the server during the authentication
+    conversation: Option<Box<dyn Conversation>>,
+    /// Variables sent for each command
+    env: Vec<(String, String)>,
+}
+
+impl Session {
+    /// Connect to the server at `addr`, run the hand shake and check that
+    /// the server can be trusted according to `config`.
+    pub fn connect<A: ToSocketAddrs>(addr: A, mut config: Config) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        let mut transport = Transport::client_handshake(stream)?;
+
+        // receive the host key of the server, trusted if its certificate is
+        // signed by a known CA or if the key itself is known
+        let (host_key, certificate) = read_host_key(&transport.receive()?, transport.session_id())?;
+        let revoked = match &certificate {
+            Some(certificate) => config.revoked_keys.is_certificate_revoked(certificate),
+            None => config.revoked_keys.is_key_revoked(&host_key),
+        };
+        if revoked {
+            transport.send("KO")?;
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("the host key {} is revoked", host_key.fingerprint()),
+            ));
+        }
+        let certified = certificate.is_some_and(|certificate| {
+            certificate
+                .signature_key()
+                .is_some_and(|ca| config.host_cert_authorities.contains(ca))
+                && certificate
+                    .check(CertificateType::Host, &config.host_name)
+                    .is_ok()
+        });
+        let fingerprint = host_key.fingerprint();
+        if !certified
+            && !config.known_hosts.contains(&fingerprint)
+            && !(config.accept_unknown_host)(&fingerprint)
+        {
+            transport.send("KO")?;
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "unknown host refused",
+            ));
+        }
+
+        Ok(Session {
+            transport,
+            host_key,
+            connection: None,
+            agent: Arc::new(Mutex::new(None)),
+            conversation: None,
+            env: Vec::new(),
+        })
+    }
+
+    /// The key the server proved it owns
+    pub fn host_key(&self) -> &PublicKey {
+        &self.host_key
+    }
+
+    /// Answer the questions the server asks during the authentication with
+    /// `conversation`: those of [`Session::authenticate_keyboard_interactive`]
+    /// and of a second factor. Without conversation they are not answered,
+    /// and the server refuses the client.
+    pub fn set_conversation(&mut self, conversation: impl Conversation + 'static) {
+        self.conversation = Some(Box::new(conversation));
+    }
+
+    /// Wait for the server to accept or refuse the client, answering its
+    /// questions meanwhile
+    fn authentication_result(&mut self) -> io::Result<bool> {
+        loop {
+            let response = self.transport.receive()?;
+            if response == TOO_MANY_FAILURES {
+                return Err(io::Error::new(io::ErrorKind::PermissionDenied, response));
+            }
+            let mut lines = response.split('\n');
+            if lines.next() != Some(INFO_REQUEST) {
+                if response != "connected" {
+                    return Ok(false);
+                }
+                let connection = Connection::new(self.transport.try_clone()?)?;
+                let (server, agent) = (connection.clone(), Arc::clone(&self.agent));
+                thread::spawn(move || serve_server_channels(server, agent));
+                self.connection = Some(connection);
+                return Ok(true);
+            }
+
+            let prompts = lines
+                .map(|line| {
+                    let (echo, text) = line.split_once(' ').unwrap_or((line, ""));
+                    let text = from_hex(text).and_then(|text| String::from_utf8(text).ok());
+                    Some(Prompt {
+                        text: text?,
+                        echo: echo == "1",
+                    })
+                })
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| invalid("bad questions from the server"))?;
+            let answers = match &mut self.conversation {
+                Some(conversation) => conversation.ask(&prompts)?,
+                None => Vec::new(),
+            };
+
+            let mut message = String::from(INFO_RESPONSE);
+            for answer in answers {
+                message += &format!("\n{}", to_hex(answer.as_bytes()));
+            }
+            self.transport.send(&message)?;
+        }
+    }
+
+    /// Log in with a login and a password.
+    ///
+    /// Returns false if the server refused them, the client can then try
+    /// again. After too many failures the server closes the connection and
+    /// an error is returned.
+    pub fn authenticate_password(&mut self, login: &str, password: &str) -> io::Result<bool> {
+        let message = format!("{}\n{}", login, password);
+        self.transport.send(&message)?;
+        self.authentication_result()
+    }
+
+    /// Log in by answering the questions of the server, usually a password,
+    /// with the conversation of [`Session::set_conversation`]. Returns false
+    /// if the server refused the answers, like
+    /// [`Session::authenticate_password`].
+    pub fn authenticate_keyboard_interactive(&mut self, login: &str) -> io::Result<bool> {
+        self.transport
+            .send(&format!("{KEYBOARD_INTERACTIVE}\n{login}"))?;
+        self.authentication_result()
+    }
+
+    /// Log in with a private key whose public key is authorized on the
+    /// server for `login`.
+    ///
+    /// The key signs the identifier of the session, it never leaves the
+    /// client. Returns false if the server refused the key, like
+    /// [`Session::authenticate_password`].
+    pub fn authenticate_key(&mut self, login: &str, key: &PrivateKey) -> io::Result<bool> {
+        self.authenticate_blob(login, &key.public_key().to_blob(), |data| key.sign(data))
+    }
+
+    /// Log in with a certificate of `key` signed by a CA the server trusts,
+    /// like [`Session::authenticate_key`].
+    pub fn authenticate_certificate(
+        &mut self,
+        login: &str,
+        certificate: &Certificate,
+        key: &PrivateKey,
+    ) -> io::Result<bool> {
+        self.authenticate_blob(login, &certificate.to_blob(), |data| key.sign(data))
+    }
+
+    /// Log in with a key or certificate held by an agent, like
+    /// [`Session::authenticate_key`]. The agent signs, the private key
+    /// stays in it.
+    pub fn authenticate_agent(
+        &mut self,
+        login: &str,
+        agent: &mut AgentClient,
+        identity: &Identity,
+    ) -> io::Result<bool> {
+        self.authenticate_blob(login, &identity.blob, |data| {
+            agent.sign(&identity.blob, data)
+        })
+    }
+
+    /// Send the key or certificate `blob` with a signature made by `sign`
+    fn authenticate_blob(
+        &mut self,
+        login: &str,
+        blob: &[u8],
+        sign: impl FnOnce(&[u8]) -> io::Result<Vec<u8>>,
+    ) -> io::Result<bool> {
+        let data = publickey_auth_data(self.transport.session_id(), login, blob);
+        let signature = sign(&data)?;
+
+        let message = format!(
+            "publickey\n{}\n{}\n{}",
+            login,
+            to_hex(blob),
+            to_hex(&signature)
+        );
+        self.transport.send(&message)?;
+        self.authentication_result()
+    }
+
+    fn connection(&self) -> io::Result<&Connection> {
+        self.connection.as_ref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::PermissionDenied, "not authenticated")
+        })
+    }
+
+    /// Open a session channel and send it the request `kind`
+    fn start(
+        &self,
+        kind: &str,
+        argument: Option<&str>,
+        terminal: Option<(&str, WindowSize)>,
+    ) -> io::Result<Channel> {
+        let mut channel = self.connection()?.open(SESSION, &[])?;
+        for (name, value) in &self.env {
+            let mut data = Writer::new();
+            data.string(name).string(value);
+            channel.request(ENV, false, &data.into_bytes())?;
+        }
+        if let Some((term, size)) = terminal {
+            let mut data = Writer::new();
+            data.string(term);
+            size.write(&mut data);
+            if !channel.request("pty-req", true, &data.into_bytes())? {
+                let _ = channel.close();
+                return Err(io::Error::other("the server refused to allocate a terminal"));
+            }
+        }
+        let mut data = Writer::new();
+        if let Some(argument) = argument {
+            data.string(argument);
+        }
+        if !channel.request(kind, true, &data.into_bytes())? {
+            let _ = channel.close();
+            return Err(io::Error::other(format!("the server refused the {kind} request")));
+        }
+        Ok(channel)
+    }
+
+    /// Start a command on the server without waiting for it: its output is
+    /// the data of the channel, and its errors the extended data. What is
+    /// written to the channel is its input, [`Channel::eof`] closes it.
+    /// Several commands can run at once, each on its own channel.
+    pub fn spawn(&self, command: &str) -> io::Result<Channel> {
+        self.start("exec", Some(command), None)
+    }
+
+    /// Run a command on the server and wait for its output.
+    pub fn exec(&mut self, command: &str) -> io::Result<Output> {
+        read_output(self.spawn(command)?)
+    }
+
+    /// Start the subsystem `name` on the server and wait for its output.
+    pub fn subsystem(&mut self, name: &str) -> io::Result<Output> {
+        read_output(self.start("subsystem", Some(name), None)?)
+    }
+
+    /// Start the login shell of the user on a terminal of type `term` and
+    /// of `size` allocated by the server. What is written to the channel is
+    /// typed on the terminal, and what the terminal shows comes back as
+    /// data. See [`resize`] when the local terminal changes size.
+    pub fn shell(&self, term: &str, size: WindowSize) -> io::Result<Channel> {
+        self.start("shell", None, Some((term, size)))
+    }
+
+    /// Ask the server to set the environment variable `name` to `value`
+    /// for the commands and shells started after. The server ignores the
+    /// variables its configuration does not accept.
+    pub fn set_env(&mut self, name: &str, value: &str) {
+        self.env.push((name.to_string(), value.to_string()));
+    }
+
+    /// Let the commands run on the server use `agent`, through a socket
+    /// whose path is in their `NSSH_AUTH_SOCK`. Each client of the socket
+    /// gets a channel to the agent.
+    pub fn forward_agent(&mut self, agent: AgentClient) -> io::Result<()> {
+        *self.agent.lock().unwrap() = Some(agent);
+        if !self.connection()?.global_request(AGENT_FORWARDING_REQUEST, &[])? {
+            *self.agent.lock().unwrap() = None;
+            return Err(io::Error::other("the server refused to forward the agent"));
+        }
+        Ok(())
+    }
+
+    /// Tell the server that the session is over and close the connection.
+    pub fn close(self) -> io::Result<()> {
+        match &self.connection {
+            Some(connection) => connection.disconnect(),
+            None => self.transport.shutdown(),
+        }
+    }
+}
+
+/// Tell the server that the terminal of `channel` is now of `size`
+pub fn resize(channel: &mut Channel, size: WindowSize) -> io::Result<()> {
+    let mut data = Writer::new();
+    size.write(&mut data);
+    channel.request("window-change", false, &data.into_bytes())?;
+    Ok(())
+}
+
+/// Send the signal `name` (`INT`, `TERM`, `KILL`, `HUP`...) to the command
+/// of `channel` and the processes it started
+pub fn send_signal(channel: &mut Channel, name: &str) -> io::Result<()> {
+    let mut data = Writer::new();
+    data.string(name);
+    channel.request(SIGNAL, false, &data.into_bytes())?;
+    Ok(())
+}
+
+/// Read a channel until the server closes it
+fn read_output(mut channel: Channel) -> io::Result<Output> {
+    let mut output = Output::default();
+    while let Some(message) = channel.recv() {
+        match message {
+            ChannelMessage::Data(data) => output.stdout.extend_from_slice(&data),
+            ChannelMessage::ExtendedData(EXTENDED_DATA_STDERR, data) => {
+                output.stderr.extend_from_slice(&data)
+            }
+            ChannelMessage::Request { kind, data, .. } => {
+                output.exit_status = output.exit_status.or(ExitStatus::from_request(&kind, &data))
+            }
+            _ => {}
+        }
+    }
+    Ok(output)
+}
+
+/// Relay the local terminal, in raw mode, to a shell on the server until it
+/// exits
+fn run_shell(session: &Session) -> io::Result<Option<ExitStatus>> {
+    let fd = io::stdin().as_raw_fd();
+    let term = env::var("TERM").unwrap_or_default();
+    let channel = session.shell(&term, WindowSize::of(fd).unwrap_or_default())?;
+    let _raw_mode = terminal::RawMode::enable(fd);
+
+    let mut resized = channel.clone();
+    terminal::on_resize(move || match WindowSize::of(fd) {
+        Some(size) => resize(&mut resized, size).is_ok(),
+        None => true,
+    })?;
+
+    send_input(&channel);
+    print_output(channel)
+}
+
+/// Send stdin to the command of `channel` from another thread, then the end
+/// of file
+fn send_input(channel: &Channel) {
+    let mut input = channel.clone();
+    thread::spawn(move || {
+        let _ = io::copy(&mut io::stdin(), &mut input);
+        let _ = input.eof();
+    });
+}
+
+/// Print the output and errors of the command of `channel` as they come,
+/// and return how it ended
+fn print_output(mut channel: Channel) -> io::Result<Option<ExitStatus>> {
+    let (mut stdout, mut stderr) = (io::stdout(), io::stderr());
+    let mut status = None;
+    while let Some(message) = channel.recv() {
+        match message {
+            ChannelMessage::Data(data) => {
+                stdout.write_all(&data)?;
+                stdout.flush()?;
+            }
+            ChannelMessage::ExtendedData(EXTENDED_DATA_STDERR, data) => {
+                stderr.write_all(&data)?
+            }
+            ChannelMessage::Request { kind, data, .. } => {
+                status = status.or(ExitStatus::from_request(&kind, &data))
+            }
+            _ => {}
+        }
+    }
+    Ok(status)
+}
+
+/// Ask on the terminal whether an unknown server should be trusted, it is
+/// not if stdin can not answer
+fn ask_trust(fingerprint: &str) -> bool {
+    eprintln!("Warning: unknown host, its key fingerprint is {fingerprint}");
+    eprintln!("Trust it ? Y/N");
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok() && answer.trim() == "Y"
+}
+
+/// Asks the questions of the server on the terminal
+struct TerminalConversation;
+
+impl Conversation for TerminalConversation {
+    fn ask(&mut self, prompts: &[Prompt]) -> io::Result<Vec<String>> {
+        prompts
+            .iter()
+            .map(|prompt| match prompt.echo {
+                true => Ok(read_line(&prompt.text).trim_end().to_string()),
+                false => terminal::read_password(&prompt.text),
+            })
+            .collect()
+    }
+}
+
+/// Print `prompt` on stderr and read one line from the terminal
+fn read_line(prompt: &str) -> String {
+    eprint!("{prompt}");
+    io::stderr().flush().expect("failed to flush stderr");
+
+    let mut line = String::new();
+    io::stdin()
+        .read_line(&mut line)
+        .expect("failed to read from stdin");
+    line
+}
+
+/// Read a private key file, asking for its passphrase if it has one
+pub fn load_identity(path: &Path) -> io::Result<PrivateKey> {
+    let text = fs::read_to_string(path)?;
+    if !PrivateKey::is_encrypted(&text) {
+        return PrivateKey::parse(&text);
+    }
+    let passphrase =
+        terminal::read_password(&format!("Enter passphrase for key '{}': ", path.display()))?;
+    Ok(PrivateKey::parse_with_comment(&text, Some(&passphrase))?.0)
+}
+
+/// The certificate next to a private key file, `<file>-cert.pub`, if any
+pub fn load_certificate(identity: &Path) -> io::Result<Option<Certificate>> {
+    let mut path = identity.as_os_str().to_owned();
+    path.push("-cert.pub");
+    match fs::read_to_string(PathBuf::from(path)) {
+        Ok(text) => Ok(Some(Certificate::parse_line(&text)?.0)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// A file of `~/.nssh`
+fn user_file(name: &str) -> Option<PathBuf> {
+    let home = env::var_os("HOME")?;
+    Some(PathBuf::from(home).join(".nssh").join(name))
+}
+
+/// Passwords asked before giving up, if the server allows as many tries
+const PASSWORD_TRIES: u32 = 3;
+
+/// Log in with the identities of the agent one after the other
+fn authenticate_with_agent(
+    session: &mut Session,
+    login: &str,
+    agent: &mut AgentClient,
+    identities: &[Identity],
+) -> io::Result<bool> {
+    for identity in identities {
+        if session.authenticate_agent(login, agent, identity)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Connect to `addr` (`host:port`) as `login`, asked on the terminal if not
+/// given, and run `command`, or else an interactive session on the
+/// terminal. Returns the exit code of the command or of the shell, 255 when
+/// it is unknown or the login is refused.
+///
+/// The user logs in with the private key file `identity` if given, else
+/// with the keys of the agent of `NSSH_AUTH_SOCK` if it has any, else by
+/// answering the questions of the server, usually a password. The questions
+/// of a second factor are asked on the terminal too. With `forward_agent`,
+/// the agent is also forwarded to the server. The local variables matching
+/// the patterns of `send_env` are sent to the server.
+///
+/// The server is trusted according to `~/.nssh/known_hosts`, the user is
+/// asked about unknown servers and those accepted are added to the file.
+/// Servers whose key is in `~/.nssh/revoked_keys` are refused.
+pub fn connect_and_communicate(
+    addr: &str,
+    login: Option<&str>,
+    identity: Option<&Path>,
+    forward_agent: bool,
+    send_env: &[String],
+    command: Option<&str>,
+) -> io::Result<i32> {
+    // read the key first, there is no need to connect if it is unusable
+    let key = identity.map(load_identity).transpose()?;
+    let certificate = identity.map(load_certificate).transpose()?.flatten();
+    // without identity file, the keys of the agent are used if there is one
+    let mut agent = match identity {
+        Some(_) => None,
+        None => AgentClient::from_env().and_then(|agent| {
+            agent
+                .map_err(|e| eprintln!("Failed to connect to the agent: {e}"))
+                .ok()
+        }),
+    };
+    let agent_identities = match &mut agent {
+        Some(agent) => agent.identities()?,
+        None => Vec::new(),
+    };
+
+    let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let mut known_hosts = user_file("known_hosts").map(KnownHosts::load).transpose()?;
+    let revoked_keys = user_file("revoked_keys")
+        .map(RevokedKeys::load)
+        .transpose()?
+        .unwrap_or_default();
+    let (known_keys, cert_authorities) = match &known_hosts {
+        Some(known_hosts) => (
+            known_hosts.host_keys(host),
+            known_hosts.cert_authorities(host),
+        ),
+        None => (Vec::new(), Vec::new()),
+    };
+
+    let accepted = Rc::new(Cell::new(false));
+    let answer = Rc::clone(&accepted);
+    let config = Config {
+        known_hosts: known_keys.iter().map(PublicKey::fingerprint).collect(),
+        host_cert_authorities: cert_authorities,
+        host_name: host.to_string(),
+        revoked_keys,
+        accept_unknown_host: Box::new(move |fingerprint| {
+            answer.set(ask_trust(fingerprint));
+            answer.get()
+        }),
+    };
+    let mut session = Session::connect(addr, config)?;
+    session.set_conversation(TerminalConversation);
+    if let (true, Some(known_hosts)) = (accepted.get(), &mut known_hosts) {
+        if let Err(e) = known_hosts.add(host, session.host_key()) {
+            eprintln!("Failed to add the host to the known hosts: {e}");
+        }
+    }
+
+    // =======================================
+    // Authentification
+    // =======================================
+
+    let login = login.map_or_else(|| read_line("login: "), str::to_string);
+    match (&key, &mut agent) {
+        (Some(key), _) => {
+            let accepted = match &certificate {
+                Some(certificate) => {
+                    session.authenticate_certificate(login.trim(), certificate, key)?
+                }
+                None => session.authenticate_key(login.trim(), key)?,
+            };
+            if !accepted {
+                eprintln!("Key refused");
+                return Ok(UNKNOWN_EXIT_CODE);
+            }
+        }
+        (None, Some(agent)) if !agent_identities.is_empty() => {
+            let accepted =
+                authenticate_with_agent(&mut session, login.trim(), agent, &agent_identities)?;
+            if !accepted {
+                eprintln!("Keys of the agent refused");
+                return Ok(UNKNOWN_EXIT_CODE);
+            }
+        }
+        _ => {
+            let mut tries = 1;
+            while !session.authenticate_keyboard_interactive(login.trim())? {
+                if tries == PASSWORD_TRIES {
+                    eprintln!("Login or password unknown");
+                    return Ok(UNKNOWN_EXIT_CODE);
+                }
+                eprintln!("Permission denied, please try again.");
+                tries += 1;
+            }
+        }
+    }
+
+    if forward_agent {
+        match AgentClient::from_env() {
+            Some(Ok(agent)) => {
+                if let Err(e) = session.forward_agent(agent) {
+                    eprintln!("{e}");
+                }
+            }
+            Some(Err(e)) => eprintln!("Failed to connect to the agent: {e}"),
+            None => eprintln!("No agent to forward, {} is not set", agent::AUTH_SOCK_ENV),
+        }
+    }
+
+    for (name, value) in env::vars() {
+        if env_matches(send_env, &name) {
+            session.set_env(&name, &value);
+        }
+    }
+
+    // ========================================
+    // Main communication loop
+    // ========================================
+
+    let status = match command {
+        Some(command) => {
+            let channel = session.spawn(command)?;
+            send_input(&channel);
+            // Ctrl-C interrupts the command rather than the client
+            let mut interrupt = channel.clone();
+            terminal::on_signal(libc::SIGINT, move || {
+                send_signal(&mut interrupt, "INT").is_ok()
+            })?;
+            Some(print_output(channel)?)
+        }
+        None if io::stdin().is_terminal() => Some(run_shell(&session)?),
+        None => None,
+    };
+    if let Some(status) = status {
+        session.close()?;
+        return Ok(status.map_or(UNKNOWN_EXIT_CODE, |status| status.code()));
+    }
+    loop {
+        let command = read_line("command: ");
+        if command.is_empty() {
+            // end of the input
+            break;
+        }
+        if command.trim() == "exit" {
+            break;
+        }
+
+        // print the server answer as it comes
+        print_output(session.spawn(&command)?)?;
+    }
+
+    session.close()?;
+    Ok(0)
+}
@@ -0,0 +1,1243 @@
+//! Channels multiplexed over one connection, like those of SSH (RFC 4254).
+//!
+//! Once the client is authenticated, both sides can open channels on the
+//! connection: sessions running a command or a shell, the forwarded agent...
+//! A channel carries data both ways, with an extended data stream for the
+//! errors, its own requests and an end of file. Each side gives the other a
+//! window of bytes it can send, grown as the data is consumed, so that a
+//! slow channel never holds the others back.
+//!
+//! A thread reads the connection and sorts the messages to their channels,
+//! the handles of the channels can be used from any thread.
+
+use crate::transport::Transport;
+use crate::wire::{Reader, Writer};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+
+/// Type of the channels running a command, a shell or a subsystem
+pub const SESSION: &str = "session";
+
+/// Code of the extended data carrying the error output of a command
+pub const EXTENDED_DATA_STDERR: u32 = 1;
+
+/// Bytes the peer can send on a channel before it has to wait for the
+/// window to grow
+#[cfg(not(test))]
+const WINDOW_SIZE: u32 = 2 << 20;
+/// Smaller in the tests, which go beyond it without cyphering megabytes
+#[cfg(test)]
+const WINDOW_SIZE: u32 = 64 << 10;
+
+/// Longest data message sent to us
+const MAX_PACKET: u32 = 32 << 10;
+
+// numbers of the messages, the same as SSH
+const DISCONNECT: u8 = 1;
+const GLOBAL_REQUEST: u8 = 80;
+const REQUEST_SUCCESS: u8 = 81;
+const REQUEST_FAILURE: u8 = 82;
+const CHANNEL_OPEN: u8 = 90;
+const CHANNEL_OPEN_CONFIRMATION: u8 = 91;
+const CHANNEL_OPEN_FAILURE: u8 = 92;
+const CHANNEL_WINDOW_ADJUST: u8 = 93;
+const CHANNEL_DATA: u8 = 94;
+const CHANNEL_EXTENDED_DATA: u8 = 95;
+const CHANNEL_EOF: u8 = 96;
+const CHANNEL_CLOSE: u8 = 97;
+const CHANNEL_REQUEST: u8 = 98;
+const CHANNEL_SUCCESS: u8 = 99;
+const CHANNEL_FAILURE: u8 = 100;
+
+/// A message of the connection, `recipient` is the number the receiver
+/// gave to the channel and `sender` the one of the sender
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Message {
+    Disconnect,
+    GlobalRequest {
+        kind: String,
+        want_reply: bool,
+        data: Vec<u8>,
+    },
+    RequestSuccess,
+    RequestFailure,
+    Open {
+        kind: String,
+        sender: u32,
+        window: u32,
+        max_packet: u32,
+        data: Vec<u8>,
+    },
+    OpenConfirmation {
+        recipient: u32,
+        sender: u32,
+        window: u32,
+        max_packet: u32,
+    },
+    OpenFailure {
+        recipient: u32,
+        reason: String,
+    },
+    WindowAdjust {
+        recipient: u32,
+        bytes: u32,
+    },
+    Data {
+        recipient: u32,
+        data: Vec<u8>,
+    },
+    ExtendedData {
+        recipient: u32,
+        code: u32,
+        data: Vec<u8>,
+    },
+    Eof {
+        recipient: u32,
+    },
+    Close {
+        recipient: u32,
+    },
+    Request {
+        recipient: u32,
+        kind: String,
+        want_reply: bool,
+        data: Vec<u8>,
+    },
+    Success {
+        recipient: u32,
+    },
+    Failure {
+        recipient: u32,
+    },
+}
+
+impl Message {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        match self {
+            Message::Disconnect => writer.u8(DISCONNECT),
+            Message::GlobalRequest {
+                kind,
+                want_reply,
+                data,
+            } => writer
+                .u8(GLOBAL_REQUEST)
+                .string(kind)
+                .bool(*want_reply)
+                .raw(data),
+            Message::RequestSuccess => writer.u8(REQUEST_SUCCESS),
+            Message::RequestFailure => writer.u8(REQUEST_FAILURE),
+            Message::Open {
+                kind,
+                sender,
+                window,
+                max_packet,
+                data,
+            } => writer
+                .u8(CHANNEL_OPEN)
+                .string(kind)
+                .u32(*sender)
+                .u32(*window)
+                .u32(*max_packet)
+                .raw(data),
+            Message::OpenConfirmation {
+                recipient,
+                sender,
+                window,
+                max_packet,
+            } => writer
+                .u8(CHANNEL_OPEN_CONFIRMATION)
+                .u32(*recipient)
+                .u32(*sender)
+                .u32(*window)
+                .u32(*max_packet),
+            Message::OpenFailure { recipient, reason } => writer
+                .u8(CHANNEL_OPEN_FAILURE)
+                .u32(*recipient)
+                .string(reason),
+            Message::WindowAdjust { recipient, bytes } => {
+                writer.u8(CHANNEL_WINDOW_ADJUST).u32(*recipient).u32(*bytes)
+            }
+            Message::Data { recipient, data } => {
+                writer.u8(CHANNEL_DATA).u32(*recipient).string(data)
+            }
+            Message::ExtendedData {
+                recipient,
+                code,
+                data,
+            } => writer
+                .u8(CHANNEL_EXTENDED_DATA)
+                .u32(*recipient)
+                .u32(*code)
+                .string(data),
+            Message::Eof { recipient } => writer.u8(CHANNEL_EOF).u32(*recipient),
+            Message::Close { recipient } => writer.u8(CHANNEL_CLOSE).u32(*recipient),
+            Message::Request {
+                recipient,
+                kind,
+                want_reply,
+                data,
+            } => writer
+                .u8(CHANNEL_REQUEST)
+                .u32(*recipient)
+                .string(kind)
+                .bool(*want_reply)
+                .raw(data),
+            Message::Success { recipient } => writer.u8(CHANNEL_SUCCESS).u32(*recipient),
+            Message::Failure { recipient } => writer.u8(CHANNEL_FAILURE).u32(*recipient),
+        };
+        writer.into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> io::Result<Message> {
+        let mut reader = Reader::new(bytes);
+        let message = match reader.u8()? {
+            DISCONNECT => Message::Disconnect,
+            GLOBAL_REQUEST => Message::GlobalRequest {
+                kind: reader.str()?.to_string(),
+                want_reply: reader.bool()?,
+                data: reader.rest().to_vec(),
+            },
+            REQUEST_SUCCESS => Message::RequestSuccess,
+            REQUEST_FAILURE => Message::RequestFailure,
+            CHANNEL_OPEN => Message::Open {
+                kind: reader.str()?.to_string(),
+                sender: reader.u32()?,
+                window: reader.u32()?,
+                max_packet: reader.u32()?,
+                data: reader.rest().to_vec(),
+            },
+            CHANNEL_OPEN_CONFIRMATION => Message::OpenConfirmation {
+                recipient: reader.u32()?,
+                sender: reader.u32()?,
+                window: reader.u32()?,
+                max_packet: reader.u32()?,
+            },
+            CHANNEL_OPEN_FAILURE => Message::OpenFailure {
+                recipient: reader.u32()?,
+                reason: reader.str()?.to_string(),
+            },
+            CHANNEL_WINDOW_ADJUST => Message::WindowAdjust {
+                recipient: reader.u32()?,
+                bytes: reader.u32()?,
+            },
+            CHANNEL_DATA => Message::Data {
+                recipient: reader.u32()?,
+                data: reader.string()?.to_vec(),
+            },
+            CHANNEL_EXTENDED_DATA => Message::ExtendedData {
+                recipient: reader.u32()?,
+                code: reader.u32()?,
+                data: reader.string()?.to_vec(),
+            },
+            CHANNEL_EOF => Message::Eof {
+                recipient: reader.u32()?,
+            },
+            CHANNEL_CLOSE => Message::Close {
+                recipient: reader.u32()?,
+            },
+            CHANNEL_REQUEST => Message::Request {
+                recipient: reader.u32()?,
+                kind: reader.str()?.to_string(),
+                want_reply: reader.bool()?,
+                data: reader.rest().to_vec(),
+            },
+            CHANNEL_SUCCESS => Message::Success {
+                recipient: reader.u32()?,
+            },
+            CHANNEL_FAILURE => Message::Failure {
+                recipient: reader.u32()?,
+            },
+            number => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown message {number}"),
+                ))
+            }
+        };
+        reader.finish()?;
+        Ok(message)
+    }
+}
+
+/// What the peer sent on a channel, in order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelMessage {
+    Data(Vec<u8>),
+    /// Data of another stream, [`EXTENDED_DATA_STDERR`] for the errors
+    ExtendedData(u32, Vec<u8>),
+    /// The peer will not send more data
+    Eof,
+    /// A request specific to the type of the channel, answered with
+    /// [`Channel::reply`] if `want_reply` is set
+    Request {
+        kind: String,
+        want_reply: bool,
+        data: Vec<u8>,
+    },
+}
+
+/// State of a channel, shared by its handles and the thread reading the
+/// connection
+#[derive(Debug, Default)]
+struct State {
+    /// Number of the channel on the peer, the recipient of our messages
+    remote_id: u32,
+    /// Bytes we can still send
+    remote_window: u32,
+    /// Longest data message the peer takes
+    remote_max_packet: u32,
+    /// Answer of the peer to our open, `None` until it comes
+    opened: Option<Result<(), String>>,
+    received: VecDeque<ChannelMessage>,
+    /// Bytes the peer can still send
+    local_window: u32,
+    /// Bytes consumed since the window last grew
+    consumed: u32,
+    /// Answers to our requests, in order
+    replies: VecDeque<bool>,
+    eof_received: bool,
+    eof_sent: bool,
+    close_received: bool,
+    close_sent: bool,
+}
+
+impl State {
+    /// Count `length` bytes as consumed, returns how much the window of the
+    /// peer must grow once half of it is consumed
+    fn consume(&mut self, length: usize) -> Option<u32> {
+        self.consumed += length as u32;
+        if self.consumed < WINDOW_SIZE / 2 {
+            return None;
+        }
+        let bytes = std::mem::take(&mut self.consumed);
+        self.local_window += bytes;
+        Some(bytes)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Slot {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+/// What the handles of a connection share with the thread reading it
+struct Shared {
+    /// Handle on the transport used to send, the reading thread owns the
+    /// other one
+    writer: Mutex<Transport>,
+    channels: Mutex<HashMap<u32, Arc<Slot>>>,
+    next_id: AtomicU32,
+    /// Waiting for the answers to our global requests, in order
+    replies: Mutex<VecDeque<Sender<bool>>>,
+    incoming: Mutex<Receiver<Incoming>>,
+    closed: AtomicBool,
+}
+
+impl Shared {
+    fn send(&self, message: &Message) -> io::Result<()> {
+        self.writer.lock().unwrap().send_bytes(&message.to_bytes())
+    }
+
+    /// Register a new channel, fails once the connection is closed
+    fn add_channel(&self, state: State) -> io::Result<(u32, Arc<Slot>)> {
+        let mut channels = self.channels.lock().unwrap();
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "the connection is closed",
+            ));
+        }
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let slot = Arc::new(Slot {
+            state: Mutex::new(state),
+            changed: Condvar::new(),
+        });
+        channels.insert(id, Arc::clone(&slot));
+        Ok((id, slot))
+    }
+}
+
+/// Something the peer started: a channel to accept or a global request
+pub enum Incoming {
+    Open(ChannelOpen),
+    Request(GlobalRequest),
+}
+
+/// A channel the peer wants to open, to accept or reject
+pub struct ChannelOpen {
+    kind: String,
+    data: Vec<u8>,
+    sender: u32,
+    window: u32,
+    max_packet: u32,
+    shared: Arc<Shared>,
+}
+
+impl ChannelOpen {
+    /// Type of the channel, like [`SESSION`]
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    /// Data specific to the type of the channel
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn accept(self) -> io::Result<Channel> {
+        let state = State {
+            remote_id: self.sender,
+            remote_window: self.window,
+            remote_max_packet: self.max_packet.max(1),
+            opened: Some(Ok(())),
+            local_window: WINDOW_SIZE,
+            ..State::default()
+        };
+        let (id, slot) = self.shared.add_channel(state)?;
+        self.shared.send(&Message::OpenConfirmation {
+            recipient: self.sender,
+            sender: id,
+            window: WINDOW_SIZE,
+            max_packet: MAX_PACKET,
+        })?;
+        Ok(Channel {
+            id,
+            slot,
+            shared: self.shared,
+        })
+    }
+
+    pub fn reject(self, reason: &str) -> io::Result<()> {
+        self.shared.send(&Message::OpenFailure {
+            recipient: self.sender,
+            reason: reason.to_string(),
+        })
+    }
+}
+
+/// A request of the peer about the whole connection
+pub struct GlobalRequest {
+    kind: String,
+    want_reply: bool,
+    data: Vec<u8>,
+    shared: Arc<Shared>,
+}
+
+impl GlobalRequest {
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Tell the peer whether the request succeeded, if it wants to know
+    pub fn reply(self, success: bool) -> io::Result<()> {
+        if !self.want_reply {
+            return Ok(());
+        }
+        self.shared.send(&match success {
+            true => Message::RequestSuccess,
+            false => Message::RequestFailure,
+        })
+    }
+}
+
+/// An authenticated connection carrying channels, its handles can be cloned
+/// and sent to other threads.
+#[derive(Clone)]
+pub struct Connection {
+    shared: Arc<Shared>,
+}
+
+impl Connection {
+    /// Start reading the messages of `transport` in a new thread
+    pub fn new(transport: Transport) -> io::Result<Self> {
+        let (incoming, receiver) = mpsc::channel();
+        let shared = Arc::new(Shared {
+            writer: Mutex::new(transport.try_clone()?),
+            channels: Mutex::new(HashMap::new()),
+            next_id: AtomicU32::new(0),
+            replies: Mutex::new(VecDeque::new()),
+            incoming: Mutex::new(receiver),
+            closed: AtomicBool::new(false),
+        });
+        let reader = Arc::clone(&shared);
+        thread::spawn(move || read_messages(transport, reader, incoming));
+        Ok(Connection { shared })
+    }
+
+    /// Open a channel of type `kind` and wait for the peer to accept it
+    pub fn open(&self, kind: &str, data: &[u8]) -> io::Result<Channel> {
+        let state = State {
+            local_window: WINDOW_SIZE,
+            ..State::default()
+        };
+        let (id, slot) = self.shared.add_channel(state)?;
+        self.shared.send(&Message::Open {
+            kind: kind.to_string(),
+            sender: id,
+            window: WINDOW_SIZE,
+            max_packet: MAX_PACKET,
+            data: data.to_vec(),
+        })?;
+
+        let channel = Channel {
+            id,
+            slot,
+            shared: Arc::clone(&self.shared),
+        };
+        let mut state = channel.lock();
+        loop {
+            match state.opened.clone() {
+                Some(Ok(())) => break,
+                Some(Err(reason)) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::ConnectionRefused,
+                        format!("channel {kind} refused: {reason}"),
+                    ))
+                }
+                None if state.close_received => return Err(closed()),
+                None => state = channel.slot.changed.wait(state).unwrap(),
+            }
+        }
+        drop(state);
+        Ok(channel)
+    }
+
+    /// Send a global request, returns whether the peer accepted it
+    pub fn global_request(&self, kind: &str, data: &[u8]) -> io::Result<bool> {
+        let (reply, answer) = mpsc::channel();
+        // registered before the request is sent so that the answer finds it
+        self.shared.replies.lock().unwrap().push_back(reply);
+        self.shared.send(&Message::GlobalRequest {
+            kind: kind.to_string(),
+            want_reply: true,
+            data: data.to_vec(),
+        })?;
+        answer.recv().map_err(|_| closed())
+    }
+
+    /// Wait for the next channel or global request of the peer, `None` once
+    /// the connection is closed
+    pub fn next_incoming(&self) -> Option<Incoming> {
+        self.shared.incoming.lock().unwrap().recv().ok()
+    }
+
+    /// Tell the peer the connection is over and close it, the channels
+    /// still open are closed too
+    pub fn disconnect(&self) -> io::Result<()> {
+        let result = self.shared.send(&Message::Disconnect);
+        self.shared.writer.lock().unwrap().shutdown()?;
+        result
+    }
+}
+
+fn closed() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "the channel is closed")
+}
+
+/// Read the messages of the connection until it ends, then close every
+/// channel
+fn read_messages(mut transport: Transport, shared: Arc<Shared>, incoming: Sender<Incoming>) {
+    while let Ok(message) = transport
+        .receive_bytes()
+        .and_then(|bytes| Message::parse(&bytes))
+    {
+        match dispatch(&shared, message, &incoming) {
+            Ok(true) => {}
+            Ok(false) | Err(_) => break,
+        }
+    }
+
+    let _ = transport.shutdown();
+    let slots: Vec<_> = {
+        let mut channels = shared.channels.lock().unwrap();
+        shared.closed.store(true, Ordering::SeqCst);
+        channels.drain().map(|(_, slot)| slot).collect()
+    };
+    for slot in slots {
+        let mut state = slot.state.lock().unwrap();
+        state.close_received = true;
+        state.close_sent = true;
+        slot.changed.notify_all();
+    }
+    shared.replies.lock().unwrap().clear();
+}
+
+/// Pass a message to the channel or the handle it is for, returns false
+/// when the peer disconnects
+fn dispatch(
+    shared: &Arc<Shared>,
+    message: Message,
+    incoming: &Sender<Incoming>,
+) -> io::Result<bool> {
+    let recipient = match &message {
+        Message::Disconnect => return Ok(false),
+        Message::GlobalRequest { .. } | Message::Open { .. } => {
+            return accept_incoming(shared, message, incoming).map(|()| true)
+        }
+        Message::RequestSuccess | Message::RequestFailure => {
+            if let Some(reply) = shared.replies.lock().unwrap().pop_front() {
+                let _ = reply.send(message == Message::RequestSuccess);
+            }
+            return Ok(true);
+        }
+        Message::OpenConfirmation { recipient, .. }
+        | Message::OpenFailure { recipient, .. }
+        | Message::WindowAdjust { recipient, .. }
+        | Message::Data { recipient, .. }
+        | Message::ExtendedData { recipient, .. }
+        | Message::Eof { recipient }
+        | Message::Close { recipient }
+        | Message::Request { recipient, .. }
+        | Message::Success { recipient }
+        | Message::Failure { recipient } => *recipient,
+    };
+    // the channel may be already gone on our side
+    let Some(slot) = shared.channels.lock().unwrap().get(&recipient).cloned() else {
+        return Ok(true);
+    };
+
+    let mut state = slot.state.lock().unwrap();
+    match message {
+        Message::OpenConfirmation {
+            sender,
+            window,
+            max_packet,
+            ..
+        } => {
+            state.remote_id = sender;
+            state.remote_window = window;
+            state.remote_max_packet = max_packet.max(1);
+            state.opened = Some(Ok(()));
+        }
+        Message::OpenFailure { reason, .. } => {
+            state.opened = Some(Err(reason));
+            shared.channels.lock().unwrap().remove(&recipient);
+        }
+        Message::WindowAdjust { bytes, .. } => {
+            state.remote_window = state.remote_window.saturating_add(bytes);
+        }
+        Message::Data { data, .. } | Message::ExtendedData { data, .. }
+            if data.len() > state.local_window as usize =>
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "data beyond the window of the channel",
+            ));
+        }
+        Message::Data { data, .. } => {
+            state.local_window -= data.len() as u32;
+            state.received.push_back(ChannelMessage::Data(data));
+        }
+        Message::ExtendedData { code, data, .. } => {
+            state.local_window -= data.len() as u32;
+            state
+                .received
+                .push_back(ChannelMessage::ExtendedData(code, data));
+        }
+        Message::Eof { .. } => {
+            state.eof_received = true;
+            state.received.push_back(ChannelMessage::Eof);
+        }
+        Message::Close { .. } => {
+            // a close is answered by a close, then the channel is gone
+            state.close_received = true;
+            if !state.close_sent {
+                state.close_sent = true;
+                shared.send(&Message::Close {
+                    recipient: state.remote_id,
+                })?;
+            }
+            shared.channels.lock().unwrap().remove(&recipient);
+        }
+        Message::Request {
+            kind,
+            want_reply,
+            data,
+            ..
+        } => state.received.push_back(ChannelMessage::Request {
+            kind,
+            want_reply,
+            data,
+        }),
+        Message::Success { .. } => state.replies.push_back(true),
+        Message::Failure { .. } => state.replies.push_back(false),
+        _ => unreachable!("the connection messages are handled above"),
+    }
+    slot.changed.notify_all();
+    Ok(true)
+}
+
+/// Give a new channel or global request to the handles waiting for them,
+/// refused if nobody waits anymore
+fn accept_incoming(
+    shared: &Arc<Shared>,
+    message: Message,
+    incoming: &Sender<Incoming>,
+) -> io::Result<()> {
+    let item = match message {
+        Message::GlobalRequest {
+            kind,
+            want_reply,
+            data,
+        } => Incoming::Request(GlobalRequest {
+            kind,
+            want_reply,
+            data,
+            shared: Arc::clone(shared),
+        }),
+        Message::Open {
+            kind,
+            sender,
+            window,
+            max_packet,
+            data,
+        } => Incoming::Open(ChannelOpen {
+            kind,
+            data,
+            sender,
+            window,
+            max_packet,
+            shared: Arc::clone(shared),
+        }),
+        _ => unreachable!("only opens and global requests come in"),
+    };
+    match incoming.send(item) {
+        Ok(()) => Ok(()),
+        Err(mpsc::SendError(Incoming::Open(open))) => open.reject("nobody accepts channels"),
+        Err(mpsc::SendError(Incoming::Request(request))) => request.reply(false),
+    }
+}
+
+/// One channel of a connection. Its handles can be cloned, for example to
+/// read in a thread and write in another.
+///
+/// [`Read`] gives the data of the peer and skips the other messages, for
+/// channels that only carry data. Close the channel once done with it.
+#[derive(Clone)]
+pub struct Channel {
+    id: u32,
+    slot: Arc<Slot>,
+    shared: Arc<Shared>,
+}
+
+impl Channel {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.slot.state.lock().unwrap()
+    }
+
+    /// Let the peer send more once enough data is consumed
+    fn grow_window(&self, remote_id: u32, bytes: Option<u32>) {
+        if let Some(bytes) = bytes {
+            // a broken connection ends the channel anyway
+            let _ = self.shared.send(&Message::WindowAdjust {
+                recipient: remote_id,
+                bytes,
+            });
+        }
+    }
+
+    /// Wait for the next message of the peer, `None` once the channel is
+    /// closed
+    pub fn recv(&mut self) -> Option<ChannelMessage> {
+        let mut state = self.lock();
+        loop {
+            if let Some(message) = state.received.pop_front() {
+                let length = match &message {
+                    ChannelMessage::Data(data) | ChannelMessage::ExtendedData(_, data) => {
+                        data.len()
+                    }
+                    _ => 0,
+                };
+                let grow = state.consume(length);
+                let remote_id = state.remote_id;
+                drop(state);
+                self.grow_window(remote_id, grow);
+                return Some(message);
+            }
+            if state.close_received {
+                return None;
+            }
+            state = self.slot.changed.wait(state).unwrap();
+        }
+    }
+
+    /// Wait until the window of the peer allows sending, returns the number
+    /// of the channel on the peer and how many bytes of `wanted` can go
+    fn reserve(&self, wanted: usize) -> io::Result<(u32, usize)> {
+        let mut state = self.lock();
+        loop {
+            if state.eof_sent || state.close_sent || state.close_received {
+                return Err(closed());
+            }
+            if state.remote_window > 0 {
+                let length = wanted
+                    .min(state.remote_window as usize)
+                    .min(state.remote_max_packet as usize);
+                state.remote_window -= length as u32;
+                return Ok((state.remote_id, length));
+            }
+            state = self.slot.changed.wait(state).unwrap();
+        }
+    }
+
+    /// Send data of the extended stream `code`, [`EXTENDED_DATA_STDERR`]
+    /// for the errors
+    pub fn write_extended(&mut self, code: u32, mut data: &[u8]) -> io::Result<()> {
+        while !data.is_empty() {
+            let (recipient, length) = self.reserve(data.len())?;
+            self.shared.send(&Message::ExtendedData {
+                recipient,
+                code,
+                data: data[..length].to_vec(),
+            })?;
+            data = &data[length..];
+        }
+        Ok(())
+    }
+
+    /// Send data on the error stream
+    pub fn write_stderr(&mut self, data: &[u8]) -> io::Result<()> {
+        self.write_extended(EXTENDED_DATA_STDERR, data)
+    }
+
+    /// Send a request specific to the type of the channel. With
+    /// `want_reply`, wait for the answer of the peer and return it.
+    pub fn request(&mut self, kind: &str, want_reply: bool, data: &[u8]) -> io::Result<bool> {
+        let recipient = self.lock().remote_id;
+        self.shared.send(&Message::Request {
+            recipient,
+            kind: kind.to_string(),
+            want_reply,
+            data: data.to_vec(),
+        })?;
+        if !want_reply {
+            return Ok(true);
+        }
+        let mut state = self.lock();
+        loop {
+            if let Some(success) = state.replies.pop_front() {
+                return Ok(success);
+            }
+            if state.close_received {
+                return Err(closed());
+            }
+            state = self.slot.changed.wait(state).unwrap();
+        }
+    }
+
+    /// Answer a request of the peer that wants a reply
+    pub fn reply(&mut self, success: bool) -> io::Result<()> {
+        let recipient = self.lock().remote_id;
+        self.shared.send(&match success {
+            true => Message::Success { recipient },
+            false => Message::Failure { recipient },
+        })
+    }
+
+    /// Tell the peer no more data will be sent
+    pub fn eof(&mut self) -> io::Result<()> {
+        let mut state = self.lock();
+        if state.eof_sent || state.close_sent {
+            return Ok(());
+        }
+        state.eof_sent = true;
+        let recipient = state.remote_id;
+        drop(state);
+        self.slot.changed.notify_all();
+        self.shared.send(&Message::Eof { recipient })
+    }
+
+    /// Close the channel, the peer closes it too
+    pub fn close(&mut self) -> io::Result<()> {
+        let mut state = self.lock();
+        if state.close_sent {
+            return Ok(());
+        }
+        state.close_sent = true;
+        if state.close_received {
+            self.shared.channels.lock().unwrap().remove(&self.id);
+        }
+        let recipient = state.remote_id;
+        drop(state);
+        self.slot.changed.notify_all();
+        self.shared.send(&Message::Close { recipient })
+    }
+
+    /// Whether the peer sent its end of file or closed the channel
+    pub fn is_eof(&self) -> bool {
+        let state = self.lock();
+        state.eof_received || state.close_received
+    }
+}
+
+impl Write for Channel {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let (recipient, length) = self.reserve(buf.len())?;
+        self.shared.send(&Message::Data {
+            recipient,
+            data: buf[..length].to_vec(),
+        })?;
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for Channel {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut state = self.lock();
+        loop {
+            match state.received.pop_front() {
+                Some(ChannelMessage::Data(mut data)) => {
+                    let length = data.len().min(buf.len());
+                    buf[..length].copy_from_slice(&data[..length]);
+                    if length < data.len() {
+                        data.drain(..length);
+                        state.received.push_front(ChannelMessage::Data(data));
+                    }
+                    let grow = state.consume(length);
+                    let remote_id = state.remote_id;
+                    drop(state);
+                    self.grow_window(remote_id, grow);
+                    return Ok(length);
+                }
+                Some(ChannelMessage::ExtendedData(_, data)) => {
+                    let grow = state.consume(data.len());
+                    self.grow_window(state.remote_id, grow);
+                }
+                Some(_) => {}
+                None if state.eof_received || state.close_received => return Ok(0),
+                None => state = self.slot.changed.wait(state).unwrap(),
+            }
+        }
+    }
+}
+
+/// Both ends of a connection over the loopback, the client's first
+#[cfg(test)]
+pub(crate) fn connected_pair() -> (Connection, Connection) {
+    use std::net::{TcpListener, TcpStream};
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        Transport::server_handshake(stream).unwrap()
+    });
+    let client = Transport::client_handshake(TcpStream::connect(addr).unwrap()).unwrap();
+    (
+        Connection::new(client).unwrap(),
+        Connection::new(server.join().unwrap()).unwrap(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Accept the channels of the peer and send back what they carry, then
+    /// their end of file, close them and count them
+    fn echo_server(connection: Connection) -> thread::JoinHandle<usize> {
+        thread::spawn(move || {
+            let mut echoes = Vec::new();
+            while let Some(incoming) = connection.next_incoming() {
+                match incoming {
+                    Incoming::Open(open) if open.kind() == SESSION => {
+                        let mut channel = open.accept().unwrap();
+                        echoes.push(thread::spawn(move || {
+                            io::copy(&mut channel.clone(), &mut channel).unwrap();
+                            channel.eof().unwrap();
+                            channel.close().unwrap();
+                        }));
+                    }
+                    Incoming::Open(open) => open.reject("unknown type").unwrap(),
+                    Incoming::Request(request) => {
+                        let known = request.kind() == "ping";
+                        request.reply(known).unwrap();
+                    }
+                }
+            }
+            let count = echoes.len();
+            for echo in echoes {
+                echo.join().unwrap();
+            }
+            count
+        })
+    }
+
+    #[test]
+    fn message_round_trip() {
+        let messages = [
+            Message::Disconnect,
+            Message::GlobalRequest {
+                kind: String::from("auth-agent-req"),
+                want_reply: true,
+                data: Vec::new(),
+            },
+            Message::RequestFailure,
+            Message::Open {
+                kind: String::from(SESSION),
+                sender: 3,
+                window: WINDOW_SIZE,
+                max_packet: MAX_PACKET,
+                data: b"extra".to_vec(),
+            },
+            Message::OpenConfirmation {
+                recipient: 3,
+                sender: 0,
+                window: 1,
+                max_packet: 2,
+            },
+            Message::OpenFailure {
+                recipient: 1,
+                reason: String::from("no"),
+            },
+            Message::WindowAdjust {
+                recipient: 1,
+                bytes: 1024,
+            },
+            Message::Data {
+                recipient: 1,
+                data: b"hello\0".to_vec(),
+            },
+            Message::ExtendedData {
+                recipient: 1,
+                code: EXTENDED_DATA_STDERR,
+                data: b"oops".to_vec(),
+            },
+            Message::Eof { recipient: 1 },
+            Message::Close { recipient: 1 },
+            Message::Request {
+                recipient: 1,
+                kind: String::from("exec"),
+                want_reply: false,
+                data: b"\0\0\0\x02ls".to_vec(),
+            },
+            Message::Success { recipient: 1 },
+        ];
+        for message in messages {
+            assert_eq!(Message::parse(&message.to_bytes()).unwrap(), message);
+        }
+
+        assert!(Message::parse(b"").is_err());
+        assert!(Message::parse(&[42]).is_err());
+        assert!(Message::parse(&[CHANNEL_EOF, 0, 0]).is_err());
+        assert!(Message::parse(&[CHANNEL_EOF, 0, 0, 0, 1, 0]).is_err());
+    }
+
+    #[test]
+    fn window() {
+        let mut state = State {
+            local_window: WINDOW_SIZE,
+            ..State::default()
+        };
+        assert_eq!(state.consume(1000), None);
+        assert_eq!(
+            state.consume(WINDOW_SIZE as usize / 2),
+            Some(WINDOW_SIZE / 2 + 1000)
+        );
+        assert_eq!(state.consumed, 0);
+    }
+
+    #[test]
+    fn channels_at_once() {
+        let (client, server) = connected_pair();
+        let server = echo_server(server);
+
+        // more than the window on each channel, so the echoes only go on
+        // once the data is read
+        let length = 2 * WINDOW_SIZE as usize + 12345;
+        let sessions: Vec<_> = (0..3u8)
+            .map(|n| {
+                let client = client.clone();
+                thread::spawn(move || {
+                    let data: Vec<u8> = (0..length).map(|i| (i % 251) as u8 ^ n).collect();
+                    let mut channel = client.open(SESSION, &[]).unwrap();
+                    let mut writer = channel.clone();
+                    let sent = data.clone();
+                    let writing = thread::spawn(move || {
+                        writer.write_all(&sent).unwrap();
+                        writer.eof().unwrap();
+                        // nothing is sent after the end of file
+                        assert!(writer.write_all(b"late").is_err());
+                    });
+
+                    let mut echo = Vec::new();
+                    channel.read_to_end(&mut echo).unwrap();
+                    writing.join().unwrap();
+                    assert!(echo == data, "channel {n} got other data back");
+                    assert!(channel.is_eof());
+                    // the peer closed the channel already
+                    assert_eq!(channel.recv(), None);
+                    channel.close().unwrap();
+                })
+            })
+            .collect();
+        for session in sessions {
+            session.join().unwrap();
+        }
+
+        assert!(client.open("x11", &[]).is_err());
+        client.disconnect().unwrap();
+        assert_eq!(server.join().unwrap(), 3);
+        assert!(client.open(SESSION, &[]).is_err());
+    }
+
+    #[test]
+    fn eof_and_close_from_each_side() {
+        let (client, server) = connected_pair();
+        let accepting = {
+            let server = server.clone();
+            thread::spawn(move || match server.next_incoming() {
+                Some(Incoming::Open(open)) => open.accept().unwrap(),
+                _ => panic!("expected a channel"),
+            })
+        };
+        let mut ours = client.open(SESSION, b"data").unwrap();
+        let mut theirs = accepting.join().unwrap();
+
+        // an end of file only ends one direction
+        ours.eof().unwrap();
+        assert_eq!(theirs.recv(), Some(ChannelMessage::Eof));
+        assert!(theirs.is_eof());
+        assert!(!ours.is_eof());
+        theirs.write_all(b"still there").unwrap();
+        theirs.write_stderr(b"and here").unwrap();
+        assert_eq!(
+            ours.recv(),
+            Some(ChannelMessage::Data(b"still there".to_vec()))
+        );
+        assert_eq!(
+            ours.recv(),
+            Some(ChannelMessage::ExtendedData(
+                EXTENDED_DATA_STDERR,
+                b"and here".to_vec()
+            ))
+        );
+
+        // a close is answered by a close
+        theirs.close().unwrap();
+        assert_eq!(ours.recv(), None);
+        assert!(ours.is_eof());
+        assert!(ours.write_all(b"gone").is_err());
+        assert_eq!(theirs.recv(), None);
+
+        // the server can open channels too, and the client closes first
+        let accepting = {
+            let client = client.clone();
+            thread::spawn(move || match client.next_incoming() {
+                Some(Incoming::Open(open)) => open.accept().unwrap(),
+                _ => panic!("expected a channel"),
+            })
+        };
+        let mut theirs = server.open("auth-agent@nssh", &[]).unwrap();
+        let mut ours = accepting.join().unwrap();
+        ours.write_all(b"hello").unwrap();
+        ours.close().unwrap();
+        let mut received = Vec::new();
+        theirs.read_to_end(&mut received).unwrap();
+        assert_eq!(received, b"hello");
+        assert_eq!(theirs.recv(), None);
+        assert_eq!(ours.recv(), None);
+
+        // the channels still open end with the connection
+        let accepting = {
+            let server = server.clone();
+            thread::spawn(move || match server.next_incoming() {
+                Some(Incoming::Open(open)) => open.accept().unwrap(),
+                _ => panic!("expected a channel"),
+            })
+        };
+        let mut ours = client.open(SESSION, &[]).unwrap();
+        let mut theirs = accepting.join().unwrap();
+        server.disconnect().unwrap();
+        assert_eq!(ours.recv(), None);
+        assert_eq!(theirs.recv(), None);
+        assert!(client.next_incoming().is_none());
+    }
+
+    #[test]
+    fn requests() {
+        let (client, server) = connected_pair();
+        let serving = {
+            let server = server.clone();
+            thread::spawn(move || {
+                // global requests are answered in order
+                for known in [true, false] {
+                    match server.next_incoming() {
+                        Some(Incoming::Request(request)) => {
+                            assert_eq!(request.data(), b"data");
+                            request.reply(known).unwrap();
+                        }
+                        _ => panic!("expected a request"),
+                    }
+                }
+                let Some(Incoming::Open(open)) = server.next_incoming() else {
+                    panic!("expected a channel");
+                };
+                let mut channel = open.accept().unwrap();
+                for success in [true, false] {
+                    match channel.recv() {
+                        Some(ChannelMessage::Request {
+                            want_reply: true, ..
+                        }) => channel.reply(success).unwrap(),
+                        other => panic!("unexpected {other:?}"),
+                    }
+                }
+                let signal = channel.recv();
+                channel.close().unwrap();
+                signal
+            })
+        };
+
+        assert!(client.global_request("ping", b"data").unwrap());
+        assert!(!client.global_request("tcpip-forward", b"data").unwrap());
+        let mut channel = client.open(SESSION, &[]).unwrap();
+        assert!(channel.request("exec", true, b"ls").unwrap());
+        assert!(!channel.request("pty-req", true, b"").unwrap());
+        assert!(channel.request("signal", false, b"INT").unwrap());
+        assert_eq!(
+            serving.join().unwrap(),
+            Some(ChannelMessage::Request {
+                kind: String::from("signal"),
+                want_reply: false,
+                data: b"INT".to_vec(),
+            })
+        );
+        assert_eq!(channel.recv(), None);
+        assert!(channel.request("exec", true, b"ls").is_err());
+
+        // and the other way
+        let answering = {
+            let client = client.clone();
+            thread::spawn(move || match client.next_incoming() {
+                Some(Incoming::Request(request)) => request.reply(true).unwrap(),
+                _ => panic!("expected a request"),
+            })
+        };
+        assert!(server.global_request("keepalive", b"").unwrap());
+        answering.join().unwrap();
+
+        server.disconnect().unwrap();
+        assert!(client.next_incoming().is_none());
+        assert!(client.global_request("ping", b"").is_err());
+    }
+}
@@ -101,23 +101,24 @@ fn shift_right(row: &mut [u8; 4]) {
 
 /// Shift rows in the state
 fn shift_rows(state: &mut [[u8; 4]; 4]) {
-    for i in 1..4 {
+    for (i, row) in state.iter_mut().enumerate().skip(1) {
         for _ in 0..i {
-            shift_left(&mut state[i]);
+            shift_left(row);
         }
     }
 }
 
 /// Inverse shift rows in the state
 fn inv_shift_rows(state: &mut [[u8; 4]; 4]) {
-    for i in 1..4 {
+    for (i, row) in state.iter_mut().enumerate().skip(1) {
         for _ in 0..i {
-            shift_right(&mut state[i]);
+            shift_right(row);
         }
     }
 }
 
 /// Mix columns in the state
+#[allow(clippy::needless_range_loop)] // the state is walked column by column
 fn mix_columns(state: &mut [[u8; 4]; 4]) {
     for i in 0..4 {
         let mut row_tmp = [0u8; 4];
@@ -132,6 +133,7 @@ fn mix_columns(state: &mut [[u8; 4]; 4]) {
 }
 
 /// Inverse mix columns in the state
+#[allow(clippy::needless_range_loop)] // the state is walked column by column
 fn inv_mix_columns(state: &mut [[u8; 4]; 4]) {
     for i in 0..4 {
         let mut row_tmp = [0u8; 4];
@@ -209,7 +211,7 @@ pub fn key_expansion(key: [u8; 16]) -> [u32; 44] {
             let x_3 = (temp) as u8;
             let x_2 = (temp >> 8) as u8;
             let mut x_1 = (temp >> 16) as u8;
-            x_1 = (power ^ S[x_1 as usize]) as u8;
+            x_1 = power ^ S[x_1 as usize];
             let x_0 = (temp >> 24) as u8;
             temp = x_1 as u32;
             temp = temp << 8 | S[x_2 as usize] as u32;
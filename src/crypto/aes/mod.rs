@@ -58,8 +58,7 @@ pub fn cypher_message(message: Vec<u8>, key: [u8; 16]) -> Vec<u8> {
     blocks = cypher_blocks(blocks, expended_key);
 
     // Cut down the blocks to 8 bites
-    let bytes = blocks_to_bytes(blocks);
-    bytes
+    blocks_to_bytes(blocks)
 }
 
 pub fn decypher_message(message: Vec<u8>, key: [u8; 16]) -> Vec<u8> {
@@ -71,8 +70,23 @@ pub fn decypher_message(message: Vec<u8>, key: [u8; 16]) -> Vec<u8> {
     blocks = decypher_blocks(blocks, expended_key);
 
     // Cut down the blocks to 8 bites
-    let bytes = blocks_to_bytes(blocks);
-    bytes
+    blocks_to_bytes(blocks)
+}
+
+/// Cypher or decypher (it is the same operation) a message in counter mode:
+/// each block is xored with the cyphered value of a counter starting at `iv`,
+/// so the message does not need padding
+pub fn ctr_message(message: &[u8], key: [u8; 16], iv: [u8; 16]) -> Vec<u8> {
+    let expended_key = key_expansion(key);
+    let mut counter = u128::from_be_bytes(iv);
+
+    let mut result = Vec::with_capacity(message.len());
+    for chunk in message.chunks(16) {
+        let key_stream = cypher(counter.to_be_bytes(), &expended_key);
+        counter = counter.wrapping_add(1);
+        result.extend(chunk.iter().zip(key_stream).map(|(byte, key)| byte ^ key));
+    }
+    result
 }
 
 #[cfg(test)]
@@ -86,8 +100,8 @@ mod tests {
 
         // Convert the hexa key to array of 16 bytes
         let mut key_bytes = [0u8; 16];
-        for i in 0..16 {
-            key_bytes[i] = ((key >> ((15 - i) * 8)) & 0xFF) as u8;
+        for (i, byte) in key_bytes.iter_mut().enumerate() {
+            *byte = ((key >> ((15 - i) * 8)) & 0xFF) as u8;
         }
 
         // Cypher the message
@@ -102,7 +116,7 @@ mod tests {
 
     #[test]
     fn cypher_decypher_string() {
-        let key = "AdfqdmFqdfqsdfqdfDFs";
+        let key = "AdfqdmFqdfqsdfqd";
         let key: [u8; 16] = key
             .as_bytes()
             .try_into()
@@ -118,4 +132,19 @@ mod tests {
         // Cut down the blocks to 8 bites
         assert_eq!(message.as_bytes().to_vec(), decyphered_message);
     }
+
+    #[test]
+    fn ctr_nist_vector() {
+        // NIST SP 800-38A, F.5.1 CTR-AES128.Encrypt
+        let hex = |text: &str| crate::encoding::from_hex(text).unwrap();
+        let key: [u8; 16] = hex("2b7e151628aed2a6abf7158809cf4f3c").try_into().unwrap();
+        let iv: [u8; 16] = hex("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff").try_into().unwrap();
+        let message = hex("6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51");
+        let cyphered = hex("874d6191b620e3261bef6864990db6ce9806f66b7970fdff8617187bb9fffdff");
+
+        assert_eq!(ctr_message(&message, key, iv), cyphered);
+        assert_eq!(ctr_message(&cyphered, key, iv), message);
+        // a partial block uses the beginning of the key stream
+        assert_eq!(ctr_message(&message[..20], key, iv), cyphered[..20]);
+    }
 }
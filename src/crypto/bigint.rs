@@ -0,0 +1,724 @@
+//! Unsigned integers of any size, for RSA keys bigger than a u32.
+//!
+//! Numbers are stored as 32 bits limbs, least significant first, without
+//! leading zero limbs so that each number has a single representation.
+
+use rand::Rng;
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Rem, Shl, Shr, Sub};
+
+#[derive(Clone, PartialEq, Eq, Hash, Default)]
+pub struct BigUint {
+    limbs: Vec<u32>,
+}
+
+impl BigUint {
+    pub fn zero() -> Self {
+        BigUint { limbs: Vec::new() }
+    }
+
+    pub fn one() -> Self {
+        BigUint::from(1u32)
+    }
+
+    fn from_limbs(mut limbs: Vec<u32>) -> Self {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        BigUint { limbs }
+    }
+
+    /// Read a big endian number
+    pub fn from_bytes_be(bytes: &[u8]) -> Self {
+        let limbs = bytes
+            .rchunks(4)
+            .map(|chunk| chunk.iter().fold(0u32, |limb, &byte| limb << 8 | byte as u32))
+            .collect();
+        BigUint::from_limbs(limbs)
+    }
+
+    /// Read a little endian number
+    pub fn from_bytes_le(bytes: &[u8]) -> Self {
+        let reversed: Vec<u8> = bytes.iter().rev().cloned().collect();
+        BigUint::from_bytes_be(&reversed)
+    }
+
+    /// Big endian bytes, without leading zeros (empty for zero)
+    pub fn to_bytes_be(&self) -> Vec<u8> {
+        let bytes: Vec<u8> = self
+            .limbs
+            .iter()
+            .rev()
+            .flat_map(|limb| limb.to_be_bytes())
+            .collect();
+        let leading_zeros = bytes.iter().take_while(|&&byte| byte == 0).count();
+        bytes[leading_zeros..].to_vec()
+    }
+
+    /// Big endian bytes, left padded with zeros to `length` bytes.
+    ///
+    /// Panics if the number does not fit.
+    pub fn to_bytes_be_padded(&self, length: usize) -> Vec<u8> {
+        let bytes = self.to_bytes_be();
+        assert!(bytes.len() <= length, "number too big for {length} bytes");
+        let mut padded = vec![0; length - bytes.len()];
+        padded.extend_from_slice(&bytes);
+        padded
+    }
+
+    /// Little endian bytes, right padded with zeros to `length` bytes
+    pub fn to_bytes_le_padded(&self, length: usize) -> Vec<u8> {
+        let mut bytes = self.to_bytes_be_padded(length);
+        bytes.reverse();
+        bytes
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn is_odd(&self) -> bool {
+        self.limbs.first().is_some_and(|limb| limb & 1 == 1)
+    }
+
+    /// Number of significant bits
+    pub fn bits(&self) -> usize {
+        match self.limbs.last() {
+            Some(last) => self.limbs.len() * 32 - last.leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    /// Value of the bit `i`, the least significant being 0
+    pub fn bit(&self, i: usize) -> bool {
+        self.limbs
+            .get(i / 32)
+            .is_some_and(|limb| (limb >> (i % 32)) & 1 == 1)
+    }
+
+    /// The number as a u64, `None` if it is too big
+    pub fn to_u64(&self) -> Option<u64> {
+        match self.limbs.len() {
+            0 => Some(0),
+            1 => Some(self.limbs[0] as u64),
+            2 => Some((self.limbs[1] as u64) << 32 | self.limbs[0] as u64),
+            _ => None,
+        }
+    }
+
+    /// Random number of `bits` bits, the most significant one being set
+    pub fn random_bits(bits: usize) -> Self {
+        assert!(bits > 0);
+        let mut rng = rand::rng();
+        let mut limbs: Vec<u32> = (0..bits.div_ceil(32)).map(|_| rng.random()).collect();
+
+        let top_bits = bits - (limbs.len() - 1) * 32;
+        let last = limbs.last_mut().unwrap();
+        if top_bits < 32 {
+            *last &= (1 << top_bits) - 1;
+        }
+        *last |= 1 << (top_bits - 1);
+        BigUint::from_limbs(limbs)
+    }
+
+    /// Random number in [1, bound[
+    pub fn random_below(bound: &BigUint) -> Self {
+        assert!(*bound > BigUint::one());
+        loop {
+            let bits = rand::rng().random_range(1..=bound.bits());
+            let candidate = BigUint::random_bits(bits);
+            if candidate < *bound {
+                return candidate;
+            }
+        }
+    }
+
+    /// Quotient and remainder of the division by `divisor`.
+    ///
+    /// Long division from Knuth, The Art of Computer Programming vol. 2,
+    /// algorithm D. Panics if `divisor` is zero.
+    pub fn div_rem(&self, divisor: &BigUint) -> (BigUint, BigUint) {
+        assert!(!divisor.is_zero(), "division by zero");
+        if self < divisor {
+            return (BigUint::zero(), self.clone());
+        }
+        if divisor.limbs.len() == 1 {
+            let (quotient, remainder) = self.div_rem_limb(divisor.limbs[0]);
+            return (quotient, BigUint::from(remainder));
+        }
+
+        // normalize so that the top limb of the divisor has its high bit set
+        let shift = divisor.limbs.last().unwrap().leading_zeros() as usize;
+        let v = (divisor << shift).limbs;
+        let mut u = (self << shift).limbs;
+        if u.len() == self.limbs.len() {
+            u.push(0);
+        }
+        let n = v.len();
+        let m = u.len() - n - 1;
+        let mut q = vec![0u32; m + 1];
+
+        for j in (0..=m).rev() {
+            // estimate the quotient digit from the top limbs
+            let numerator = (u[j + n] as u64) << 32 | u[j + n - 1] as u64;
+            let mut qhat = numerator / v[n - 1] as u64;
+            let mut rhat = numerator % v[n - 1] as u64;
+            while qhat > u32::MAX as u64
+                || qhat * v[n - 2] as u64 > (rhat << 32 | u[j + n - 2] as u64)
+            {
+                qhat -= 1;
+                rhat += v[n - 1] as u64;
+                if rhat > u32::MAX as u64 {
+                    break;
+                }
+            }
+
+            // u -= qhat * v
+            let mut borrow: i64 = 0;
+            let mut carry: u64 = 0;
+            for i in 0..n {
+                let product = qhat * v[i] as u64 + carry;
+                carry = product >> 32;
+                let t = u[i + j] as i64 - borrow - (product & 0xffff_ffff) as i64;
+                u[i + j] = t as u32;
+                borrow = (t < 0) as i64;
+            }
+            let t = u[j + n] as i64 - borrow - carry as i64;
+            u[j + n] = t as u32;
+
+            // the estimate was one too big, add v back
+            if t < 0 {
+                qhat -= 1;
+                let mut carry: u64 = 0;
+                for i in 0..n {
+                    let sum = u[i + j] as u64 + v[i] as u64 + carry;
+                    u[i + j] = sum as u32;
+                    carry = sum >> 32;
+                }
+                u[j + n] = u[j + n].wrapping_add(carry as u32);
+            }
+            q[j] = qhat as u32;
+        }
+
+        u.truncate(n);
+        (BigUint::from_limbs(q), BigUint::from_limbs(u) >> shift)
+    }
+
+    /// Division by a single limb
+    fn div_rem_limb(&self, divisor: u32) -> (BigUint, u32) {
+        let mut quotient = vec![0u32; self.limbs.len()];
+        let mut remainder: u64 = 0;
+        for (i, &limb) in self.limbs.iter().enumerate().rev() {
+            let current = remainder << 32 | limb as u64;
+            quotient[i] = (current / divisor as u64) as u32;
+            remainder = current % divisor as u64;
+        }
+        (BigUint::from_limbs(quotient), remainder as u32)
+    }
+
+    /// self^exponent mod modulus
+    pub fn modpow(&self, exponent: &BigUint, modulus: &BigUint) -> BigUint {
+        assert!(!modulus.is_zero(), "modulus is zero");
+        if *modulus == BigUint::one() {
+            return BigUint::zero();
+        }
+        if modulus.is_odd() {
+            return Montgomery::new(modulus).pow(self, exponent);
+        }
+
+        // square and multiply
+        let mut result = BigUint::one();
+        let base = self % modulus;
+        for i in (0..exponent.bits()).rev() {
+            result = &(&result * &result) % modulus;
+            if exponent.bit(i) {
+                result = &(&result * &base) % modulus;
+            }
+        }
+        result
+    }
+
+    /// Inverse of self modulo `modulus`, `None` if they are not coprime
+    pub fn modinv(&self, modulus: &BigUint) -> Option<BigUint> {
+        // extended Euclid, the bezout coefficient is kept modulo `modulus`
+        let mut old_r = self % modulus;
+        let mut r = modulus.clone();
+        let mut old_s = BigUint::one();
+        let mut s = BigUint::zero();
+
+        while !r.is_zero() {
+            let (quotient, remainder) = old_r.div_rem(&r);
+            old_r = std::mem::replace(&mut r, remainder);
+
+            let product = &(&quotient * &s) % modulus;
+            let new_s = &(&old_s + modulus) - &product;
+            old_s = std::mem::replace(&mut s, &new_s % modulus);
+        }
+
+        if old_r == BigUint::one() {
+            Some(old_s)
+        } else {
+            None
+        }
+    }
+
+    /// Greatest common divisor
+    pub fn gcd(&self, other: &BigUint) -> BigUint {
+        let mut a = self.clone();
+        let mut b = other.clone();
+        while !b.is_zero() {
+            let remainder = &a % &b;
+            a = std::mem::replace(&mut b, remainder);
+        }
+        a
+    }
+
+    /// Miller-Rabin test with `rounds` random witnesses, after a trial
+    /// division by small primes
+    pub fn is_probable_prime(&self, rounds: usize) -> bool {
+        if let Some(small) = self.to_u64().filter(|&n| n < 1 << 16) {
+            return small >= 2 && SMALL_PRIMES.iter().all(|&p| p as u64 == small || small % p as u64 != 0);
+        }
+        if SMALL_PRIMES.iter().any(|&p| self.div_rem_limb(p).1 == 0) {
+            return false;
+        }
+
+        // self - 1 = m * 2^s with m odd
+        let one = BigUint::one();
+        let n_minus_one = self - &one;
+        let mut s = 0;
+        while !n_minus_one.bit(s) {
+            s += 1;
+        }
+        let m = &n_minus_one >> s;
+
+        let montgomery = Montgomery::new(self);
+        'witness: for _ in 0..rounds {
+            let a = BigUint::random_below(&n_minus_one);
+            let mut x = montgomery.pow(&a, &m);
+            if x == one || x == n_minus_one {
+                continue;
+            }
+            for _ in 1..s {
+                x = &(&x * &x) % self;
+                if x == n_minus_one {
+                    continue 'witness;
+                }
+            }
+            return false;
+        }
+        true
+    }
+
+    /// Random prime number of `bits` bits, with its two top bits set so
+    /// that the product of two of them has exactly `2 * bits` bits
+    pub fn random_prime(bits: usize) -> BigUint {
+        assert!(bits >= 16);
+        let top = &BigUint::one() << (bits - 2);
+        loop {
+            let mut candidate = &BigUint::random_bits(bits) + &top;
+            if candidate.bits() > bits {
+                continue;
+            }
+            if !candidate.is_odd() {
+                candidate = &candidate + &BigUint::one();
+            }
+            if candidate.is_probable_prime(20) {
+                return candidate;
+            }
+        }
+    }
+}
+
+/// The primes below 1000, to quickly reject most candidates
+const SMALL_PRIMES: [u32; 168] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89,
+    97, 101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191,
+    193, 197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293,
+    307, 311, 313, 317, 331, 337, 347, 349, 353, 359, 367, 373, 379, 383, 389, 397, 401, 409, 419,
+    421, 431, 433, 439, 443, 449, 457, 461, 463, 467, 479, 487, 491, 499, 503, 509, 521, 523, 541,
+    547, 557, 563, 569, 571, 577, 587, 593, 599, 601, 607, 613, 617, 619, 631, 641, 643, 647, 653,
+    659, 661, 673, 677, 683, 691, 701, 709, 719, 727, 733, 739, 743, 751, 757, 761, 769, 773, 787,
+    797, 809, 811, 821, 823, 827, 829, 839, 853, 857, 859, 863, 877, 881, 883, 887, 907, 911, 919,
+    929, 937, 941, 947, 953, 967, 971, 977, 983, 991, 997,
+];
+
+/// Multiplication modulo an odd number without division, numbers are kept
+/// multiplied by R = 2^(32 * number of limbs of the modulus).
+struct Montgomery<'a> {
+    modulus: &'a BigUint,
+    /// -modulus^-1 mod 2^32
+    inverse: u32,
+    /// R^2 mod modulus, to convert numbers to the Montgomery form
+    r2: BigUint,
+}
+
+impl<'a> Montgomery<'a> {
+    fn new(modulus: &'a BigUint) -> Self {
+        // inverse of the low limb by Newton iterations, each doubles the
+        // number of correct bits
+        let low = modulus.limbs[0];
+        let mut inverse: u32 = 1;
+        for _ in 0..5 {
+            inverse = inverse.wrapping_mul(2u32.wrapping_sub(low.wrapping_mul(inverse)));
+        }
+
+        let r2 = &(&BigUint::one() << (64 * modulus.limbs.len())) % modulus;
+        Montgomery {
+            modulus,
+            inverse: inverse.wrapping_neg(),
+            r2,
+        }
+    }
+
+    /// a * b / R mod modulus, for a and b below the modulus
+    fn multiply(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        let n = &self.modulus.limbs;
+        let k = n.len();
+        let limb = |x: &BigUint, i: usize| x.limbs.get(i).copied().unwrap_or(0) as u64;
+        let mut t = vec![0u32; k + 2];
+
+        for i in 0..k {
+            // t += a[i] * b
+            let ai = limb(a, i);
+            let mut carry = 0u64;
+            for (j, tj) in t.iter_mut().enumerate().take(k) {
+                let sum = *tj as u64 + ai * limb(b, j) + carry;
+                *tj = sum as u32;
+                carry = sum >> 32;
+            }
+            let sum = t[k] as u64 + carry;
+            t[k] = sum as u32;
+            t[k + 1] = (sum >> 32) as u32;
+
+            // t = (t + m * n) / 2^32, with m chosen so that the division is exact
+            let m = t[0].wrapping_mul(self.inverse) as u64;
+            let mut carry = (t[0] as u64 + m * n[0] as u64) >> 32;
+            for j in 1..k {
+                let sum = t[j] as u64 + m * n[j] as u64 + carry;
+                t[j - 1] = sum as u32;
+                carry = sum >> 32;
+            }
+            let sum = t[k] as u64 + carry;
+            t[k - 1] = sum as u32;
+            t[k] = t[k + 1] + (sum >> 32) as u32;
+            t[k + 1] = 0;
+        }
+
+        let result = BigUint::from_limbs(t);
+        if result >= *self.modulus {
+            &result - self.modulus
+        } else {
+            result
+        }
+    }
+
+    /// base^exponent mod modulus
+    fn pow(&self, base: &BigUint, exponent: &BigUint) -> BigUint {
+        let base = self.multiply(&(base % self.modulus), &self.r2);
+        let mut result = self.multiply(&BigUint::one(), &self.r2);
+        for i in (0..exponent.bits()).rev() {
+            result = self.multiply(&result, &result);
+            if exponent.bit(i) {
+                result = self.multiply(&result, &base);
+            }
+        }
+        self.multiply(&result, &BigUint::one())
+    }
+}
+
+impl From<u32> for BigUint {
+    fn from(value: u32) -> Self {
+        BigUint::from_limbs(vec![value])
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(value: u64) -> Self {
+        BigUint::from_limbs(vec![value as u32, (value >> 32) as u32])
+    }
+}
+
+impl Ord for BigUint {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.limbs
+            .len()
+            .cmp(&other.limbs.len())
+            .then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+}
+
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for &BigUint {
+    type Output = BigUint;
+
+    fn add(self, other: &BigUint) -> BigUint {
+        let length = self.limbs.len().max(other.limbs.len());
+        let mut limbs = Vec::with_capacity(length + 1);
+        let mut carry = 0u64;
+        for i in 0..length {
+            let sum = *self.limbs.get(i).unwrap_or(&0) as u64
+                + *other.limbs.get(i).unwrap_or(&0) as u64
+                + carry;
+            limbs.push(sum as u32);
+            carry = sum >> 32;
+        }
+        limbs.push(carry as u32);
+        BigUint::from_limbs(limbs)
+    }
+}
+
+impl Sub for &BigUint {
+    type Output = BigUint;
+
+    /// Panics if `other` is bigger than `self`
+    fn sub(self, other: &BigUint) -> BigUint {
+        assert!(*self >= *other, "subtraction overflow");
+        let mut limbs = Vec::with_capacity(self.limbs.len());
+        let mut borrow = 0i64;
+        for (i, &limb) in self.limbs.iter().enumerate() {
+            let difference = limb as i64 - *other.limbs.get(i).unwrap_or(&0) as i64 - borrow;
+            limbs.push(difference as u32);
+            borrow = (difference < 0) as i64;
+        }
+        BigUint::from_limbs(limbs)
+    }
+}
+
+impl Mul for &BigUint {
+    type Output = BigUint;
+
+    fn mul(self, other: &BigUint) -> BigUint {
+        let mut limbs = vec![0u32; self.limbs.len() + other.limbs.len()];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.limbs.iter().enumerate() {
+                let product = limbs[i + j] as u64 + a as u64 * b as u64 + carry;
+                limbs[i + j] = product as u32;
+                carry = product >> 32;
+            }
+            limbs[i + other.limbs.len()] = carry as u32;
+        }
+        BigUint::from_limbs(limbs)
+    }
+}
+
+impl Rem for &BigUint {
+    type Output = BigUint;
+
+    fn rem(self, modulus: &BigUint) -> BigUint {
+        self.div_rem(modulus).1
+    }
+}
+
+impl Shl<usize> for &BigUint {
+    type Output = BigUint;
+
+    fn shl(self, shift: usize) -> BigUint {
+        let (limb_shift, bit_shift) = (shift / 32, shift % 32);
+        let mut limbs = vec![0u32; limb_shift];
+        let mut carry = 0u32;
+        for &limb in &self.limbs {
+            if bit_shift == 0 {
+                limbs.push(limb);
+            } else {
+                limbs.push(limb << bit_shift | carry);
+                carry = limb >> (32 - bit_shift);
+            }
+        }
+        limbs.push(carry);
+        BigUint::from_limbs(limbs)
+    }
+}
+
+impl Shl<usize> for BigUint {
+    type Output = BigUint;
+
+    fn shl(self, shift: usize) -> BigUint {
+        &self << shift
+    }
+}
+
+impl Shr<usize> for &BigUint {
+    type Output = BigUint;
+
+    fn shr(self, shift: usize) -> BigUint {
+        let (limb_shift, bit_shift) = (shift / 32, shift % 32);
+        if limb_shift >= self.limbs.len() {
+            return BigUint::zero();
+        }
+        let high = &self.limbs[limb_shift..];
+        let limbs = (0..high.len())
+            .map(|i| {
+                if bit_shift == 0 {
+                    high[i]
+                } else {
+                    let next = high.get(i + 1).copied().unwrap_or(0);
+                    high[i] >> bit_shift | next << (32 - bit_shift)
+                }
+            })
+            .collect();
+        BigUint::from_limbs(limbs)
+    }
+}
+
+impl Shr<usize> for BigUint {
+    type Output = BigUint;
+
+    fn shr(self, shift: usize) -> BigUint {
+        &self >> shift
+    }
+}
+
+impl fmt::Debug for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:x}", self)
+    }
+}
+
+impl fmt::LowerHex for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.limbs.split_last() {
+            None => write!(f, "0x0"),
+            Some((last, rest)) => {
+                write!(f, "0x{:x}", last)?;
+                rest.iter().rev().try_for_each(|limb| write!(f, "{:08x}", limb))
+            }
+        }
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        // cut the number in chunks of 9 decimal digits
+        let mut chunks = Vec::new();
+        let mut rest = self.clone();
+        while !rest.is_zero() {
+            let (quotient, chunk) = rest.div_rem_limb(1_000_000_000);
+            chunks.push(chunk);
+            rest = quotient;
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        chunks
+            .iter()
+            .rev()
+            .try_for_each(|chunk| write!(f, "{:09}", chunk))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(hex: &str) -> BigUint {
+        let hex = if hex.len() % 2 == 1 {
+            format!("0{hex}")
+        } else {
+            hex.to_string()
+        };
+        BigUint::from_bytes_be(&crate::encoding::from_hex(&hex).unwrap())
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let bytes = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09];
+        let number = BigUint::from_bytes_be(&bytes);
+        assert_eq!(number.to_bytes_be(), bytes);
+        assert_eq!(number.bits(), 65);
+        assert_eq!(BigUint::from_bytes_be(&[0, 0, 1]).to_bytes_be(), [1]);
+        assert_eq!(BigUint::from_bytes_le(&[1, 2]), BigUint::from(0x0201u32));
+        assert_eq!(BigUint::from(5u32).to_bytes_be_padded(3), [0, 0, 5]);
+        assert!(BigUint::zero().to_bytes_be().is_empty());
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = big("ffffffffffffffffffffffff");
+        let b = big("1");
+        assert_eq!(&a + &b, big("1000000000000000000000000"));
+        assert_eq!(&(&a + &b) - &b, a);
+        assert_eq!(&a * &a, big("fffffffffffffffffffffffe000000000000000000000001"));
+        assert_eq!(&a << 36, big("ffffffffffffffffffffffff000000000"));
+        assert_eq!(&a >> 36, big("fffffffffffffff"));
+        assert!(a > b);
+    }
+
+    #[test]
+    fn division() {
+        let a = big("1234567890abcdef1234567890abcdef1234567890abcdef");
+        let b = big("fedcba0987654321fedcba09");
+        let (q, r) = a.div_rem(&b);
+        assert!(r < b);
+        assert_eq!(&(&q * &b) + &r, a);
+        assert_eq!(q, big("1249249c805663c40aa756c8"));
+
+        // cases where the quotient estimate is too big
+        let a = big("800000000000000000000000000000000000000000000000");
+        let b = big("800000000000000000000001");
+        let (q, r) = a.div_rem(&b);
+        assert_eq!(&(&q * &b) + &r, a);
+        assert!(r < b);
+
+        let (q, r) = a.div_rem(&BigUint::from(7u32));
+        assert_eq!(&(&q * &BigUint::from(7u32)) + &r, a);
+    }
+
+    #[test]
+    fn modular_arithmetic() {
+        // Fermat: a^(p-1) = 1 mod p for the prime 2^127 - 1
+        let p = big("7fffffffffffffffffffffffffffffff");
+        let a = big("123456789abcdef");
+        assert_eq!(a.modpow(&(&p - &BigUint::one()), &p), BigUint::one());
+        assert_eq!(
+            BigUint::from(4u32).modpow(&BigUint::from(13u32), &BigUint::from(497u32)),
+            BigUint::from(445u32)
+        );
+        // even modulus
+        assert_eq!(
+            BigUint::from(3u32).modpow(&BigUint::from(5u32), &BigUint::from(100u32)),
+            BigUint::from(43u32)
+        );
+
+        let inverse = a.modinv(&p).unwrap();
+        assert_eq!(&(&a * &inverse) % &p, BigUint::one());
+        assert_eq!(BigUint::from(6u32).modinv(&BigUint::from(9u32)), None);
+        assert_eq!(
+            BigUint::from(12u32).gcd(&BigUint::from(18u32)),
+            BigUint::from(6u32)
+        );
+    }
+
+    #[test]
+    fn primes() {
+        assert!(big("7fffffffffffffffffffffffffffffff").is_probable_prime(20));
+        assert!(!big("7ffffffffffffffffffffffffffffffd").is_probable_prime(20));
+        assert!(BigUint::from(997u32).is_probable_prime(20));
+        assert!(!BigUint::from(561u32).is_probable_prime(20));
+        // carmichael number
+        assert!(!BigUint::from(41041u32).is_probable_prime(20));
+
+        let prime = BigUint::random_prime(128);
+        assert_eq!(prime.bits(), 128);
+        assert!(prime.bit(126));
+        assert!(prime.is_probable_prime(20));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(BigUint::zero().to_string(), "0");
+        assert_eq!(
+            big("1000000000000000000000000").to_string(),
+            "79228162514264337593543950336"
+        );
+        assert_eq!(format!("{:x}", big("10000000f")), "0x10000000f");
+    }
+}
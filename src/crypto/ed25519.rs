@@ -0,0 +1,494 @@
+//! Ed25519 signatures (RFC 8032).
+//!
+//! Points of the curve -x^2 + y^2 = 1 + d x^2 y^2 over the field of the
+//! integers modulo p = 2^255 - 19 are kept in extended coordinates
+//! (X, Y, Z, T) with x = X/Z, y = Y/Z and x*y = T/Z.
+
+use super::bigint::BigUint;
+use super::sha512::sha512;
+use rand::Rng;
+use std::ops::{Add, Mul, Neg, Sub};
+use std::sync::OnceLock;
+
+/***********************************
+* 			 Field			   *
+************************************/
+
+const MASK: u64 = (1 << 51) - 1;
+
+/// Element of the field, as 5 limbs of 51 bits, least significant first
+#[derive(Debug, Clone, Copy)]
+struct Fe([u64; 5]);
+
+impl Fe {
+    const ZERO: Fe = Fe([0; 5]);
+    const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+
+    fn from_u64(value: u64) -> Fe {
+        Fe([value & MASK, value >> 51, 0, 0, 0])
+    }
+
+    /// Read 255 bits little endian, the last bit is ignored
+    fn from_bytes(bytes: &[u8; 32]) -> Fe {
+        let mut limbs = [0u64; 5];
+        for i in 0..255 {
+            if (bytes[i / 8] >> (i % 8)) & 1 == 1 {
+                limbs[i / 51] |= 1 << (i % 51);
+            }
+        }
+        Fe(limbs)
+    }
+
+    /// Canonical little endian encoding, fully reduced modulo p
+    fn to_bytes(self) -> [u8; 32] {
+        let mut h = self.carry().carry().0;
+
+        // h is below 2^255, subtract p once if h >= p
+        let mut q = (h[0] + 19) >> 51;
+        for limb in &h[1..] {
+            q = (limb + q) >> 51;
+        }
+        h[0] += 19 * q;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= MASK;
+        }
+        h[4] &= MASK;
+
+        let mut bytes = [0u8; 32];
+        for i in 0..255 {
+            if (h[i / 51] >> (i % 51)) & 1 == 1 {
+                bytes[i / 8] |= 1 << (i % 8);
+            }
+        }
+        bytes
+    }
+
+    /// Propagate the carries so that every limb fits in 51 bits (plus a
+    /// little for the first one)
+    fn carry(self) -> Fe {
+        let mut h = self.0;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= MASK;
+        }
+        // 2^255 = 19 mod p
+        h[0] += 19 * (h[4] >> 51);
+        h[4] &= MASK;
+        Fe(h)
+    }
+
+    fn square(self) -> Fe {
+        self * self
+    }
+
+    /// self^exponent by square and multiply
+    fn pow(self, exponent: &BigUint) -> Fe {
+        let mut result = Fe::ONE;
+        for i in (0..exponent.bits()).rev() {
+            result = result.square();
+            if exponent.bit(i) {
+                result = result * self;
+            }
+        }
+        result
+    }
+
+    fn invert(self) -> Fe {
+        self.pow(&constants().p_minus_2)
+    }
+
+    /// The "sign" of the element: whether its canonical encoding is odd
+    fn is_negative(self) -> bool {
+        self.to_bytes()[0] & 1 == 1
+    }
+
+    fn is_zero(self) -> bool {
+        self.to_bytes() == [0; 32]
+    }
+}
+
+impl PartialEq for Fe {
+    fn eq(&self, other: &Fe) -> bool {
+        self.to_bytes() == other.to_bytes()
+    }
+}
+
+impl Add for Fe {
+    type Output = Fe;
+
+    fn add(self, other: Fe) -> Fe {
+        let mut h = self.0;
+        for (limb, other) in h.iter_mut().zip(other.0) {
+            *limb += other;
+        }
+        Fe(h).carry()
+    }
+}
+
+impl Sub for Fe {
+    type Output = Fe;
+
+    fn sub(self, other: Fe) -> Fe {
+        // add 2p first so that no limb goes below zero
+        const TWO_P: [u64; 5] = [
+            2 * (MASK - 18),
+            2 * MASK,
+            2 * MASK,
+            2 * MASK,
+            2 * MASK,
+        ];
+        let (a, b) = (self.carry().0, other.carry().0);
+        let mut h = [0u64; 5];
+        for i in 0..5 {
+            h[i] = a[i] + TWO_P[i] - b[i];
+        }
+        Fe(h).carry()
+    }
+}
+
+impl Neg for Fe {
+    type Output = Fe;
+
+    fn neg(self) -> Fe {
+        Fe::ZERO - self
+    }
+}
+
+impl Mul for Fe {
+    type Output = Fe;
+
+    fn mul(self, other: Fe) -> Fe {
+        let a = self.0.map(|limb| limb as u128);
+        let b = other.0.map(|limb| limb as u128);
+
+        // the limbs above 2^255 wrap around multiplied by 19
+        let mut r = [
+            a[0] * b[0] + 19 * (a[1] * b[4] + a[2] * b[3] + a[3] * b[2] + a[4] * b[1]),
+            a[0] * b[1] + a[1] * b[0] + 19 * (a[2] * b[4] + a[3] * b[3] + a[4] * b[2]),
+            a[0] * b[2] + a[1] * b[1] + a[2] * b[0] + 19 * (a[3] * b[4] + a[4] * b[3]),
+            a[0] * b[3] + a[1] * b[2] + a[2] * b[1] + a[3] * b[0] + 19 * (a[4] * b[4]),
+            a[0] * b[4] + a[1] * b[3] + a[2] * b[2] + a[3] * b[1] + a[4] * b[0],
+        ];
+
+        for i in 0..4 {
+            r[i + 1] += r[i] >> 51;
+            r[i] &= MASK as u128;
+        }
+        r[0] += 19 * (r[4] >> 51);
+        r[4] &= MASK as u128;
+        r[1] += r[0] >> 51;
+        r[0] &= MASK as u128;
+
+        Fe(r.map(|limb| limb as u64)).carry()
+    }
+}
+
+/***********************************
+* 			 Curve			   *
+************************************/
+
+#[derive(Debug, Clone, Copy)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+/// Constants of the curve, computed once
+struct Constants {
+    d: Fe,
+    d2: Fe,
+    sqrt_m1: Fe,
+    base: Point,
+    /// order of the base point
+    l: BigUint,
+    p_minus_2: BigUint,
+    /// (p - 5) / 8, to compute square roots
+    p_minus_5_over_8: BigUint,
+}
+
+fn constants() -> &'static Constants {
+    static CONSTANTS: OnceLock<Constants> = OnceLock::new();
+    CONSTANTS.get_or_init(|| {
+        let p = &(&BigUint::one() << 255) - &BigUint::from(19u32);
+        let p_minus_2 = &p - &BigUint::from(2u32);
+        let p_minus_5_over_8 = &(&p - &BigUint::from(5u32)) >> 3;
+        let p_minus_1_over_4 = &(&p - &BigUint::one()) >> 2;
+        let l = &(&BigUint::one() << 252)
+            + &BigUint::from_bytes_be(&[
+                0x14, 0xde, 0xf9, 0xde, 0xa2, 0xf7, 0x9c, 0xd6, 0x58, 0x12, 0x63, 0x1a, 0x5c, 0xf5,
+                0xd3, 0xed,
+            ]);
+
+        // d = -121665 / 121666
+        let d = -Fe::from_u64(121665) * Fe::from_u64(121666).pow(&p_minus_2);
+        let sqrt_m1 = Fe::from_u64(2).pow(&p_minus_1_over_4);
+        // the base point has y = 4/5 and a positive x
+        let base_y = Fe::from_u64(4) * Fe::from_u64(5).pow(&p_minus_2);
+
+        let mut constants = Constants {
+            d,
+            d2: d + d,
+            sqrt_m1,
+            base: Point::identity(),
+            l,
+            p_minus_2,
+            p_minus_5_over_8,
+        };
+
+        constants.base = Point::decompress_with(&base_y.to_bytes(), &constants).unwrap();
+        constants
+    })
+}
+
+impl Point {
+    fn identity() -> Point {
+        Point {
+            x: Fe::ZERO,
+            y: Fe::ONE,
+            z: Fe::ONE,
+            t: Fe::ZERO,
+        }
+    }
+
+    /// Encoding of the point: y with the sign of x in the last bit
+    fn compress(&self) -> [u8; 32] {
+        let z_inverse = self.z.invert();
+        let x = self.x * z_inverse;
+        let y = self.y * z_inverse;
+
+        let mut bytes = y.to_bytes();
+        bytes[31] |= (x.is_negative() as u8) << 7;
+        bytes
+    }
+
+    fn decompress(bytes: &[u8; 32]) -> Option<Point> {
+        Point::decompress_with(bytes, constants())
+    }
+
+    fn decompress_with(bytes: &[u8; 32], constants: &Constants) -> Option<Point> {
+        let sign = bytes[31] >> 7 == 1;
+        let y = Fe::from_bytes(bytes);
+        let mut canonical = *bytes;
+        canonical[31] &= 0x7f;
+        if y.to_bytes() != canonical {
+            return None;
+        }
+
+        // x^2 = (y^2 - 1) / (d y^2 + 1) = u / v
+        let y2 = y.square();
+        let u = y2 - Fe::ONE;
+        let v = constants.d * y2 + Fe::ONE;
+        let v3 = v.square() * v;
+        let v7 = v3.square() * v;
+        let mut x = u * v3 * (u * v7).pow(&constants.p_minus_5_over_8);
+
+        let vx2 = v * x.square();
+        if vx2 == -u {
+            x = x * constants.sqrt_m1;
+        } else if vx2 != u {
+            return None;
+        }
+        if x.is_zero() && sign {
+            return None;
+        }
+        if x.is_negative() != sign {
+            x = -x;
+        }
+
+        Some(Point {
+            x,
+            y,
+            z: Fe::ONE,
+            t: x * y,
+        })
+    }
+
+    /// Sum of two points, the formula also works to double a point
+    fn add(&self, other: &Point) -> Point {
+        let a = (self.y - self.x) * (other.y - other.x);
+        let b = (self.y + self.x) * (other.y + other.x);
+        let c = self.t * constants().d2 * other.t;
+        let d = (self.z + self.z) * other.z;
+        let (e, f, g, h) = (b - a, d - c, d + c, b + a);
+        Point {
+            x: e * f,
+            y: g * h,
+            z: f * g,
+            t: e * h,
+        }
+    }
+
+    /// scalar * self, the scalar being little endian
+    fn multiply(&self, scalar: &[u8]) -> Point {
+        let mut result = Point::identity();
+        for byte in scalar.iter().rev() {
+            for i in (0..8).rev() {
+                result = result.add(&result);
+                if (byte >> i) & 1 == 1 {
+                    result = result.add(self);
+                }
+            }
+        }
+        result
+    }
+}
+
+/***********************************
+* 			 Keys			   *
+************************************/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicKey(pub [u8; 32]);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrivateKey {
+    seed: [u8; 32],
+    pub pub_key: PublicKey,
+}
+
+/// Scalar and prefix derived from the seed of a private key
+fn expand_seed(seed: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let hash = sha512(seed.to_vec());
+    let mut scalar = [0u8; 32];
+    let mut prefix = [0u8; 32];
+    scalar.copy_from_slice(&hash[..32]);
+    prefix.copy_from_slice(&hash[32..]);
+
+    scalar[0] &= 248;
+    scalar[31] &= 127;
+    scalar[31] |= 64;
+    (scalar, prefix)
+}
+
+/// Hash of the concatenation of the parts, reduced modulo l
+fn hash_to_scalar(parts: &[&[u8]]) -> BigUint {
+    let hash = sha512(parts.concat());
+    &BigUint::from_bytes_le(&hash) % &constants().l
+}
+
+impl PrivateKey {
+    /// The key derived from a 32 bytes secret seed
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        let (scalar, _) = expand_seed(&seed);
+        let pub_key = PublicKey(constants().base.multiply(&scalar).compress());
+        PrivateKey { seed, pub_key }
+    }
+
+    /// Generate a new private key from a random seed
+    pub fn generate() -> Self {
+        PrivateKey::from_seed(rand::rng().random())
+    }
+
+    pub fn seed(&self) -> &[u8; 32] {
+        &self.seed
+    }
+}
+
+/// Sign `message`, the signature is 64 bytes long
+pub fn sign_message(message: &[u8], key: &PrivateKey) -> [u8; 64] {
+    let (scalar, prefix) = expand_seed(&key.seed);
+    let constants = constants();
+
+    let r = hash_to_scalar(&[&prefix, message]);
+    let big_r = constants.base.multiply(&r.to_bytes_le_padded(32)).compress();
+    let k = hash_to_scalar(&[&big_r, &key.pub_key.0, message]);
+
+    // s = r + k * scalar mod l
+    let s = &(&r + &(&k * &BigUint::from_bytes_le(&scalar))) % &constants.l;
+
+    let mut signature = [0u8; 64];
+    signature[..32].copy_from_slice(&big_r);
+    signature[32..].copy_from_slice(&s.to_bytes_le_padded(32));
+    signature
+}
+
+/// Check a signature made by sign_message
+pub fn verify_signature(message: &[u8], signature: &[u8], key: &PublicKey) -> bool {
+    if signature.len() != 64 {
+        return false;
+    }
+    let big_r: [u8; 32] = signature[..32].try_into().unwrap();
+    let s = &signature[32..];
+    if BigUint::from_bytes_le(s) >= constants().l {
+        return false;
+    }
+    let (Some(a), Some(r)) = (Point::decompress(&key.0), Point::decompress(&big_r)) else {
+        return false;
+    };
+
+    // s B = R + k A
+    let k = hash_to_scalar(&[&big_r, &key.0, message]);
+    let left = constants().base.multiply(s);
+    let right = r.add(&a.multiply(&k.to_bytes_le_padded(32)));
+    left.compress() == right.compress()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::from_hex;
+
+    /// Check a test vector of RFC 8032, section 7.1
+    fn check_vector(secret: &str, public: &str, message: &str, signature: &str) {
+        let seed: [u8; 32] = from_hex(secret).unwrap().try_into().unwrap();
+        let message = from_hex(message).unwrap();
+        let key = PrivateKey::from_seed(seed);
+        assert_eq!(key.pub_key.0.to_vec(), from_hex(public).unwrap());
+
+        let computed = sign_message(&message, &key);
+        assert_eq!(computed.to_vec(), from_hex(signature).unwrap());
+        assert!(verify_signature(&message, &computed, &key.pub_key));
+    }
+
+    #[test]
+    fn rfc8032_empty_message() {
+        check_vector(
+            "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+            "",
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155\
+             5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        );
+    }
+
+    #[test]
+    fn rfc8032_one_byte() {
+        check_vector(
+            "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+            "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+            "72",
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da\
+             085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        );
+    }
+
+    #[test]
+    fn rfc8032_two_bytes() {
+        check_vector(
+            "c5aa8df43f9f837bedb7442f31dcb7b166d38535076f094b85ce3a2e0b4458f7",
+            "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+            "af82",
+            "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac\
+             18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+        );
+    }
+
+    #[test]
+    fn bad_signatures() {
+        let key = PrivateKey::generate();
+        let signature = sign_message(b"message", &key);
+        assert!(verify_signature(b"message", &signature, &key.pub_key));
+        assert!(!verify_signature(b"massage", &signature, &key.pub_key));
+        assert!(!verify_signature(b"message", &signature[..63], &key.pub_key));
+
+        let mut altered = signature;
+        altered[40] ^= 1;
+        assert!(!verify_signature(b"message", &altered, &key.pub_key));
+
+        let other = PrivateKey::generate();
+        assert!(!verify_signature(b"message", &signature, &other.pub_key));
+    }
+}
@@ -1,3 +1,18 @@
+//! Home made cryptographic primitives used by the transport.
+
 pub mod aes;
+pub mod bigint;
+pub mod ed25519;
+pub mod pbkdf2;
 pub mod rsa;
 pub mod sha;
+pub mod sha512;
+pub mod totp;
+
+/// Compare two secrets in a time that does not depend on where they differ
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
@@ -0,0 +1,67 @@
+//! PBKDF2 key derivation (RFC 8018) with HMAC-SHA256.
+
+use super::sha::HmacSha256;
+
+/// Derive `length` bytes from a password and a salt, repeating the HMAC
+/// `iterations` times so that guessing the password is slow.
+pub fn pbkdf2_hmac_sha256(password: &[u8], salt: &[u8], iterations: u32, length: usize) -> Vec<u8> {
+    let hmac = HmacSha256::new(password);
+    let mut key = Vec::with_capacity(length);
+
+    // each bloc of 32 bytes is derived on its own, numbered from 1
+    let mut bloc_index: u32 = 1;
+    while key.len() < length {
+        let mut message = Vec::from(salt);
+        message.extend_from_slice(&bloc_index.to_be_bytes());
+
+        let mut u = hmac.sign(&message);
+        let mut bloc = u;
+        for _ in 1..iterations {
+            u = hmac.sign(&u);
+            bloc.iter_mut().zip(u).for_each(|(b, u)| *b ^= u);
+        }
+
+        let needed = (length - key.len()).min(bloc.len());
+        key.extend_from_slice(&bloc[..needed]);
+        bloc_index += 1;
+    }
+
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|x| format!("{:02x}", x)).collect()
+    }
+
+    #[test]
+    fn pbkdf2_vectors() {
+        let key = pbkdf2_hmac_sha256(b"password", b"salt", 1, 32);
+        let expected = "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b";
+        assert_eq!(to_hex(&key), expected);
+
+        let key = pbkdf2_hmac_sha256(b"password", b"salt", 2, 32);
+        let expected = "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43";
+        assert_eq!(to_hex(&key), expected);
+
+        let key = pbkdf2_hmac_sha256(b"password", b"salt", 4096, 32);
+        let expected = "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a";
+        assert_eq!(to_hex(&key), expected);
+    }
+
+    #[test]
+    fn pbkdf2_several_blocs() {
+        let key = pbkdf2_hmac_sha256(
+            b"passwordPASSWORDpassword",
+            b"saltSALTsaltSALTsaltSALTsaltSALTsalt",
+            4096,
+            40,
+        );
+        let expected =
+            "348c89dbcbd32b2f32d814b8116e84cf2b17347ebc1800181c4e2a1fb8dd53e1c635518c7dac47e9";
+        assert_eq!(to_hex(&key), expected);
+    }
+}
@@ -70,7 +70,7 @@ pub fn find_coprime(n: u32) -> u32 {
             return i;
         }
     }
-    return u32::MAX;
+    u32::MAX
 }
 
 pub fn fast_exp(mut base: u32, mut exp: u32, modulus: u32) -> u32 {
@@ -108,7 +108,7 @@ pub fn mr_test(n: u32, nb_witness: u32) -> bool {
 
     // find s and m with n-1 = 2^s*m with m odd
     let mut tmp = n - 1;
-    while tmp % 2 == 0 {
+    while tmp.is_multiple_of(2) {
         tmp /= 2;
         s += 1;
     }
@@ -1,144 +1,222 @@
 mod arithm;
 
-use arithm::{fast_exp, find_coprime, modular_inv, mr_prime};
+use super::bigint::BigUint;
+use super::sha;
+use arithm::{find_coprime, modular_inv, mr_prime};
 
 /***********************************
 * 				RSA				   *
 ************************************/
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PublicKey {
-    pub n: u32, // public module
-    pub e: u32, // cypher exposant
+    pub n: BigUint, // public module
+    pub e: BigUint, // cypher exposant
 }
 
 impl PublicKey {
-    pub fn new(n: u32, e: u32) -> Self {
+    pub fn new(n: BigUint, e: BigUint) -> Self {
         PublicKey { n, e }
     }
 
-    pub fn as_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::with_capacity(8);
-        bytes.extend_from_slice(&self.n.to_be_bytes());
-        bytes.extend_from_slice(&self.e.to_be_bytes());
-        bytes
+    /// Size of the module in bits
+    pub fn bits(&self) -> usize {
+        self.n.bits()
+    }
+
+    /// Number of bytes of a cyphered block or of a signature
+    fn block_len(&self) -> usize {
+        self.n.bits().div_ceil(8)
+    }
+
+    /// Number of bytes of clear text cyphered in one block, so that a block
+    /// is always smaller than n
+    fn chunk_len(&self) -> usize {
+        (self.n.bits() - 1) / 8
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PrivateKey {
-    p: u32,
-    q: u32,
-    d: u32,
+    p: BigUint,
+    q: BigUint,
+    d: BigUint,
     pub pub_key: PublicKey,
 }
 
 impl PrivateKey {
-    pub fn _new(p: u32, q: u32, d: u32, pub_key: PublicKey) -> Self {
+    pub fn _new(p: BigUint, q: BigUint, d: BigUint, pub_key: PublicKey) -> Self {
         PrivateKey { p, q, d, pub_key }
     }
 
+    pub fn p(&self) -> &BigUint {
+        &self.p
+    }
+
+    pub fn q(&self) -> &BigUint {
+        &self.q
+    }
+
+    pub fn d(&self) -> &BigUint {
+        &self.d
+    }
+
     /// Generate a new private key with a public key
     pub fn generate() -> Self {
-        let mut private_key = PrivateKey {
-            p: 0,
-            q: 0,
-            d: 0,
-            pub_key: PublicKey { n: 0, e: 0 },
-        };
-
-        private_key.p = mr_prime(16, 0.9);
-        private_key.q = mr_prime(16, 0.9);
+        let mut p = mr_prime(16, 0.9);
+        let mut q = mr_prime(16, 0.9);
 
         // Ensure n has 32 binary numbers
         loop {
-            if (private_key.p * private_key.q) >> 31 == 1 {
+            if (p * q) >> 31 == 1 {
                 break;
             }
-            private_key.p = mr_prime(16, 0.9);
-            private_key.q = mr_prime(16, 0.9);
+            p = mr_prime(16, 0.9);
+            q = mr_prime(16, 0.9);
         }
 
-        private_key.pub_key.n = (private_key.p * private_key.q) as u32;
-        let phi_n = (private_key.p - 1) * (private_key.q - 1);
-        private_key.pub_key.e = find_coprime(phi_n);
-        private_key.d = modular_inv(private_key.pub_key.e, phi_n);
+        let phi_n = (p - 1) * (q - 1);
+        let e = find_coprime(phi_n);
+        let d = modular_inv(e, phi_n);
 
-        private_key
+        PrivateKey {
+            p: BigUint::from(p),
+            q: BigUint::from(q),
+            d: BigUint::from(d),
+            pub_key: PublicKey::new(BigUint::from(p * q), BigUint::from(e)),
+        }
     }
-}
 
-/// Cypher function of an u32 depending on the public key
-fn cypher(block: u32, key: PublicKey) -> u32 {
-    // block^e % n
-    fast_exp(block, key.e, key.n)
-}
+    /// Generate a key with a module of `bits` bits and the usual public
+    /// exponent 65537, for keys that identify a user or a host
+    pub fn generate_bits(bits: usize) -> Self {
+        assert!(bits >= 64 && bits.is_multiple_of(2), "bad RSA key size");
+        let e = BigUint::from(65537u32);
+        let one = BigUint::one();
 
-/// Decypher function of an u32 depending on the private key
-fn decypher(block: u32, key: PrivateKey) -> u32 {
-    fast_exp(block, key.d, key.pub_key.n)
+        loop {
+            let p = BigUint::random_prime(bits / 2);
+            let q = BigUint::random_prime(bits / 2);
+            if p == q {
+                continue;
+            }
+
+            let phi_n = &(&p - &one) * &(&q - &one);
+            if let Some(d) = e.modinv(&phi_n) {
+                let n = &p * &q;
+                return PrivateKey {
+                    p,
+                    q,
+                    d,
+                    pub_key: PublicKey::new(n, e),
+                };
+            }
+        }
+    }
 }
 
-fn cypher_blocks(blocks: Vec<u32>, key: PublicKey) -> Vec<u32> {
-    blocks.iter().map(|block| cypher(*block, key)).collect()
+/// Cypher function of a block depending on the public key
+fn cypher(block: &BigUint, key: &PublicKey) -> BigUint {
+    // block^e % n
+    block.modpow(&key.e, &key.n)
 }
 
-fn decypher_blocks(blocks: Vec<u32>, key: PrivateKey) -> Vec<u32> {
-    blocks.iter().map(|block| decypher(*block, key)).collect()
+/// Decypher function of a block depending on the private key
+fn decypher(block: &BigUint, key: &PrivateKey) -> BigUint {
+    block.modpow(&key.d, &key.pub_key.n)
 }
 
 /*********************************
 *		 Message manipulation	 *
 **********************************/
 
-fn bytes_to_blocks(bytes: Vec<u8>) -> Vec<u32> {
-    bytes
-        .chunks(4) // like iter but over 4 element at se same time
-        .map(|chunk| {
-            let mut block = 0;
-            for (i, &byte) in chunk.iter().enumerate() {
-                block |= (byte as u32) << (i * 8);
-            }
-            block
-        })
-        .collect()
+/// Cut the bytes in little endian blocks of `length` bytes
+fn bytes_to_blocks(bytes: &[u8], length: usize) -> Vec<BigUint> {
+    bytes.chunks(length).map(BigUint::from_bytes_le).collect()
 }
 
-fn blocks_to_bytes(blocks: Vec<u32>) -> Vec<u8> {
-    let mut bytes: Vec<u8> = blocks
+/// Inverse of bytes_to_blocks, a block too big for `length` bytes (only
+/// when it was decyphered with the wrong key) loses its high bytes
+fn blocks_to_bytes(blocks: &[BigUint], length: usize) -> Vec<u8> {
+    blocks
         .iter()
         .flat_map(|block| {
-            let mut bytes = [0u8; 4];
-            for i in 0..4 {
-                bytes[i] = ((block >> (i * 8)) & 0xFF) as u8;
-            }
+            let mut bytes = block.to_bytes_be();
+            bytes.reverse();
+            bytes.resize(length, 0);
             bytes
         })
-        .collect();
+        .collect()
+}
 
-    // delete the null bytes at the end that were added during conversion to blocks
-    bytes.truncate(
-        bytes.len()
-            - bytes
-                .clone()
-                .iter()
-                .rev()
-                .take_while(|&byte| *byte == 0)
-                .count(),
-    );
+/// Delete the null bytes at the end that were added to fill the last block
+fn trim_trailing_zeros(mut bytes: Vec<u8>) -> Vec<u8> {
+    let zeros = bytes.iter().rev().take_while(|&&byte| byte == 0).count();
+    bytes.truncate(bytes.len() - zeros);
     bytes
 }
 
-pub fn cypher_message(message: Vec<u8>, key: PublicKey) -> Vec<u8> {
-    let blocks = bytes_to_blocks(message);
-    let cyphered_blocks = cypher_blocks(blocks, key);
-    blocks_to_bytes(cyphered_blocks)
+pub fn cypher_message(message: Vec<u8>, key: &PublicKey) -> Vec<u8> {
+    let blocks: Vec<BigUint> = bytes_to_blocks(&message, key.chunk_len())
+        .iter()
+        .map(|block| cypher(block, key))
+        .collect();
+    blocks_to_bytes(&blocks, key.block_len())
 }
 
-pub fn decypher_message(message: Vec<u8>, key: PrivateKey) -> Vec<u8> {
-    let blocks = bytes_to_blocks(message);
-    let decyphered_blocks = decypher_blocks(blocks, key);
-    blocks_to_bytes(decyphered_blocks)
+pub fn decypher_message(message: Vec<u8>, key: &PrivateKey) -> Vec<u8> {
+    let blocks: Vec<BigUint> = bytes_to_blocks(&message, key.pub_key.block_len())
+        .iter()
+        .map(|block| decypher(block, key))
+        .collect();
+    trim_trailing_zeros(blocks_to_bytes(&blocks, key.pub_key.chunk_len()))
+}
+
+/*********************************
+*		 	Signatures		 	 *
+**********************************/
+
+/// DER header of the SHA256 hash in a PKCS#1 v1.5 signature
+const SHA256_DIGEST_INFO: [u8; 19] = [
+    0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05,
+    0x00, 0x04, 0x20,
+];
+
+/// The block signed for `message`: 00 01 ff .. ff 00 DigestInfo sha256(message)
+fn signature_block(message: &[u8], length: usize) -> Option<Vec<u8>> {
+    let hash = sha::sha256_bytes(message.to_vec());
+    let padding = length.checked_sub(3 + SHA256_DIGEST_INFO.len() + hash.len())?;
+    if padding < 8 {
+        return None;
+    }
+
+    let mut block = vec![0x00, 0x01];
+    block.resize(2 + padding, 0xff);
+    block.push(0x00);
+    block.extend_from_slice(&SHA256_DIGEST_INFO);
+    block.extend_from_slice(&hash);
+    Some(block)
+}
+
+/// Sign `message` with RSASSA-PKCS1-v1_5 and SHA256 (RFC 8017), `None` if
+/// the key is too small to hold the hash
+pub fn sign_message(message: &[u8], key: &PrivateKey) -> Option<Vec<u8>> {
+    let length = key.pub_key.block_len();
+    let block = BigUint::from_bytes_be(&signature_block(message, length)?);
+    Some(decypher(&block, key).to_bytes_be_padded(length))
+}
+
+/// Check a signature made by sign_message
+pub fn verify_signature(message: &[u8], signature: &[u8], key: &PublicKey) -> bool {
+    let length = key.block_len();
+    let signature = BigUint::from_bytes_be(signature);
+    if signature >= key.n {
+        return false;
+    }
+    match signature_block(message, length) {
+        Some(expected) => cypher(&signature, key).to_bytes_be_padded(length) == expected,
+        None => false,
+    }
 }
 
 #[cfg(test)]
@@ -149,13 +227,18 @@ mod tests {
     fn cypher_decipher() {
         // Arrange -> on a un message et une clé
         let message = "Hello, world!";
-        let public_key = PublicKey::new(2436929723, 5);
-        let private_key = PrivateKey::_new(56519, 43117, 1462098053, public_key.clone());
+        let public_key = PublicKey::new(BigUint::from(2436929723u32), BigUint::from(5u32));
+        let private_key = PrivateKey::_new(
+            BigUint::from(56519u32),
+            BigUint::from(43117u32),
+            BigUint::from(1462098053u32),
+            public_key.clone(),
+        );
 
         // Act -> chiffre et déciffre un message
         let decyphered_message = String::from_utf8(decypher_message(
-            cypher_message(message.as_bytes().to_vec(), public_key),
-            private_key,
+            cypher_message(message.as_bytes().to_vec(), &public_key),
+            &private_key,
         ))
         .unwrap_or_else(|_| {
             println!("{:?}", private_key);
@@ -176,8 +259,8 @@ mod tests {
         let public_key = private_key.pub_key.clone();
 
         let decyphered_message = String::from_utf8(decypher_message(
-            cypher_message(message.as_bytes().to_vec(), public_key),
-            private_key,
+            cypher_message(message.as_bytes().to_vec(), &public_key),
+            &private_key,
         ))
         .unwrap_or_else(|_| {
             println!("{:?}", private_key);
@@ -200,8 +283,8 @@ mod tests {
             let public_key = private_key.pub_key.clone();
 
             String::from_utf8(decypher_message(
-                cypher_message(message.as_bytes().to_vec(), public_key),
-                private_key,
+                cypher_message(message.as_bytes().to_vec(), &public_key),
+                &private_key,
             ))
             .unwrap_or_else(|_| {
                 bad_key += 1;
@@ -210,6 +293,35 @@ mod tests {
         }
 
         // Assert
-        assert!(bad_key as f64 / 10000 as f64 <= 0.9);
+        assert!(bad_key as f64 / 10000.0 <= 0.9);
+    }
+
+    #[test]
+    fn big_key() {
+        let message = "Hello, world! This message is longer than one block.";
+        let private_key = PrivateKey::generate_bits(512);
+        let public_key = private_key.pub_key.clone();
+        assert_eq!(public_key.bits(), 512);
+
+        let cyphered = cypher_message(message.as_bytes().to_vec(), &public_key);
+        assert_eq!(cyphered.len() % 64, 0);
+        assert_eq!(decypher_message(cyphered, &private_key), message.as_bytes());
+    }
+
+    #[test]
+    fn signature() {
+        let private_key = PrivateKey::generate_bits(512);
+        let public_key = private_key.pub_key.clone();
+
+        let signature = sign_message(b"session id", &private_key).unwrap();
+        assert_eq!(signature.len(), 64);
+        assert!(verify_signature(b"session id", &signature, &public_key));
+        assert!(!verify_signature(b"session ie", &signature, &public_key));
+
+        let other_key = PrivateKey::generate_bits(512).pub_key;
+        assert!(!verify_signature(b"session id", &signature, &other_key));
+
+        // a 32 bits key can not hold the hash
+        assert_eq!(sign_message(b"session id", &PrivateKey::generate()), None);
     }
 }
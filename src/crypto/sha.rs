@@ -22,7 +22,7 @@ fn maj(x: u32, y: u32, z: u32) -> u32 {
 }
 
 fn rotr(x: u32, n: u32) -> u32 {
-    (x >> n) | (x << (32 - n))
+    x.rotate_right(n)
 }
 
 fn sigma0(x: u32) -> u32 {
@@ -41,13 +41,20 @@ fn sigma1_256(x: u32) -> u32 {
     rotr(x, 17) ^ rotr(x, 19) ^ (x >> 10)
 }
 
+/// Initial hash value
+const H0: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
 // =======================================
 // Preprocessing
 // =======================================
 
-fn preprocess_message(message: &mut Vec<u8>) {
-    // compute thashe number of bits of thashe message
-    let bit_number = message.len() * 8;
+/// pad thashe message, `hashed_before` bytes (a multiple of 64) were already
+/// compressed before it
+fn preprocess_message(message: &mut Vec<u8>, hashed_before: usize) {
+    // compute thashe number of bits of thashe whole message
+    let bit_number = (hashed_before + message.len()) * 8;
 
     // append a 1 bit to thashe message
     message.push(0x80);
@@ -82,61 +89,124 @@ fn add32(a: u32, b: u32) -> u32 {
     a.wrapping_add(b)
 }
 
-pub fn sha256(mut message: Vec<u8>) -> [u32; 8] {
-    preprocess_message(&mut message);
-    let message = parse_message(message);
-    let mut hash: [u32; 8] = [
-        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
-        0x5be0cd19,
-    ];
+/// mix one bloc of 512 bit into the hash
+fn compress(hash: &mut [u32; 8], message_bloc: [u32; 16]) {
+    let mut w: [u32; 64] = [0; 64];
+    // Copy the first 16 words of the message block into w
+    w[..16].copy_from_slice(&message_bloc);
+    // Extend the first 16 words into the remaining 48 words of w
+    for i in 16..64 {
+        w[i] = add32(
+            add32(add32(sigma1_256(w[i - 2]), w[i - 7]), sigma0_256(w[i - 15])),
+            w[i - 16],
+        );
+    }
+
+    let mut a = hash[0];
+    let mut b = hash[1];
+    let mut c = hash[2];
+    let mut d = hash[3];
+    let mut e = hash[4];
+    let mut f = hash[5];
+    let mut g = hash[6];
+    let mut h = hash[7];
 
-    for message_bloc in message {
-        let mut w: [u32; 64] = [0; 64];
-        // Copy the first 16 words of the message block into w
-        for i in 0..16 {
-            w[i] = message_bloc[i];
-        }
-        // Extend the first 16 words into the remaining 48 words of w
-        for i in 16..64 {
-            w[i] = add32(
-                add32(add32(sigma1_256(w[i - 2]), w[i - 7]), sigma0_256(w[i - 15])),
-                w[i - 16],
-            );
-        }
+    for t in 0..64 {
+        let tmp1 = add32(add32(add32(add32(h, sigma1(e)), ch(e, f, g)), K[t]), w[t]);
+        let tmp2 = add32(sigma0(a), maj(a, b, c));
+        h = g;
+        g = f;
+        f = e;
+        e = add32(d, tmp1);
+        d = c;
+        c = b;
+        b = a;
+        a = add32(tmp1, tmp2);
+    }
+
+    hash[0] = add32(a, hash[0]);
+    hash[1] = add32(b, hash[1]);
+    hash[2] = add32(c, hash[2]);
+    hash[3] = add32(d, hash[3]);
+    hash[4] = add32(e, hash[4]);
+    hash[5] = add32(f, hash[5]);
+    hash[6] = add32(g, hash[6]);
+    hash[7] = add32(h, hash[7]);
+}
+
+/// hash the end of a message starting from the state `hash` reached after
+/// compressing its first `hashed_before` bytes
+fn sha256_from(mut hash: [u32; 8], mut message: Vec<u8>, hashed_before: usize) -> [u32; 8] {
+    preprocess_message(&mut message, hashed_before);
+    for message_bloc in parse_message(message) {
+        compress(&mut hash, message_bloc);
+    }
+    hash
+}
+
+pub fn sha256(message: Vec<u8>) -> [u32; 8] {
+    sha256_from(H0, message, 0)
+}
+
+/// convert a hash to 32 bytes, big endian
+fn hash_to_bytes(hash: [u32; 8]) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    for (chunk, word) in bytes.chunks_mut(4).zip(hash) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    bytes
+}
+
+/// SHA256 of the message as 32 bytes, big endian
+pub fn sha256_bytes(message: Vec<u8>) -> [u8; 32] {
+    hash_to_bytes(sha256(message))
+}
+
+// =======================================
+// HMAC
+// =======================================
+
+/// HMAC-SHA256 (RFC 2104) with the key already mixed in, to sign many
+/// messages with the same key without hashing the key each time.
+#[derive(Debug, Clone, Copy)]
+pub struct HmacSha256 {
+    // states after compressing the key xored with the inner and outer pads
+    inner: [u32; 8],
+    outer: [u32; 8],
+}
 
-        let mut a = hash[0];
-        let mut b = hash[1];
-        let mut c = hash[2];
-        let mut d = hash[3];
-        let mut e = hash[4];
-        let mut f = hash[5];
-        let mut g = hash[6];
-        let mut h = hash[7];
-
-        for t in 0..64 {
-            let tmp1 = add32(add32(add32(add32(h, sigma1(e)), ch(e, f, g)), K[t]), w[t]);
-            let tmp2 = add32(sigma0(a), maj(a, b, c));
-            h = g;
-            g = f;
-            f = e;
-            e = add32(d, tmp1);
-            d = c;
-            c = b;
-            b = a;
-            a = add32(tmp1, tmp2);
+impl HmacSha256 {
+    pub fn new(key: &[u8]) -> Self {
+        // a key longer than a bloc is replaced by its hash
+        let mut key_bloc = [0u8; 64];
+        if key.len() > 64 {
+            key_bloc[..32].copy_from_slice(&sha256_bytes(key.to_vec()));
+        } else {
+            key_bloc[..key.len()].copy_from_slice(key);
         }
 
-        hash[0] = add32(a, hash[0]);
-        hash[1] = add32(b, hash[1]);
-        hash[2] = add32(c, hash[2]);
-        hash[3] = add32(d, hash[3]);
-        hash[4] = add32(e, hash[4]);
-        hash[5] = add32(f, hash[5]);
-        hash[6] = add32(g, hash[6]);
-        hash[7] = add32(h, hash[7]);
+        let mut inner = H0;
+        let inner_pad = key_bloc.iter().map(|byte| byte ^ 0x36).collect();
+        compress(&mut inner, parse_message(inner_pad)[0]);
+
+        let mut outer = H0;
+        let outer_pad = key_bloc.iter().map(|byte| byte ^ 0x5c).collect();
+        compress(&mut outer, parse_message(outer_pad)[0]);
+
+        HmacSha256 { inner, outer }
     }
 
-    hash
+    /// Keyed hash of the message
+    pub fn sign(&self, message: &[u8]) -> [u8; 32] {
+        let inner_hash = sha256_from(self.inner, message.to_vec(), 64);
+        let outer_hash = sha256_from(self.outer, hash_to_bytes(inner_hash).to_vec(), 64);
+        hash_to_bytes(outer_hash)
+    }
+}
+
+/// HMAC-SHA256 of the message with the key
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    HmacSha256::new(key).sign(message)
 }
 
 #[cfg(test)]
@@ -194,4 +264,44 @@ mod tests {
         let hash = sha256(msg);
         assert_eq!(hash_to_hex(hash), expected);
     }
+
+    #[test]
+    fn test_sha256_bytes() {
+        let hash = sha256_bytes(b"abc".to_vec());
+        assert_eq!(hash[..4], [0xba, 0x78, 0x16, 0xbf]);
+        assert_eq!(hash[28..], [0xf2, 0x00, 0x15, 0xad]);
+    }
+
+    #[test]
+    fn test_sha256_multiple_blocs() {
+        // SHA256("abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq") from FIPS 180-2
+        let expected = "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1";
+        let msg = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq".to_vec();
+        assert_eq!(hash_to_hex(sha256(msg)), expected);
+    }
+
+    fn bytes_to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|x| format!("{:02x}", x)).collect()
+    }
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231 test cases 1, 2 and 6
+        let expected = "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7";
+        assert_eq!(
+            bytes_to_hex(&hmac_sha256(&[0x0b; 20], b"Hi There")),
+            expected
+        );
+
+        let expected = "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843";
+        let hmac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(bytes_to_hex(&hmac), expected);
+
+        let expected = "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54";
+        let hmac = hmac_sha256(
+            &[0xaa; 131],
+            b"Test Using Larger Than Block-Size Key - Hash Key First",
+        );
+        assert_eq!(bytes_to_hex(&hmac), expected);
+    }
 }
@@ -0,0 +1,95 @@
+//! Time based one time passwords (RFC 6238) with HMAC-SHA256, the codes of
+//! authenticator applications.
+
+use super::constant_time_eq;
+use super::sha::hmac_sha256;
+
+/// Seconds a code is valid
+pub const TIME_STEP: u64 = 30;
+
+/// Digits of a code
+pub const DIGITS: u32 = 6;
+
+/// Codes of the steps before and after the current one are accepted too,
+/// for clocks that drift and users that type slowly
+const WINDOW: u64 = 1;
+
+/// The code of counter `counter` (HOTP, RFC 4226) with `digits` digits
+pub fn hotp(secret: &[u8], counter: u64, digits: u32) -> u32 {
+    let hash = hmac_sha256(secret, &counter.to_be_bytes());
+
+    // the last 4 bits choose where to read 31 bits of the hash
+    let offset = (hash[31] & 0x0f) as usize;
+    let bytes: [u8; 4] = hash[offset..offset + 4].try_into().unwrap();
+    let number = u32::from_be_bytes(bytes) & 0x7fff_ffff;
+    number % 10u32.pow(digits)
+}
+
+/// The code of the time `time`, in seconds since 1970
+pub fn totp(secret: &[u8], time: u64) -> u32 {
+    hotp(secret, time / TIME_STEP, DIGITS)
+}
+
+/// Whether `code` is the code of `secret` around `time`
+pub fn verify(secret: &[u8], code: &str, time: u64) -> bool {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|byte| byte.is_ascii_digit()) {
+        return false;
+    }
+    let step = time / TIME_STEP;
+    let steps = step.saturating_sub(WINDOW)..=step + WINDOW;
+    // every step is checked so that the time taken does not tell which one
+    // matched
+    steps.fold(false, |valid, step| {
+        let expected = format!(
+            "{:0width$}",
+            hotp(secret, step, DIGITS),
+            width = DIGITS as usize
+        );
+        constant_time_eq(expected.as_bytes(), code.as_bytes()) | valid
+    })
+}
+
+/// The `otpauth://` URI of a secret, shown as a QR code by most tools to
+/// enroll authenticator applications
+pub fn uri(secret: &[u8], issuer: &str, account: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={}&issuer={issuer}\
+         &algorithm=SHA256&digits={DIGITS}&period={TIME_STEP}",
+        crate::encoding::to_base32(secret)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc6238_vectors() {
+        // the SHA256 seed of the RFC, with 8 digits
+        let secret = b"12345678901234567890123456789012";
+        let vectors = [
+            (59, 46119246),
+            (1111111109, 68084774),
+            (1111111111, 67062674),
+            (1234567890, 91819424),
+            (2000000000, 90698825),
+            (20000000000, 77737706),
+        ];
+        for (time, code) in vectors {
+            assert_eq!(hotp(secret, time / TIME_STEP, 8), code);
+        }
+        assert_eq!(totp(secret, 59), 119246);
+    }
+
+    #[test]
+    fn verify_window() {
+        let secret = b"12345678901234567890123456789012";
+        assert!(verify(secret, "119246", 59));
+        assert!(verify(secret, " 119246\n", 89));
+        assert!(!verify(secret, "119246", 120));
+        assert!(!verify(secret, "19246", 59));
+        assert!(!verify(secret, "+19246", 59));
+        assert!(!verify(secret, "46119246", 59));
+    }
+}
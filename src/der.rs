@@ -0,0 +1,219 @@
+//! The subset of ASN.1 DER used by PKCS#1 and PKCS#8 key files.
+//!
+//! Every value is a tag, its length and its content. Only the types found in
+//! keys are handled: sequences, integers, octet and bit strings, object
+//! identifiers and null.
+
+use crate::crypto::bigint::BigUint;
+use std::io;
+
+pub const SEQUENCE: u8 = 0x30;
+pub const INTEGER: u8 = 0x02;
+pub const BIT_STRING: u8 = 0x03;
+pub const OCTET_STRING: u8 = 0x04;
+pub const NULL: u8 = 0x05;
+pub const OBJECT_IDENTIFIER: u8 = 0x06;
+
+/// Build DER values one after the other.
+#[derive(Debug, Default, Clone)]
+pub struct Writer {
+    buffer: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        Writer::default()
+    }
+
+    /// A value of any type
+    pub fn tagged(&mut self, tag: u8, content: &[u8]) -> &mut Self {
+        self.buffer.push(tag);
+        // short form below 128, else the number of bytes of the length first
+        if content.len() < 0x80 {
+            self.buffer.push(content.len() as u8);
+        } else {
+            let length = content.len().to_be_bytes();
+            let zeros = length.iter().take_while(|&&byte| byte == 0).count();
+            self.buffer.push(0x80 | (length.len() - zeros) as u8);
+            self.buffer.extend_from_slice(&length[zeros..]);
+        }
+        self.buffer.extend_from_slice(content);
+        self
+    }
+
+    /// A sequence of the values written in `content`
+    pub fn sequence(&mut self, content: &Writer) -> &mut Self {
+        self.tagged(SEQUENCE, &content.buffer)
+    }
+
+    /// A positive integer
+    pub fn integer(&mut self, number: &BigUint) -> &mut Self {
+        let mut bytes = number.to_bytes_be();
+        // zero is one null byte, and a leading 1 bit would make it negative
+        if bytes.first().is_none_or(|&byte| byte & 0x80 != 0) {
+            bytes.insert(0, 0);
+        }
+        self.tagged(INTEGER, &bytes)
+    }
+
+    pub fn octet_string(&mut self, bytes: &[u8]) -> &mut Self {
+        self.tagged(OCTET_STRING, bytes)
+    }
+
+    /// A bit string made of whole bytes
+    pub fn bit_string(&mut self, bytes: &[u8]) -> &mut Self {
+        let mut content = vec![0];
+        content.extend_from_slice(bytes);
+        self.tagged(BIT_STRING, &content)
+    }
+
+    pub fn null(&mut self) -> &mut Self {
+        self.tagged(NULL, &[])
+    }
+
+    /// An object identifier, given already encoded
+    pub fn oid(&mut self, oid: &[u8]) -> &mut Self {
+        self.tagged(OBJECT_IDENTIFIER, oid)
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Read DER values one after the other.
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    /// The next value, as its tag and its content
+    pub fn any(&mut self) -> io::Result<(u8, &'a [u8])> {
+        let [tag, first, rest @ ..] = self.data else {
+            return Err(invalid("truncated DER value"));
+        };
+
+        let (length, rest) = if first & 0x80 == 0 {
+            (*first as usize, rest)
+        } else {
+            let size = (first & 0x7f) as usize;
+            if size == 0 || size > 4 || rest.len() < size {
+                return Err(invalid("bad DER length"));
+            }
+            let length = rest[..size]
+                .iter()
+                .fold(0usize, |length, &byte| length << 8 | byte as usize);
+            (length, &rest[size..])
+        };
+
+        if rest.len() < length {
+            return Err(invalid("truncated DER value"));
+        }
+        let (content, rest) = rest.split_at(length);
+        self.data = rest;
+        Ok((*tag, content))
+    }
+
+    /// The content of the next value, which must have the type `tag`
+    pub fn expect(&mut self, tag: u8) -> io::Result<&'a [u8]> {
+        match self.any()? {
+            (found, content) if found == tag => Ok(content),
+            (found, _) => Err(invalid(&format!(
+                "expected DER type {tag:#04x}, found {found:#04x}"
+            ))),
+        }
+    }
+
+    /// A reader over the content of the next value, a sequence
+    pub fn sequence(&mut self) -> io::Result<Reader<'a>> {
+        Ok(Reader::new(self.expect(SEQUENCE)?))
+    }
+
+    /// A positive integer
+    pub fn integer(&mut self) -> io::Result<BigUint> {
+        let bytes = self.expect(INTEGER)?;
+        if bytes.first().is_none_or(|&byte| byte & 0x80 != 0) {
+            return Err(invalid("negative or empty DER integer"));
+        }
+        Ok(BigUint::from_bytes_be(bytes))
+    }
+
+    pub fn octet_string(&mut self) -> io::Result<&'a [u8]> {
+        self.expect(OCTET_STRING)
+    }
+
+    /// A bit string made of whole bytes
+    pub fn bit_string(&mut self) -> io::Result<&'a [u8]> {
+        match self.expect(BIT_STRING)? {
+            [0, bytes @ ..] => Ok(bytes),
+            _ => Err(invalid("unsupported DER bit string")),
+        }
+    }
+
+    pub fn oid(&mut self) -> io::Result<&'a [u8]> {
+        self.expect(OBJECT_IDENTIFIER)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Fail if some values were not read
+    pub fn finish(&self) -> io::Result<()> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid("unexpected data after the DER value"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut content = Writer::new();
+        content
+            .integer(&BigUint::zero())
+            .integer(&BigUint::from(0x80u32))
+            .null()
+            .oid(&[0x2b, 0x65, 0x70])
+            .octet_string(&[1; 200])
+            .bit_string(&[2, 3]);
+        let mut writer = Writer::new();
+        writer.sequence(&content);
+        let bytes = writer.into_bytes();
+        assert_eq!(bytes[..9], [0x30, 0x81, 0xde, 0x02, 0x01, 0x00, 0x02, 0x02, 0x00]);
+
+        let mut reader = Reader::new(&bytes);
+        let mut sequence = reader.sequence().unwrap();
+        assert!(reader.finish().is_ok());
+        assert_eq!(sequence.integer().unwrap(), BigUint::zero());
+        assert_eq!(sequence.integer().unwrap(), BigUint::from(0x80u32));
+        assert_eq!(sequence.expect(NULL).unwrap(), []);
+        assert_eq!(sequence.oid().unwrap(), [0x2b, 0x65, 0x70]);
+        assert_eq!(sequence.octet_string().unwrap(), [1; 200]);
+        assert_eq!(sequence.bit_string().unwrap(), [2, 3]);
+        assert!(sequence.finish().is_ok());
+    }
+
+    #[test]
+    fn bad_values() {
+        assert!(Reader::new(&[0x02]).any().is_err());
+        assert!(Reader::new(&[0x02, 0x02, 0x01]).any().is_err());
+        assert!(Reader::new(&[0x02, 0x01, 0xff]).integer().is_err());
+        assert!(Reader::new(&[0x04, 0x00]).integer().is_err());
+        assert!(Reader::new(&[0x04, 0x85, 0, 0, 0, 0, 1]).any().is_err());
+    }
+}
@@ -0,0 +1,217 @@
+//! Text encodings of binary data used in files and messages.
+
+/// Encode bytes as lower case hexadecimal
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decode hexadecimal, `None` if `text` is not valid hexadecimal
+pub fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode bytes as base64 (RFC 4648), with `=` padding
+pub fn to_base64(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (i, &byte)| group | (byte as u32) << (16 - 8 * i));
+
+        // a chunk of n bytes gives n + 1 characters
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (group >> (18 - 6 * i)) & 0x3f;
+                text.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+/// Decode base64, the padding is optional. `None` if `text` is not valid
+/// base64
+pub fn from_base64(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+    if text.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    for chunk in text.as_bytes().chunks(4) {
+        let mut group = 0u32;
+        for (i, &char) in chunk.iter().enumerate() {
+            let value = BASE64_ALPHABET.iter().position(|&c| c == char)?;
+            group |= (value as u32) << (18 - 6 * i);
+        }
+        // n characters give n - 1 bytes
+        for i in 0..chunk.len() - 1 {
+            bytes.push((group >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Encode bytes as base32 (RFC 4648) without padding, the encoding of TOTP
+/// secrets
+pub fn to_base32(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            text.push(BASE32_ALPHABET[(buffer >> bits) as usize & 0x1f] as char);
+        }
+    }
+    if bits > 0 {
+        text.push(BASE32_ALPHABET[(buffer << (5 - bits)) as usize & 0x1f] as char);
+    }
+    text
+}
+
+/// Decode base32, the padding is optional and lower case letters and spaces
+/// are accepted as secrets are often typed by hand. `None` if `text` is not
+/// valid base32
+pub fn from_base32(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for char in text.trim_end_matches('=').bytes().filter(|&c| c != b' ') {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|&c| c == char.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+/// Wrap bytes in a PEM block: base64 lines of `width` characters between
+/// `-----BEGIN <label>-----` and `-----END <label>-----`
+pub fn to_pem(label: &str, bytes: &[u8], width: usize) -> String {
+    let base64 = to_base64(bytes);
+    let mut text = format!("-----BEGIN {label}-----\n");
+    for line in base64.as_bytes().chunks(width) {
+        text.push_str(std::str::from_utf8(line).unwrap());
+        text.push('\n');
+    }
+    text.push_str(&format!("-----END {label}-----\n"));
+    text
+}
+
+/// Decode the first PEM block of `text`, returns its label and its bytes.
+/// `None` if there is no valid block
+pub fn from_pem(text: &str) -> Option<(String, Vec<u8>)> {
+    let mut lines = text.lines().map(str::trim);
+    let label = lines
+        .find_map(|line| line.strip_prefix("-----BEGIN "))?
+        .strip_suffix("-----")?;
+    let end = format!("-----END {label}-----");
+
+    let mut base64 = String::new();
+    for line in lines.by_ref() {
+        if line == end {
+            return Some((label.to_string(), from_base64(&base64)?));
+        }
+        base64.push_str(line);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trip() {
+        let bytes = [0x00, 0x01, 0x7f, 0x80, 0xff];
+        assert_eq!(to_hex(&bytes), "00017f80ff");
+        assert_eq!(from_hex("00017F80ff"), Some(bytes.to_vec()));
+    }
+
+    #[test]
+    fn hex_invalid() {
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+        assert_eq!(from_hex("é0"), None);
+    }
+
+    #[test]
+    fn base64_round_trip() {
+        // RFC 4648 test vectors
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (clear, encoded) in vectors {
+            assert_eq!(to_base64(clear.as_bytes()), encoded);
+            assert_eq!(from_base64(encoded), Some(clear.as_bytes().to_vec()));
+        }
+        assert_eq!(from_base64("Zm9vYg"), Some(b"foob".to_vec()));
+        assert_eq!(to_base64(&[0xfb, 0xff]), "+/8=");
+    }
+
+    #[test]
+    fn base64_invalid() {
+        assert_eq!(from_base64("Zm9vY"), None);
+        assert_eq!(from_base64("Zm9v!"), None);
+        assert_eq!(from_base64("Zm 9v"), None);
+    }
+
+    #[test]
+    fn base32_round_trip() {
+        // RFC 4648 test vectors, without padding
+        let vectors = [
+            ("", ""),
+            ("f", "MY"),
+            ("fo", "MZXQ"),
+            ("foo", "MZXW6"),
+            ("foob", "MZXW6YQ"),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI"),
+        ];
+        for (clear, encoded) in vectors {
+            assert_eq!(to_base32(clear.as_bytes()), encoded);
+            assert_eq!(from_base32(encoded), Some(clear.as_bytes().to_vec()));
+        }
+        assert_eq!(from_base32("mzxw 6ytb oi======"), Some(b"foobar".to_vec()));
+        assert_eq!(from_base32("MZXW1"), None);
+    }
+
+    #[test]
+    fn pem_round_trip() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let pem = to_pem("TEST KEY", &bytes, 64);
+        assert!(pem.starts_with("-----BEGIN TEST KEY-----\n"));
+        assert!(pem.ends_with("-----END TEST KEY-----\n"));
+        assert!(pem.lines().all(|line| line.len() <= 64));
+        assert_eq!(from_pem(&pem), Some((String::from("TEST KEY"), bytes)));
+
+        assert_eq!(from_pem("-----BEGIN A-----\nZm9v\n-----END B-----\n"), None);
+        assert_eq!(from_pem("Zm9v"), None);
+    }
+}
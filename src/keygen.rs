@@ -0,0 +1,618 @@
+//! `nssh keygen`: create and inspect key files.
+//!
+//! ```text
+//! nssh keygen [-t ed25519|rsa] [-b <bits>] [-C <comment>] [-m <format>] [-N <passphrase>] [-f <file>]
+//! nssh keygen -l -f <file>    fingerprint and randomart of a key
+//! nssh keygen -y -f <file>    public key of a private key file
+//! nssh keygen -e [-m <format>] -f <file>    public key in another format
+//! nssh keygen -p [-P <old passphrase>] [-N <new passphrase>] -f <file>
+//! nssh keygen sign <CA key> -I <key id> [-h] [-n <principals>] [-V <validity>] [-z <serial>]
+//!             [-O <option>=<value>] -f <public key>
+//! nssh keygen -L -f <certificate>    content of a certificate
+//! ```
+//!
+//! `sign` signs a public key with a CA key into `<public key>-cert.pub`, a user
+//! certificate or a host one with `-h`, valid for the comma separated
+//! `principals`. The validity is `<from>:<to>` or `<to>` (from now on) where
+//! times are `always`, `forever`, `YYYYMMDD[HHMMSS]` (UTC) or relative to now
+//! like `+52w`, `-1d` (units `s`, `m`, `h`, `d` and `w`). As with ssh-keygen,
+//! `-s <CA key>` anywhere does the same.
+//!
+//! The formats are `openssh`, `pkcs8` and `pem` (PKCS#1, only for RSA). Keys
+//! are read in any of them, only `openssh` keys can have a passphrase. The
+//! passphrases not given with `-P` and `-N` are asked on the terminal.
+
+use nssh::keys::{unix_time, Certificate, CertificateType, Format, PrivateKey, PublicKey};
+use nssh::terminal;
+use std::env;
+use std::ffi::CStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const USAGE: &str = "usage: nssh keygen [-t ed25519|rsa] [-b <bits>] [-C <comment>] [-m <format>] [-N <passphrase>] [-f <file>]\n       \
+                     nssh keygen -l -f <file>\n       \
+                     nssh keygen -y -f <file>\n       \
+                     nssh keygen -e [-m <format>] -f <file>\n       \
+                     nssh keygen -p [-P <old passphrase>] [-N <new passphrase>] -f <file>\n       \
+                     nssh keygen sign <CA key> -I <key id> [-h] [-n <principals>] [-V <validity>] [-z <serial>] [-O <option>=<value>] -f <public key>\n       \
+                     nssh keygen -L -f <certificate>";
+
+/// Size of the RSA keys when `-b` is not given
+const DEFAULT_RSA_BITS: usize = 3072;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Generate,
+    Fingerprint,
+    PublicKey,
+    Export,
+    ChangePassphrase,
+    Sign,
+    ShowCertificate,
+}
+
+#[derive(Debug)]
+struct Options {
+    action: Action,
+    key_type: String,
+    bits: Option<usize>,
+    comment: Option<String>,
+    format: Option<Format>,
+    file: Option<PathBuf>,
+    /// `-P`, the passphrase of the key read
+    old_passphrase: Option<String>,
+    /// `-N`, the passphrase of the key written
+    new_passphrase: Option<String>,
+    /// `-s`, the CA key signing a certificate
+    ca_key: Option<PathBuf>,
+    /// `-I`, `-h`, `-n`, `-V`, `-z` and `-O`: fields of a certificate
+    key_id: Option<String>,
+    host_certificate: bool,
+    principals: Vec<String>,
+    validity: Option<String>,
+    serial: u64,
+    critical_options: Vec<(String, String)>,
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
+
+fn parse_format(name: &str) -> io::Result<Format> {
+    match name.to_lowercase().as_str() {
+        "openssh" | "rfc4716" => Ok(Format::OpenSsh),
+        "pkcs8" => Ok(Format::Pkcs8),
+        "pem" | "pkcs1" => Ok(Format::Pkcs1),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown key format {name}"),
+        )),
+    }
+}
+
+fn parse_args(args: &[String]) -> io::Result<Options> {
+    let mut options = Options {
+        action: Action::Generate,
+        key_type: String::from("ed25519"),
+        bits: None,
+        comment: None,
+        format: None,
+        file: None,
+        old_passphrase: None,
+        new_passphrase: None,
+        ca_key: None,
+        key_id: None,
+        host_certificate: false,
+        principals: Vec::new(),
+        validity: None,
+        serial: 0,
+        critical_options: Vec::new(),
+    };
+
+    let mut args = args.iter().peekable();
+    if args.next_if(|arg| *arg == "sign").is_some() {
+        options.action = Action::Sign;
+        options.ca_key = Some(PathBuf::from(args.next().ok_or_else(usage)?));
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-l" => options.action = Action::Fingerprint,
+            "-y" => options.action = Action::PublicKey,
+            "-e" => options.action = Action::Export,
+            "-p" => options.action = Action::ChangePassphrase,
+            "-P" => options.old_passphrase = Some(args.next().ok_or_else(usage)?.clone()),
+            "-N" => options.new_passphrase = Some(args.next().ok_or_else(usage)?.clone()),
+            "-m" => options.format = Some(parse_format(args.next().ok_or_else(usage)?)?),
+            "-t" => options.key_type = args.next().ok_or_else(usage)?.to_lowercase(),
+            "-b" => {
+                let bits = args.next().ok_or_else(usage)?;
+                options.bits = Some(bits.parse().map_err(|_| usage())?);
+            }
+            "-C" => options.comment = Some(args.next().ok_or_else(usage)?.clone()),
+            "-s" => {
+                options.action = Action::Sign;
+                options.ca_key = Some(PathBuf::from(args.next().ok_or_else(usage)?));
+            }
+            "-L" => options.action = Action::ShowCertificate,
+            "-I" => options.key_id = Some(args.next().ok_or_else(usage)?.clone()),
+            "-h" => options.host_certificate = true,
+            "-n" => {
+                let principals = args.next().ok_or_else(usage)?;
+                options.principals = principals.split(',').map(String::from).collect();
+            }
+            "-V" => options.validity = Some(args.next().ok_or_else(usage)?.clone()),
+            "-z" => {
+                let serial = args.next().ok_or_else(usage)?;
+                options.serial = serial.parse().map_err(|_| usage())?;
+            }
+            "-O" => {
+                let option = args.next().ok_or_else(usage)?;
+                let (name, value) = option.split_once('=').unwrap_or((option, ""));
+                options
+                    .critical_options
+                    .push((name.to_string(), value.to_string()));
+            }
+            "-f" => options.file = Some(PathBuf::from(args.next().ok_or_else(usage)?)),
+            _ => return Err(usage()),
+        }
+    }
+    Ok(options)
+}
+
+/// `~/.nssh/id_<type>`
+fn default_file(key_type: &str) -> io::Result<PathBuf> {
+    let home = env::var_os("HOME")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?;
+    Ok(PathBuf::from(home).join(".nssh").join(format!("id_{key_type}")))
+}
+
+/// `user@host`, the default comment of a new key
+fn default_comment() -> String {
+    let user = env::var("USER")
+        .or_else(|_| env::var("LOGNAME"))
+        .unwrap_or_default();
+    let mut host = [0u8; 256];
+    // SAFETY: the buffer is big enough for any host name and stays null
+    // terminated as its last byte is never written
+    let host = unsafe {
+        if libc::gethostname(host.as_mut_ptr() as *mut libc::c_char, host.len() - 1) == 0 {
+            CStr::from_ptr(host.as_ptr() as *const libc::c_char)
+                .to_string_lossy()
+                .into_owned()
+        } else {
+            String::new()
+        }
+    };
+    format!("{user}@{host}")
+}
+
+/// The public key path of a private key file
+fn public_file(file: &Path) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(".pub");
+    PathBuf::from(name)
+}
+
+/// The passphrase of a new key from `-N` or the terminal, `None` for no
+/// passphrase
+fn new_passphrase(options: &Options) -> io::Result<Option<String>> {
+    let passphrase = match &options.new_passphrase {
+        Some(passphrase) => passphrase.clone(),
+        None => {
+            let passphrase =
+                terminal::read_password("Enter passphrase (empty for no passphrase): ")?;
+            let again = terminal::read_password("Enter same passphrase again: ")?;
+            if passphrase != again {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "passphrases do not match",
+                ));
+            }
+            passphrase
+        }
+    };
+    Ok(Some(passphrase).filter(|passphrase| !passphrase.is_empty()))
+}
+
+fn generate(options: &Options) -> io::Result<()> {
+    let key = match (options.key_type.as_str(), options.bits) {
+        ("ed25519", None) => PrivateKey::generate_ed25519(),
+        ("ed25519", Some(_)) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "ed25519 keys have a fixed size",
+            ))
+        }
+        ("rsa", bits) => {
+            let bits = bits.unwrap_or(DEFAULT_RSA_BITS);
+            if !(1024..=16384).contains(&bits) || bits % 8 != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "RSA keys must have between 1024 and 16384 bits, a multiple of 8",
+                ));
+            }
+            println!("Generating a {bits} bits RSA key, this can take a while...");
+            PrivateKey::generate_rsa(bits)
+        }
+        (other, _) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown key type {other}"),
+            ))
+        }
+    };
+
+    let file = match &options.file {
+        Some(file) => file.clone(),
+        None => {
+            let file = default_file(&options.key_type)?;
+            if let Some(dir) = file.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            file
+        }
+    };
+    let comment = options.comment.clone().unwrap_or_else(default_comment);
+    let format = options.format.unwrap_or(Format::OpenSsh);
+    let public = key.public_key();
+    // only the OpenSSH format can be cyphered, there is nothing to ask for others
+    let passphrase = match (format, &options.new_passphrase) {
+        (Format::OpenSsh, _) | (_, Some(_)) => new_passphrase(options)?,
+        _ => None,
+    };
+
+    key.write_file(&file, format, &comment, passphrase.as_deref())
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", file.display())))?;
+    // the public key stays a line that can be copied to authorized_keys
+    fs::write(public_file(&file), public.to_line(&comment) + "\n")?;
+
+    println!("Your identification has been saved in {}", file.display());
+    println!(
+        "Your public key has been saved in {}",
+        public_file(&file).display()
+    );
+    println!("The key fingerprint is:");
+    println!("{} {}", public.fingerprint(), comment);
+    println!("The key's randomart image is:");
+    println!("{}", public.randomart());
+    Ok(())
+}
+
+/// Read a private key file with its comment, the passphrase comes from `-P`
+/// or the terminal if the key has one
+fn read_private_key(file: &Path, options: &Options) -> io::Result<(PrivateKey, String)> {
+    let text = fs::read_to_string(file)?;
+    let passphrase = match &options.old_passphrase {
+        _ if !PrivateKey::is_encrypted(&text) => None,
+        Some(passphrase) => Some(passphrase.clone()),
+        None => {
+            let passphrase = terminal::read_password("Enter passphrase: ")?;
+            Some(passphrase)
+        }
+    };
+    PrivateKey::parse_with_comment(&text, passphrase.as_deref())
+}
+
+/// Read a public key line or a private key file, with the comment if any
+fn read_any_key(file: &Path, options: &Options) -> io::Result<(PublicKey, String)> {
+    let text = fs::read_to_string(file)?;
+    if PublicKey::parse(&text).is_ok() {
+        return PublicKey::parse(&text);
+    }
+    let (key, comment) = read_private_key(file, options)?;
+    Ok((key.public_key(), comment))
+}
+
+/// Cypher a key file with a new passphrase, or remove it. The file is written
+/// in the OpenSSH format, the only one with passphrases.
+fn change_passphrase(options: &Options) -> io::Result<()> {
+    let file = options.file.as_deref().ok_or_else(usage)?;
+    let (key, comment) = read_private_key(file, options)?;
+    let passphrase = new_passphrase(options)?;
+
+    // written next to the file then moved, the key is never lost halfway
+    let mut temporary = file.as_os_str().to_owned();
+    temporary.push(".new");
+    let temporary = PathBuf::from(temporary);
+    key.write_file(&temporary, Format::OpenSsh, &comment, passphrase.as_deref())
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", temporary.display())))?;
+    if let Err(e) = fs::rename(&temporary, file) {
+        let _ = fs::remove_file(&temporary);
+        return Err(e);
+    }
+    println!("Your identification has been saved with the new passphrase.");
+    Ok(())
+}
+
+/// Days since 1970-01-01 of a date of the Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Date of the Gregorian calendar of a number of days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// A time of `-V` as seconds since 1970, `now` is used by relative times
+fn parse_time(text: &str, now: u64) -> io::Result<u64> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid time {text}"),
+        )
+    };
+
+    match text {
+        "always" => return Ok(0),
+        "forever" => return Ok(u64::MAX),
+        _ => {}
+    }
+    if let Some(relative) = text.strip_prefix(['+', '-']) {
+        let (number, unit) = relative.split_at(relative.len().saturating_sub(1));
+        let seconds = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            "d" => 86400,
+            "w" => 7 * 86400,
+            _ => return Err(invalid()),
+        };
+        let offset = number
+            .parse::<u64>()
+            .map_err(|_| invalid())?
+            .saturating_mul(seconds);
+        return Ok(if text.starts_with('+') {
+            now.saturating_add(offset)
+        } else {
+            now.saturating_sub(offset)
+        });
+    }
+
+    if !(text.len() == 8 || text.len() == 14) || !text.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let field = |range: std::ops::Range<usize>| text.get(range).map_or(0, |f| f.parse().unwrap());
+    let (month, day) = (field(4..6), field(6..8));
+    let (hour, minute, second) = (field(8..10), field(10..12), field(12..14));
+    let bad_time = hour > 23 || minute > 59 || second > 59;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || bad_time {
+        return Err(invalid());
+    }
+    let days = days_from_civil(field(0..4), month, day);
+    u64::try_from(days * 86400 + hour * 3600 + minute * 60 + second).map_err(|_| invalid())
+}
+
+/// The validity window of `-V`, forever by default
+fn parse_validity(validity: Option<&str>, now: u64) -> io::Result<(u64, u64)> {
+    match validity {
+        None => Ok((0, u64::MAX)),
+        Some(validity) => match validity.split_once(':') {
+            Some((from, to)) => Ok((parse_time(from, now)?, parse_time(to, now)?)),
+            None => Ok((now, parse_time(validity, now)?)),
+        },
+    }
+}
+
+/// A time as `YYYY-MM-DDTHH:MM:SS` (UTC)
+fn format_time(time: u64) -> String {
+    match time {
+        0 => String::from("always"),
+        u64::MAX => String::from("forever"),
+        time => {
+            let time = time as i64;
+            let (year, month, day) = civil_from_days(time.div_euclid(86400));
+            let seconds = time.rem_euclid(86400);
+            format!(
+                "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}",
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60
+            )
+        }
+    }
+}
+
+/// The certificate path of a public key file, `<name>-cert.pub`
+fn certificate_file(file: &Path) -> PathBuf {
+    let name = file.to_string_lossy();
+    let name = name.strip_suffix(".pub").unwrap_or(&name);
+    PathBuf::from(format!("{name}-cert.pub"))
+}
+
+/// Sign the public key of `-f` with the CA key of `sign` or `-s`
+fn sign(options: &Options) -> io::Result<()> {
+    let ca_file = options.ca_key.as_deref().ok_or_else(usage)?;
+    let file = options.file.as_deref().ok_or_else(usage)?;
+    let key_id = options.key_id.clone().ok_or_else(usage)?;
+    let (ca, _) = read_private_key(ca_file, options)?;
+    let (key, comment) = read_any_key(file, options)?;
+
+    let certificate_type = if options.host_certificate {
+        CertificateType::Host
+    } else {
+        CertificateType::User
+    };
+    let mut certificate = Certificate::new(key, certificate_type);
+    certificate.serial = options.serial;
+    certificate.key_id = key_id;
+    certificate.principals = options.principals.clone();
+    (certificate.valid_after, certificate.valid_before) =
+        parse_validity(options.validity.as_deref(), unix_time())?;
+    certificate.critical_options = options.critical_options.clone();
+    if certificate.valid_after >= certificate.valid_before {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the certificate would never be valid",
+        ));
+    }
+    certificate.sign(&ca)?;
+
+    let output = certificate_file(file);
+    fs::write(&output, certificate.to_line(&comment) + "\n")?;
+    let kind = if options.host_certificate { "host" } else { "user" };
+    println!(
+        "Signed {} key {}: id \"{}\" serial {} valid from {} to {}",
+        kind,
+        output.display(),
+        certificate.key_id,
+        certificate.serial,
+        format_time(certificate.valid_after),
+        format_time(certificate.valid_before)
+    );
+    if certificate.principals.is_empty() {
+        println!("Warning: no principal given, the certificate is valid for nobody");
+    }
+    for principal in &certificate.principals {
+        println!("        {principal}");
+    }
+    Ok(())
+}
+
+/// Print the content of a certificate file
+fn show_certificate(options: &Options) -> io::Result<()> {
+    let file = options.file.as_deref().ok_or_else(usage)?;
+    let (certificate, _) = Certificate::parse_line(&fs::read_to_string(file)?)?;
+    let signature_key = certificate.signature_key().ok_or_else(usage)?;
+
+    println!("{}:", file.display());
+    println!("        Type: {:?} certificate", certificate.certificate_type);
+    println!(
+        "        Public key: {} {}",
+        certificate.key.type_name(),
+        certificate.key.fingerprint()
+    );
+    let signature = if certificate.verify_signature() { "" } else { " (bad signature)" };
+    println!(
+        "        Signing CA: {} {}{}",
+        signature_key.type_name(),
+        signature_key.fingerprint(),
+        signature
+    );
+    println!("        Key ID: \"{}\"", certificate.key_id);
+    println!("        Serial: {}", certificate.serial);
+    println!(
+        "        Valid: from {} to {}",
+        format_time(certificate.valid_after),
+        format_time(certificate.valid_before)
+    );
+    println!("        Principals:");
+    for principal in &certificate.principals {
+        println!("                {principal}");
+    }
+    println!("        Critical Options:");
+    for (name, value) in &certificate.critical_options {
+        println!("                {name} {value}");
+    }
+    Ok(())
+}
+
+/// Run `nssh keygen` with the arguments that follow it
+pub fn run(args: &[String]) -> io::Result<()> {
+    let options = parse_args(args)?;
+
+    match options.action {
+        Action::Generate => generate(&options),
+        Action::Fingerprint => {
+            let file = options.file.as_deref().ok_or_else(usage)?;
+            let (key, comment) = read_any_key(file, &options)?;
+            let comment = if comment.is_empty() { "no comment" } else { &comment };
+            println!(
+                "{} {} {} ({})",
+                key.bits(),
+                key.fingerprint(),
+                comment,
+                key.type_name()
+            );
+            println!("{}", key.randomart());
+            Ok(())
+        }
+        Action::PublicKey => {
+            let file = options.file.as_deref().ok_or_else(usage)?;
+            let (key, comment) = read_private_key(file, &options)?;
+            println!("{}", key.public_key().to_line(&comment));
+            Ok(())
+        }
+        Action::Export => {
+            let file = options.file.as_deref().ok_or_else(usage)?;
+            let (key, comment) = read_any_key(file, &options)?;
+            let format = options.format.unwrap_or(Format::Pkcs8);
+            print!("{}", key.to_file_string(format, &comment)?);
+            Ok(())
+        }
+        Action::ChangePassphrase => change_passphrase(&options),
+        Action::Sign => sign(&options),
+        Action::ShowCertificate => show_certificate(&options),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates() {
+        for days in [-1, 0, 59, 10957, 19000, 2932896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(format_time(951782400 + 3723), "2000-02-29T01:02:03");
+    }
+
+    #[test]
+    fn sign_arguments() {
+        let args = |line: &str| line.split(' ').map(String::from).collect::<Vec<_>>();
+        for line in [
+            "sign ca -I alice -n alice,admin -f id.pub",
+            "-s ca -I alice -n alice,admin -f id.pub",
+        ] {
+            let options = parse_args(&args(line)).unwrap();
+            assert_eq!(options.action, Action::Sign, "{line}");
+            assert_eq!(options.ca_key, Some(PathBuf::from("ca")));
+            assert_eq!(options.key_id.as_deref(), Some("alice"));
+            assert_eq!(options.principals, ["alice", "admin"]);
+            assert_eq!(options.file, Some(PathBuf::from("id.pub")));
+        }
+        assert!(parse_args(&args("sign")).is_err());
+        assert!(parse_args(&args("-I alice sign ca")).is_err());
+    }
+
+    #[test]
+    fn validity() {
+        let now = 1_000_000;
+        assert_eq!(parse_validity(None, now).unwrap(), (0, u64::MAX));
+        assert_eq!(
+            parse_validity(Some("+1w"), now).unwrap(),
+            (now, now + 7 * 86400)
+        );
+        assert_eq!(
+            parse_validity(Some("-5m:forever"), now).unwrap(),
+            (now - 300, u64::MAX)
+        );
+        assert_eq!(
+            parse_validity(Some("20000229:20000301010203"), now).unwrap(),
+            (951782400, 951872523)
+        );
+        assert!(parse_validity(Some("+1y"), now).is_err());
+        assert!(parse_validity(Some("20001301"), now).is_err());
+        assert!(parse_validity(Some("tomorrow"), now).is_err());
+    }
+}
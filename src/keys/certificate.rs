@@ -0,0 +1,385 @@
+//! Certificates: a public key signed by a certificate authority (CA) for some
+//! principals (user logins or host names) during a validity window.
+//!
+//! A server that trusts a user CA accepts any key it signed, a client that
+//! trusts a host CA accepts any server it signed, so there is no need to copy
+//! every key in authorized_keys and known hosts files.
+//!
+//! The format is a simpler version of the OpenSSH one (PROTOCOL.certkeys):
+//!
+//! ```text
+//! string "nssh-cert-v1"
+//! string public key blob
+//! u64    serial
+//! u32    type, 1 for users and 2 for hosts
+//! string key id
+//! string principals, a list of strings
+//! u64    valid after, u64 valid before (seconds since 1970)
+//! string critical options, a list of string name and string data
+//! string public key blob of the CA
+//! string signature by the CA of everything above
+//! ```
+
+use super::{PrivateKey, PublicKey};
+use crate::cidr::Cidr;
+use crate::encoding::{from_base64, to_base64};
+use crate::wire::{Reader, Writer};
+use std::io;
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the certificate format, at the start of blobs and lines
+pub const CERTIFICATE_TYPE: &str = "nssh-cert-v1";
+/// Critical option restricting the addresses a user certificate can be used
+/// from, a comma separated list of networks
+pub const SOURCE_ADDRESS: &str = "source-address";
+
+/// Critical options understood, a certificate with another one is refused
+const KNOWN_OPTIONS: [&str; 1] = [SOURCE_ADDRESS];
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn refused(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, message)
+}
+
+/// Seconds since 1970
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Who a certificate is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertificateType {
+    User,
+    Host,
+}
+
+impl CertificateType {
+    fn code(self) -> u32 {
+        match self {
+            CertificateType::User => 1,
+            CertificateType::Host => 2,
+        }
+    }
+}
+
+/// A public key signed by a CA, see the module documentation.
+///
+/// The fields are filled then the certificate is signed with
+/// [`Certificate::sign`], changing a field afterwards breaks the signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Certificate {
+    pub key: PublicKey,
+    pub serial: u64,
+    pub certificate_type: CertificateType,
+    /// Free text identifying the key, logged when the certificate is used
+    pub key_id: String,
+    /// Logins or host names allowed, an empty list allows none
+    pub principals: Vec<String>,
+    pub valid_after: u64,
+    pub valid_before: u64,
+    pub critical_options: Vec<(String, String)>,
+    signature_key: Option<PublicKey>,
+    signature: Vec<u8>,
+}
+
+impl Certificate {
+    /// An unsigned certificate for `key`, valid forever and for no principal
+    pub fn new(key: PublicKey, certificate_type: CertificateType) -> Self {
+        Certificate {
+            key,
+            serial: 0,
+            certificate_type,
+            key_id: String::new(),
+            principals: Vec::new(),
+            valid_after: 0,
+            valid_before: u64::MAX,
+            critical_options: Vec::new(),
+            signature_key: None,
+            signature: Vec::new(),
+        }
+    }
+
+    /// The key of the CA that signed the certificate
+    pub fn signature_key(&self) -> Option<&PublicKey> {
+        self.signature_key.as_ref()
+    }
+
+    /// Value of a critical option
+    pub fn critical_option(&self, name: &str) -> Option<&str> {
+        self.critical_options
+            .iter()
+            .find(|(option, _)| option == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// What the CA signs: the blob up to the signature
+    fn signed_data(&self, signature_key: &PublicKey) -> Vec<u8> {
+        let mut principals = Writer::new();
+        for principal in &self.principals {
+            principals.string(principal);
+        }
+        let mut options = Writer::new();
+        for (name, value) in &self.critical_options {
+            options.string(name).string(value);
+        }
+
+        let mut writer = Writer::new();
+        writer
+            .string(CERTIFICATE_TYPE)
+            .string(self.key.to_blob())
+            .u64(self.serial)
+            .u32(self.certificate_type.code())
+            .string(&self.key_id)
+            .string(principals.into_bytes())
+            .u64(self.valid_after)
+            .u64(self.valid_before)
+            .string(options.into_bytes())
+            .string(signature_key.to_blob());
+        writer.into_bytes()
+    }
+
+    /// Sign the certificate with the key of the CA
+    pub fn sign(&mut self, ca: &PrivateKey) -> io::Result<()> {
+        let signature_key = ca.public_key();
+        self.signature = ca.sign(&self.signed_data(&signature_key))?;
+        self.signature_key = Some(signature_key);
+        Ok(())
+    }
+
+    pub fn to_blob(&self) -> Vec<u8> {
+        let signature_key = self.signature_key.as_ref().unwrap_or(&self.key);
+        let mut writer = Writer::new();
+        writer
+            .raw(&self.signed_data(signature_key))
+            .string(&self.signature);
+        writer.into_bytes()
+    }
+
+    /// Read a certificate blob, the signature is not checked
+    pub fn from_blob(blob: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader::new(blob);
+        if reader.str()? != CERTIFICATE_TYPE {
+            return Err(invalid("not a certificate"));
+        }
+        let key = PublicKey::from_blob(reader.string()?)?;
+        let serial = reader.u64()?;
+        let certificate_type = match reader.u32()? {
+            1 => CertificateType::User,
+            2 => CertificateType::Host,
+            _ => return Err(invalid("unknown certificate type")),
+        };
+        let key_id = reader.str()?.to_string();
+
+        let mut principals = Vec::new();
+        let mut list = Reader::new(reader.string()?);
+        while !list.is_empty() {
+            principals.push(list.str()?.to_string());
+        }
+        let valid_after = reader.u64()?;
+        let valid_before = reader.u64()?;
+        let mut critical_options = Vec::new();
+        let mut list = Reader::new(reader.string()?);
+        while !list.is_empty() {
+            critical_options.push((list.str()?.to_string(), list.str()?.to_string()));
+        }
+
+        let signature_key = PublicKey::from_blob(reader.string()?)?;
+        let signature = reader.string()?.to_vec();
+        reader.finish()?;
+
+        Ok(Certificate {
+            key,
+            serial,
+            certificate_type,
+            key_id,
+            principals,
+            valid_after,
+            valid_before,
+            critical_options,
+            signature_key: Some(signature_key),
+            signature,
+        })
+    }
+
+    /// Whether a blob is a certificate rather than a plain public key
+    pub fn is_certificate(blob: &[u8]) -> bool {
+        Reader::new(blob)
+            .str()
+            .is_ok_and(|name| name == CERTIFICATE_TYPE)
+    }
+
+    /// The certificate as one line of text, like [`PublicKey::to_line`]
+    pub fn to_line(&self, comment: &str) -> String {
+        let line = format!("{} {}", CERTIFICATE_TYPE, to_base64(&self.to_blob()));
+        if comment.is_empty() {
+            line
+        } else {
+            format!("{line} {comment}")
+        }
+    }
+
+    /// Parse a line written by [`Certificate::to_line`], returns the
+    /// certificate and its comment
+    pub fn parse_line(line: &str) -> io::Result<(Self, String)> {
+        let mut fields = line.trim().splitn(3, char::is_whitespace);
+        if fields.next() != Some(CERTIFICATE_TYPE) {
+            return Err(invalid("not a certificate"));
+        }
+        let blob = fields
+            .next()
+            .and_then(from_base64)
+            .ok_or_else(|| invalid("expected nssh-cert-v1 <certificate in base64> [comment]"))?;
+        let comment = fields.next().unwrap_or_default().trim().to_string();
+        Ok((Certificate::from_blob(&blob)?, comment))
+    }
+
+    /// Whether the signature of the CA is right
+    pub fn verify_signature(&self) -> bool {
+        match &self.signature_key {
+            Some(signature_key) => {
+                signature_key.verify(&self.signed_data(signature_key), &self.signature)
+            }
+            None => false,
+        }
+    }
+
+    /// Check that the certificate can be used by `principal` at the time
+    /// `now`: signature, type, principal, validity and critical options.
+    ///
+    /// Whether the CA is trusted is up to the caller.
+    pub fn check_at(
+        &self,
+        certificate_type: CertificateType,
+        principal: &str,
+        now: u64,
+    ) -> io::Result<()> {
+        if !self.verify_signature() {
+            return Err(refused(String::from("bad certificate signature")));
+        }
+        if self.certificate_type != certificate_type {
+            return Err(refused(format!(
+                "expected a {certificate_type:?} certificate"
+            )));
+        }
+        if !self.principals.iter().any(|name| name == principal) {
+            return Err(refused(format!(
+                "the certificate is not valid for {principal}"
+            )));
+        }
+        if now < self.valid_after || now >= self.valid_before {
+            return Err(refused(String::from(
+                "the certificate has expired or is not yet valid",
+            )));
+        }
+        if let Some((name, _)) = self
+            .critical_options
+            .iter()
+            .find(|(name, _)| !KNOWN_OPTIONS.contains(&name.as_str()))
+        {
+            return Err(refused(format!("unknown critical option {name}")));
+        }
+        Ok(())
+    }
+
+    /// [`Certificate::check_at`] the current time
+    pub fn check(&self, certificate_type: CertificateType, principal: &str) -> io::Result<()> {
+        self.check_at(certificate_type, principal, unix_time())
+    }
+
+    /// Whether the `source-address` option, if any, allows `ip`
+    pub fn allows_source(&self, ip: IpAddr) -> bool {
+        match self.critical_option(SOURCE_ADDRESS) {
+            Some(list) => Cidr::parse_list(list)
+                .is_ok_and(|networks| networks.iter().any(|network| network.contains(ip))),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{test_ed25519_key, test_rsa_key};
+
+    fn user_certificate(ca: &PrivateKey) -> Certificate {
+        let mut certificate =
+            Certificate::new(test_ed25519_key().public_key(), CertificateType::User);
+        certificate.serial = 42;
+        certificate.key_id = String::from("alice@laptop");
+        certificate.principals = vec![String::from("alice"), String::from("admin")];
+        certificate.valid_after = 1000;
+        certificate.valid_before = 2000;
+        certificate.sign(ca).unwrap();
+        certificate
+    }
+
+    #[test]
+    fn round_trip() {
+        let ca = test_rsa_key();
+        let certificate = user_certificate(&ca);
+        assert_eq!(certificate.signature_key(), Some(&ca.public_key()));
+        assert!(Certificate::is_certificate(&certificate.to_blob()));
+        assert!(!Certificate::is_certificate(&ca.public_key().to_blob()));
+
+        let line = certificate.to_line("comment");
+        assert!(line.starts_with("nssh-cert-v1 "));
+        let (parsed, comment) = Certificate::parse_line(&line).unwrap();
+        assert_eq!(parsed, certificate);
+        assert_eq!(comment, "comment");
+        assert!(Certificate::parse_line("ssh-ed25519 AAAA").is_err());
+    }
+
+    #[test]
+    fn checks() {
+        let ca = test_ed25519_key();
+        let certificate = user_certificate(&ca);
+        assert!(certificate
+            .check_at(CertificateType::User, "alice", 1500)
+            .is_ok());
+        assert!(certificate
+            .check_at(CertificateType::Host, "alice", 1500)
+            .is_err());
+        assert!(certificate
+            .check_at(CertificateType::User, "bob", 1500)
+            .is_err());
+        assert!(certificate
+            .check_at(CertificateType::User, "alice", 999)
+            .is_err());
+        assert!(certificate
+            .check_at(CertificateType::User, "alice", 2000)
+            .is_err());
+
+        // a changed field breaks the signature
+        let mut altered = certificate.clone();
+        altered.principals.push(String::from("root"));
+        assert!(altered
+            .check_at(CertificateType::User, "root", 1500)
+            .is_err());
+        let unsigned = Certificate::new(ca.public_key(), CertificateType::User);
+        assert!(!unsigned.verify_signature());
+
+        let mut restricted = certificate.clone();
+        restricted.critical_options = vec![(SOURCE_ADDRESS.to_string(), "10.0.0.0/8".to_string())];
+        restricted.sign(&ca).unwrap();
+        assert!(restricted
+            .check_at(CertificateType::User, "alice", 1500)
+            .is_ok());
+        assert!(restricted.allows_source("10.1.2.3".parse().unwrap()));
+        assert!(!restricted.allows_source("192.168.1.1".parse().unwrap()));
+        assert!(certificate.allows_source("192.168.1.1".parse().unwrap()));
+
+        let mut unknown = certificate;
+        unknown.critical_options = vec![("permit-everything".to_string(), String::new())];
+        unknown.sign(&ca).unwrap();
+        assert!(unknown
+            .check_at(CertificateType::User, "alice", 1500)
+            .is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! PKCS#1 (RFC 8017) and PKCS#8 (RFC 5208, RFC 8410) encodings of keys, the
+//! DER inside `RSA PRIVATE KEY`, `PRIVATE KEY`, `RSA PUBLIC KEY` and
+//! `PUBLIC KEY` PEM blocks.
+
+use super::{PrivateKey, PublicKey};
+use crate::crypto::bigint::BigUint;
+use crate::crypto::{ed25519, rsa};
+use crate::der::{Reader, Writer, NULL};
+use std::io;
+
+/// rsaEncryption, 1.2.840.113549.1.1.1
+const RSA_ENCRYPTION: [u8; 9] = [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+/// id-Ed25519, 1.3.101.112
+const ED25519: [u8; 3] = [0x2b, 0x65, 0x70];
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// RSAPrivateKey of PKCS#1
+pub(super) fn rsa_private_key(key: &rsa::PrivateKey) -> Vec<u8> {
+    let one = BigUint::one();
+    let (p, q, d) = (key.p(), key.q(), key.d());
+
+    let mut content = Writer::new();
+    content
+        .integer(&BigUint::zero())
+        .integer(&key.pub_key.n)
+        .integer(&key.pub_key.e)
+        .integer(d)
+        .integer(p)
+        .integer(q)
+        .integer(&(d % &(p - &one)))
+        .integer(&(d % &(q - &one)))
+        .integer(&q.modinv(p).unwrap_or_default());
+    let mut writer = Writer::new();
+    writer.sequence(&content);
+    writer.into_bytes()
+}
+
+pub(super) fn parse_rsa_private_key(der: &[u8]) -> io::Result<rsa::PrivateKey> {
+    let mut reader = Reader::new(der);
+    let mut sequence = reader.sequence()?;
+    reader.finish()?;
+
+    if sequence.integer()? != BigUint::zero() {
+        return Err(invalid("multi-prime RSA keys are not supported"));
+    }
+    let n = sequence.integer()?;
+    let e = sequence.integer()?;
+    let d = sequence.integer()?;
+    let p = sequence.integer()?;
+    let q = sequence.integer()?;
+    // the CRT numbers are computed again when needed
+    if &p * &q != n {
+        return Err(invalid("inconsistent RSA key"));
+    }
+    Ok(rsa::PrivateKey::_new(p, q, d, rsa::PublicKey::new(n, e)))
+}
+
+/// RSAPublicKey of PKCS#1
+pub(super) fn rsa_public_key(key: &rsa::PublicKey) -> Vec<u8> {
+    let mut content = Writer::new();
+    content.integer(&key.n).integer(&key.e);
+    let mut writer = Writer::new();
+    writer.sequence(&content);
+    writer.into_bytes()
+}
+
+pub(super) fn parse_rsa_public_key(der: &[u8]) -> io::Result<rsa::PublicKey> {
+    let mut reader = Reader::new(der);
+    let mut sequence = reader.sequence()?;
+    reader.finish()?;
+    let n = sequence.integer()?;
+    let e = sequence.integer()?;
+    sequence.finish()?;
+    Ok(rsa::PublicKey::new(n, e))
+}
+
+/// AlgorithmIdentifier of a key
+fn algorithm(rsa: bool) -> Writer {
+    let mut algorithm = Writer::new();
+    if rsa {
+        algorithm.oid(&RSA_ENCRYPTION).null();
+    } else {
+        algorithm.oid(&ED25519);
+    }
+    algorithm
+}
+
+/// Read an AlgorithmIdentifier, true for RSA and false for Ed25519
+fn parse_algorithm(reader: &mut Reader) -> io::Result<bool> {
+    let mut algorithm = reader.sequence()?;
+    let oid = algorithm.oid()?;
+    if oid == RSA_ENCRYPTION {
+        // the parameters are NULL or absent
+        if !algorithm.is_empty() {
+            algorithm.expect(NULL)?;
+        }
+        Ok(true)
+    } else if oid == ED25519 {
+        Ok(false)
+    } else {
+        Err(invalid("unsupported key algorithm"))
+    }
+}
+
+/// PrivateKeyInfo of PKCS#8
+pub(super) fn private_key_info(key: &PrivateKey) -> Vec<u8> {
+    let (rsa, private) = match key {
+        PrivateKey::Rsa(key) => (true, rsa_private_key(key)),
+        PrivateKey::Ed25519(key) => {
+            let mut seed = Writer::new();
+            seed.octet_string(key.seed());
+            (false, seed.into_bytes())
+        }
+    };
+
+    let mut content = Writer::new();
+    content
+        .integer(&BigUint::zero())
+        .sequence(&algorithm(rsa))
+        .octet_string(&private);
+    let mut writer = Writer::new();
+    writer.sequence(&content);
+    writer.into_bytes()
+}
+
+pub(super) fn parse_private_key_info(der: &[u8]) -> io::Result<PrivateKey> {
+    let mut reader = Reader::new(der);
+    let mut sequence = reader.sequence()?;
+    reader.finish()?;
+
+    // version 1 adds the public key after the private one, it is ignored
+    if sequence.integer()? > BigUint::one() {
+        return Err(invalid("unsupported PKCS#8 version"));
+    }
+    let rsa = parse_algorithm(&mut sequence)?;
+    let private = sequence.octet_string()?;
+
+    if rsa {
+        Ok(PrivateKey::Rsa(parse_rsa_private_key(private)?))
+    } else {
+        let mut reader = Reader::new(private);
+        let seed = reader
+            .octet_string()?
+            .try_into()
+            .map_err(|_| invalid("bad ed25519 key length"))?;
+        reader.finish()?;
+        Ok(PrivateKey::Ed25519(ed25519::PrivateKey::from_seed(seed)))
+    }
+}
+
+/// SubjectPublicKeyInfo of X.509, used by PKCS#8 public key files
+pub(super) fn subject_public_key_info(key: &PublicKey) -> Vec<u8> {
+    let (rsa, public) = match key {
+        PublicKey::Rsa(key) => (true, rsa_public_key(key)),
+        PublicKey::Ed25519(key) => (false, key.0.to_vec()),
+    };
+
+    let mut content = Writer::new();
+    content.sequence(&algorithm(rsa)).bit_string(&public);
+    let mut writer = Writer::new();
+    writer.sequence(&content);
+    writer.into_bytes()
+}
+
+pub(super) fn parse_subject_public_key_info(der: &[u8]) -> io::Result<PublicKey> {
+    let mut reader = Reader::new(der);
+    let mut sequence = reader.sequence()?;
+    reader.finish()?;
+    let rsa = parse_algorithm(&mut sequence)?;
+    let public = sequence.bit_string()?;
+    sequence.finish()?;
+
+    if rsa {
+        Ok(PublicKey::Rsa(parse_rsa_public_key(public)?))
+    } else {
+        let key = public
+            .try_into()
+            .map_err(|_| invalid("bad ed25519 key length"))?;
+        Ok(PublicKey::Ed25519(ed25519::PublicKey(key)))
+    }
+}
@@ -0,0 +1,181 @@
+//! Revoked keys and certificates.
+//!
+//! A revocation file has one entry per line, followed by an optional comment.
+//! Empty lines and lines starting with `#` are ignored:
+//!
+//! ```text
+//! # stolen laptop
+//! key SHA256:06oCA3sYdAPrFFPM+9v2erdQzchk7NgewELlIrrWM94 alice@laptop
+//! serial 42
+//! ```
+//!
+//! A key is revoked by its fingerprint, which also revokes its certificates
+//! and, for a CA, every certificate it signed. A certificate is revoked by its
+//! serial, whatever CA signed it.
+
+use super::{Certificate, PublicKey};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// One entry of a revocation file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Revocation {
+    /// The fingerprint of a key, as written by [`PublicKey::fingerprint`]
+    Key(String),
+    /// The serial of a certificate
+    Serial(u64),
+}
+
+impl fmt::Display for Revocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Revocation::Key(fingerprint) => write!(f, "key {fingerprint}"),
+            Revocation::Serial(serial) => write!(f, "serial {serial}"),
+        }
+    }
+}
+
+/// Parse one line of a revocation file, `None` for empty lines and comments
+fn parse_entry(line: &str) -> Option<io::Result<(Revocation, String)>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let mut fields = line.splitn(3, char::is_whitespace);
+    let kind = fields.next().unwrap_or_default();
+    let value = fields.next().unwrap_or_default();
+    let comment = fields.next().unwrap_or_default().trim().to_string();
+
+    let revocation = match kind {
+        "key" if value.starts_with("SHA256:") => Revocation::Key(value.to_string()),
+        "serial" => match value.parse() {
+            Ok(serial) => Revocation::Serial(serial),
+            Err(_) => return Some(Err(invalid(&format!("invalid serial {value}")))),
+        },
+        _ => return Some(Err(invalid("expected key <fingerprint> or serial <number>"))),
+    };
+    Some(Ok((revocation, comment)))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// A revocation file, see the module documentation. The default one is
+/// empty and can not be written.
+#[derive(Debug, Clone, Default)]
+pub struct RevokedKeys {
+    path: PathBuf,
+    entries: Vec<(Revocation, String)>,
+}
+
+impl RevokedKeys {
+    /// Read the file at `path`, empty if it does not exist yet.
+    ///
+    /// A line that can not be parsed is an error rather than skipped, as it
+    /// could be a revocation.
+    pub fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let entries = content
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| {
+                parse_entry(line).map(|entry| {
+                    entry.map_err(|e| invalid(&format!("line {}: {e}", i + 1)))
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(RevokedKeys { path, entries })
+    }
+
+    /// The entries of the file with their comment
+    pub fn entries(&self) -> &[(Revocation, String)] {
+        &self.entries
+    }
+
+    fn contains(&self, revocation: &Revocation) -> bool {
+        self.entries.iter().any(|(entry, _)| entry == revocation)
+    }
+
+    pub fn is_key_revoked(&self, key: &PublicKey) -> bool {
+        self.contains(&Revocation::Key(key.fingerprint()))
+    }
+
+    /// Whether the certificate, its key or the CA that signed it is revoked
+    pub fn is_certificate_revoked(&self, certificate: &Certificate) -> bool {
+        self.contains(&Revocation::Serial(certificate.serial))
+            || self.is_key_revoked(&certificate.key)
+            || certificate
+                .signature_key()
+                .is_some_and(|ca| self.is_key_revoked(ca))
+    }
+
+    /// Add an entry at the end of the file, false if it was already there
+    pub fn revoke(&mut self, revocation: Revocation, comment: &str) -> io::Result<bool> {
+        if self.contains(&revocation) {
+            return Ok(false);
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        if comment.is_empty() {
+            writeln!(file, "{revocation}")?;
+        } else {
+            writeln!(file, "{revocation} {comment}")?;
+        }
+        self.entries.push((revocation, comment.to_string()));
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{test_ed25519_key, test_rsa_key, CertificateType};
+    use std::env;
+
+    #[test]
+    fn revocation_file() {
+        let path = env::temp_dir().join(format!("nssh-revoked-{}", std::process::id()));
+        let ca = test_rsa_key();
+        let key = test_ed25519_key().public_key();
+        let mut certificate = Certificate::new(key.clone(), CertificateType::User);
+        certificate.serial = 42;
+        certificate.sign(&ca).unwrap();
+
+        let mut revoked = RevokedKeys::load(&path).unwrap();
+        assert!(!revoked.is_key_revoked(&key));
+        assert!(!revoked.is_certificate_revoked(&certificate));
+
+        assert!(revoked.revoke(Revocation::Serial(42), "").unwrap());
+        assert!(!revoked.revoke(Revocation::Serial(42), "again").unwrap());
+        assert!(revoked.is_certificate_revoked(&certificate));
+        certificate.serial = 43;
+        assert!(!revoked.is_certificate_revoked(&certificate));
+
+        let fingerprint = ca.public_key().fingerprint();
+        assert!(revoked.revoke(Revocation::Key(fingerprint), "the CA").unwrap());
+        assert!(revoked.is_certificate_revoked(&certificate));
+        assert!(!revoked.is_key_revoked(&key));
+
+        let revoked = RevokedKeys::load(&path).unwrap();
+        assert_eq!(revoked.entries().len(), 2);
+        assert_eq!(revoked.entries()[1].1, "the CA");
+        assert!(revoked.is_key_revoked(&ca.public_key()));
+
+        fs::write(&path, "# comment\n\nserial forty-two\n").unwrap();
+        let error = RevokedKeys::load(&path).unwrap_err();
+        assert_eq!(error.to_string(), "line 3: invalid serial forty-two");
+        fs::write(&path, "ssh-ed25519 AAAA\n").unwrap();
+        assert!(RevokedKeys::load(&path).is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
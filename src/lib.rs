@@ -0,0 +1,22 @@
+//! NSSH : Not Secure SHell.
+//!
+//! A simplified ssh built on home made RSA, AES and SHA256. The crate can be
+//! embedded: [`client::Session`] connects to a server and [`server::Server`]
+//! accepts clients, both talking through an encrypted [`transport::Transport`].
+
+pub mod agent;
+pub mod cidr;
+pub mod client;
+pub mod connection;
+pub mod crypto;
+pub mod der;
+pub mod encoding;
+pub mod keys;
+pub mod server;
+pub mod session;
+pub mod terminal;
+pub mod transport;
+pub mod wire;
+
+/// Address used by the server and the client when none is given
+pub const DEFAULT_ADDR: &str = "127.0.0.1:7878";
@@ -1,19 +1,117 @@
-mod client;
-mod crypto;
-mod serveur;
+mod keygen;
+
+use nssh::client;
+use nssh::server::{Config, Server};
+use nssh::DEFAULT_ADDR;
 use std::env;
+use std::path::Path;
+use std::process;
+
+/// Print an error and quit
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("nssh: {message}");
+    process::exit(1);
+}
+
+/// Run the server, with the configuration file given by `--config <file>`
+fn serve(args: &[String]) {
+    let config = match args {
+        [] => Config::default(),
+        [flag, path] if flag == "--config" => {
+            Config::from_file(path).unwrap_or_else(|e| fail(format!("{path}: {e}")))
+        }
+        _ => fail("usage: nssh --serveur [--config <file>]"),
+    };
+
+    let server = Server::builder()
+        .config(&config)
+        .build()
+        .unwrap_or_else(|e| fail(format!("failed to start the server: {e}")));
+    println!("Server is running on {}", server.local_addr().unwrap());
+    server.run();
+}
+
+/// `host` with the default port if it has none
+fn with_port(host: &str) -> String {
+    let port = DEFAULT_ADDR.rsplit_once(':').unwrap().1;
+    match host.matches(':').count() {
+        0 => format!("{host}:{port}"),
+        // an IPv6 address
+        _ if host.ends_with(']') => format!("{host}:{port}"),
+        1 => host.to_string(),
+        _ if host.starts_with('[') => host.to_string(),
+        _ => format!("[{host}]:{port}"),
+    }
+}
+
+/// Run the client, logging in with the key given by `--identity <file>` and
+/// forwarding the agent with `-A`. The server is `[login@]host[:port]`, and
+/// the words after it are the command to run, whose exit code is ours. The
+/// variables matching `--send-env <pattern>`, `LANG` and `LC_*` by default,
+/// are sent to the server.
+fn connect(args: &[String]) {
+    const USAGE: &str = concat!(
+        "usage: nssh [--client] [--identity <file>] [-A] [--send-env <pattern>]... ",
+        "[[<login>@]<host>[:<port>] [<command>...]]"
+    );
+    let mut identity = None;
+    let mut forward_agent = false;
+    let mut send_env = vec![String::from("LANG"), String::from("LC_*")];
+
+    let mut args = args.iter();
+    let mut destination = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--identity" => identity = Some(Path::new(args.next().unwrap_or_else(|| fail(USAGE)))),
+            "-A" => forward_agent = true,
+            "--send-env" => send_env.push(args.next().unwrap_or_else(|| fail(USAGE)).clone()),
+            _ if arg.starts_with('-') => fail(USAGE),
+            _ => {
+                destination = Some(arg.as_str());
+                break;
+            }
+        }
+    }
+    let command: Vec<&str> = args.map(String::as_str).collect();
+    let command = (!command.is_empty()).then(|| command.join(" "));
+
+    let (login, addr) = match destination {
+        Some(destination) => match destination.split_once('@') {
+            Some((login, host)) => (Some(login), with_port(host)),
+            None => (None, with_port(destination)),
+        },
+        None => (None, DEFAULT_ADDR.to_string()),
+    };
+    let result = client::connect_and_communicate(
+        &addr,
+        login,
+        identity,
+        forward_agent,
+        &send_env,
+        command.as_deref(),
+    );
+    match result {
+        Ok(code) => process::exit(code),
+        Err(e) => fail(e),
+    }
+}
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+
     // launch the server or client
-    if env::args().len() > 1 {
-        match env::args().nth(1).as_deref() {
-            Some("--serveur") => serveur::launch(),
-            Some("--client") => {
-                client::connect_and_communicate().expect("Failed to connect and communicate")
+    if args.len() > 1 {
+        match args[1].as_str() {
+            "--serveur" => serve(&args[2..]),
+            "--client" => connect(&args[2..]),
+            "keygen" => {
+                if let Err(e) = keygen::run(&args[2..]) {
+                    fail(e);
+                }
             }
-            _ => println!("Invalid argument. Use --serveur or --client."),
+            _ => connect(&args[1..]),
         }
     } else {
-        println!("Please provide an argument: --serveur or --client.");
+        println!("Please provide an argument: --serveur, --client, keygen or a host.");
     }
 }
@@ -0,0 +1,268 @@
+//! Unix accounts of the password database.
+//!
+//! A server running as root runs the commands of each user as its account,
+//! the others run them as themselves.
+
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Shell of the users whose account does not say
+const DEFAULT_SHELL: &str = "/bin/sh";
+
+/// The login shells allowed on the system
+const SHELLS_FILE: &str = "/etc/shells";
+
+/// `PATH` of the commands run as an account
+const DEFAULT_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+
+/// Whether the commands run as the account of the users, that is whether
+/// the server runs as root
+pub(crate) fn switches_user() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// Whether `login` can run commands: when the server switches user, it
+/// needs an account with a valid shell
+pub(crate) fn can_log_in(login: &str) -> bool {
+    if !switches_user() {
+        return true;
+    }
+    matches!(Account::by_name(login), Ok(Some(account)) if account.has_valid_shell())
+}
+
+/// An entry of the password database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Account {
+    pub(crate) name: String,
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+    pub(crate) home: PathBuf,
+    pub(crate) shell: PathBuf,
+}
+
+impl Account {
+    /// The account named `name`, `None` if there is none
+    pub(crate) fn by_name(name: &str) -> io::Result<Option<Account>> {
+        let Ok(name) = CString::new(name) else {
+            return Ok(None);
+        };
+        lookup(|(entry, buffer, result)| unsafe {
+            libc::getpwnam_r(
+                name.as_ptr(),
+                entry,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                result,
+            )
+        })
+    }
+
+    /// The account of the user id `uid`, `None` if there is none
+    pub(crate) fn by_uid(uid: u32) -> io::Result<Option<Account>> {
+        lookup(|(entry, buffer, result)| unsafe {
+            libc::getpwuid_r(uid, entry, buffer.as_mut_ptr(), buffer.len(), result)
+        })
+    }
+
+    /// The account the server runs as
+    pub(crate) fn current() -> io::Result<Option<Account>> {
+        Account::by_uid(unsafe { libc::getuid() })
+    }
+
+    /// The groups of the account, its primary group included
+    pub(crate) fn groups(&self) -> io::Result<Vec<libc::gid_t>> {
+        let name = CString::new(self.name.as_str())?;
+        let mut groups = vec![0; 32];
+        loop {
+            let mut count = groups.len() as libc::c_int;
+            let result = unsafe {
+                libc::getgrouplist(name.as_ptr(), self.gid, groups.as_mut_ptr(), &mut count)
+            };
+            // the count is the number of groups needed when they do not fit
+            groups.resize(count as usize, 0);
+            if result != -1 {
+                return Ok(groups);
+            }
+            if groups.len() > 1 << 16 {
+                return Err(io::Error::other("too many groups"));
+            }
+        }
+    }
+
+    /// Whether the shell of the account is an executable listed in
+    /// `/etc/shells`, or only executable if there is no such file
+    pub(crate) fn has_valid_shell(&self) -> bool {
+        is_valid_shell(&self.shell, Path::new(SHELLS_FILE))
+    }
+
+    /// Make `command` run as the account: with its user and groups, in its
+    /// home directory (`/` if it does not exist) and with only `HOME`,
+    /// `USER`, `LOGNAME`, `SHELL` and [`DEFAULT_PATH`] in its environment,
+    /// nothing of the one of the server
+    pub(crate) fn apply(&self, command: &mut Command) -> io::Result<()> {
+        let groups = self.groups()?;
+        let (uid, gid) = (self.uid, self.gid);
+        let home = match self.home.is_dir() {
+            true => self.home.as_path(),
+            false => Path::new("/"),
+        };
+        command
+            .current_dir(home)
+            .env_clear()
+            .env("PATH", DEFAULT_PATH)
+            .env("HOME", &self.home)
+            .env("USER", &self.name)
+            .env("LOGNAME", &self.name)
+            .env("SHELL", &self.shell);
+        // SAFETY: only async-signal-safe calls between fork and exec, the
+        // groups first as they can not be changed anymore after the user
+        unsafe {
+            command.pre_exec(move || {
+                if libc::setgroups(groups.len(), groups.as_ptr()) != 0
+                    || libc::setgid(gid) != 0
+                    || libc::setuid(uid) != 0
+                {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Whether `shell` is executable and listed in the file `shells`, if it
+/// exists
+fn is_valid_shell(shell: &Path, shells: &Path) -> bool {
+    let executable = fs::metadata(shell)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0);
+    let listed = match fs::read_to_string(shells) {
+        Ok(text) => text
+            .lines()
+            .map(str::trim)
+            .any(|line| !line.starts_with('#') && Path::new(line) == shell),
+        Err(_) => true,
+    };
+    executable && listed
+}
+
+/// Arguments of the `getpw*_r` functions: the entry, the buffer for its
+/// strings and where to put the result
+type Lookup<'a> = (
+    *mut libc::passwd,
+    &'a mut [libc::c_char],
+    *mut *mut libc::passwd,
+);
+
+/// Call a `getpw*_r` function with a buffer big enough for the entry
+fn lookup(mut get: impl FnMut(Lookup) -> libc::c_int) -> io::Result<Option<Account>> {
+    let mut buffer = vec![0; 1024];
+    loop {
+        // SAFETY: passwd is plain data filled by the lookup
+        let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        match get((&mut entry, &mut buffer, &mut result)) {
+            0 if result.is_null() => return Ok(None),
+            // SAFETY: the strings point to the buffer, still alive here
+            0 => return Ok(Some(unsafe { Account::from_entry(&entry) })),
+            libc::ERANGE if buffer.len() < 1 << 20 => buffer.resize(buffer.len() * 2, 0),
+            error => return Err(io::Error::from_raw_os_error(error)),
+        }
+    }
+}
+
+impl Account {
+    /// # Safety
+    /// The strings of `entry` must be valid
+    unsafe fn from_entry(entry: &libc::passwd) -> Account {
+        let string = |pointer: *const libc::c_char| match pointer.is_null() {
+            true => String::new(),
+            false => CStr::from_ptr(pointer).to_string_lossy().into_owned(),
+        };
+        let shell = string(entry.pw_shell);
+        Account {
+            name: string(entry.pw_name),
+            uid: entry.pw_uid,
+            gid: entry.pw_gid,
+            home: PathBuf::from(string(entry.pw_dir)),
+            shell: PathBuf::from(match shell.is_empty() {
+                true => DEFAULT_SHELL.to_string(),
+                false => shell,
+            }),
+        }
+    }
+}
+
+/// The shell of `login`, the one of the server's account if `login` has
+/// none, `/bin/sh` if nobody has one
+pub(crate) fn login_shell(login: &str) -> PathBuf {
+    let account = match Account::by_name(login) {
+        Ok(Some(account)) => Some(account),
+        _ => Account::current().ok().flatten(),
+    };
+    account.map_or_else(|| PathBuf::from(DEFAULT_SHELL), |account| account.shell)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn lookup_accounts() {
+        let current = Account::current().unwrap().unwrap();
+        assert_eq!(current.uid, unsafe { libc::getuid() });
+        assert_eq!(
+            Account::by_name(&current.name).unwrap(),
+            Some(current.clone())
+        );
+        assert_eq!(login_shell(&current.name), current.shell);
+
+        assert_eq!(Account::by_name("no such user").unwrap(), None);
+        assert_eq!(Account::by_name("nul\0byte").unwrap(), None);
+        assert_eq!(login_shell("no such user"), current.shell);
+
+        let groups = current.groups().unwrap();
+        assert!(groups.contains(&current.gid));
+    }
+
+    #[test]
+    fn valid_shells() {
+        let path = env::temp_dir().join(format!("nssh-shells-{}", std::process::id()));
+        fs::write(&path, "# login shells\n/bin/sh\n/no/such/shell\n").unwrap();
+
+        assert!(is_valid_shell(Path::new("/bin/sh"), &path));
+        // not listed, or not there
+        assert!(!is_valid_shell(Path::new("/bin/false"), &path));
+        assert!(!is_valid_shell(Path::new("/no/such/shell"), &path));
+        assert!(!is_valid_shell(Path::new("/etc"), &path));
+
+        fs::remove_file(&path).unwrap();
+        assert!(is_valid_shell(Path::new("/bin/false"), &path));
+    }
+
+    // only root can take the groups of an account, even its own
+    #[test]
+    #[ignore = "needs root"]
+    fn environment_of_the_account() {
+        let current = Account::current().unwrap().unwrap();
+        let mut command = Command::new("env");
+        command.env("NSSH_SECRET", "server only");
+        current.apply(&mut command).unwrap();
+        let output = command.output().unwrap();
+        assert!(output.status.success());
+
+        let mut names: Vec<_> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .filter_map(|line| line.split_once('=').map(|(name, _)| name.to_string()))
+            .collect();
+        names.sort();
+        assert_eq!(names, ["HOME", "LOGNAME", "PATH", "SHELL", "USER"]);
+    }
+}
@@ -0,0 +1,95 @@
+//! Agent forwarding: a Unix socket per connection whose clients are each
+//! given a channel to the agent of the client.
+
+use super::account::Account;
+use crate::connection::Connection;
+use crate::transport::AGENT_CHANNEL;
+use std::env;
+use std::fs;
+use std::io;
+use std::net::Shutdown;
+use std::os::unix;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// The socket of a connection, removed when dropped
+pub(crate) struct AgentForwarding {
+    socket: PathBuf,
+    closed: Arc<AtomicBool>,
+}
+
+impl AgentForwarding {
+    /// Listen on a new socket, in a directory only the server, or `owner`
+    /// if given, can open. Each of its clients talks to the agent through a
+    /// new channel of `connection`.
+    pub(crate) fn start(connection: Connection, owner: Option<&Account>) -> io::Result<Self> {
+        let dir = env::temp_dir().join(format!("nssh-{:016x}", rand::random::<u64>()));
+        fs::DirBuilder::new().mode(0o700).create(&dir)?;
+        let socket = dir.join(format!("agent.{}", std::process::id()));
+        let listener = UnixListener::bind(&socket)?;
+        fs::set_permissions(&socket, fs::Permissions::from_mode(0o600))?;
+        if let Some(owner) = owner {
+            for path in [&dir, &socket] {
+                unix::fs::chown(path, Some(owner.uid), Some(owner.gid))?;
+            }
+        }
+
+        let closed = Arc::new(AtomicBool::new(false));
+        let stop = Arc::clone(&closed);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                let Ok(stream) = stream else { continue };
+                let connection = connection.clone();
+                thread::spawn(move || relay(stream, connection));
+            }
+        });
+        Ok(AgentForwarding { socket, closed })
+    }
+
+    /// Path of the socket, for `NSSH_AUTH_SOCK`
+    pub(crate) fn socket(&self) -> &Path {
+        &self.socket
+    }
+}
+
+impl Drop for AgentForwarding {
+    fn drop(&mut self) {
+        // wake the thread waiting for clients so that it sees it must stop
+        self.closed.store(true, Ordering::SeqCst);
+        let _ = UnixStream::connect(&self.socket);
+        let _ = fs::remove_file(&self.socket);
+        if let Some(dir) = self.socket.parent() {
+            let _ = fs::remove_dir(dir);
+        }
+    }
+}
+
+/// Pass the bytes of a local client to a new agent channel and back, until
+/// one of them is done
+fn relay(stream: UnixStream, connection: Connection) {
+    let Ok(mut channel) = connection.open(AGENT_CHANNEL, &[]) else {
+        return;
+    };
+    let Ok(mut reader) = stream.try_clone() else {
+        let _ = channel.close();
+        return;
+    };
+    let mut sender = channel.clone();
+    let requests = thread::spawn(move || {
+        let _ = io::copy(&mut reader, &mut sender);
+        let _ = sender.eof();
+    });
+
+    let mut writer = stream;
+    let _ = io::copy(&mut channel, &mut writer);
+    let _ = writer.shutdown(Shutdown::Both);
+    let _ = channel.close();
+    let _ = requests.join();
+}
@@ -0,0 +1,838 @@
+//! How the server checks who a client is.
+
+use crate::cidr::Cidr;
+use crate::crypto::constant_time_eq;
+use crate::crypto::pbkdf2::pbkdf2_hmac_sha256;
+use crate::crypto::totp;
+use crate::encoding::{from_base32, from_hex, to_base32, to_hex};
+use crate::keys::{unix_time, Certificate, CertificateType, PublicKey};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// One question of a keyboard-interactive authentication
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prompt {
+    pub text: String,
+    /// Whether the answer can be shown while the user types it
+    pub echo: bool,
+}
+
+/// Asks questions to the user being authenticated and returns the answers,
+/// one for each prompt.
+pub trait Conversation {
+    fn ask(&mut self, prompts: &[Prompt]) -> io::Result<Vec<String>>;
+}
+
+/// A source of user credentials.
+///
+/// Every method refuses by default, a backend only implements the methods it
+/// knows how to check.
+pub trait Authenticator: Send + Sync {
+    /// Whether `password` is the password of `user`
+    fn password(&self, _user: &str, _password: &str) -> bool {
+        false
+    }
+
+    /// Whether `key` is allowed to log in as `user`
+    fn public_key(&self, _user: &str, _key: &PublicKey) -> bool {
+        false
+    }
+
+    /// The restrictions of `key` when it logs in as `user`, `None` if it is
+    /// not allowed. By default, none for the keys `public_key` allows.
+    fn public_key_options(&self, user: &str, key: &PublicKey) -> Option<KeyOptions> {
+        self.public_key(user, key).then(KeyOptions::default)
+    }
+
+    /// Whether `certificate` is allowed to log in as `user`
+    fn certificate(&self, _user: &str, _certificate: &Certificate) -> bool {
+        false
+    }
+
+    /// Ask `user` questions through `conversation` and check the answers.
+    ///
+    /// By default the user is asked for their password.
+    fn keyboard_interactive(
+        &self,
+        user: &str,
+        conversation: &mut dyn Conversation,
+    ) -> io::Result<bool> {
+        let prompt = Prompt {
+            text: String::from("Password: "),
+            echo: false,
+        };
+        let answers = conversation.ask(&[prompt])?;
+        Ok(answers.len() == 1 && self.password(user, &answers[0]))
+    }
+}
+
+/// Accepts a user if any of its backends does.
+///
+/// With keyboard-interactive every backend asks its questions in turn, a
+/// question asked again gets the same answer without bothering the user.
+#[derive(Default)]
+pub struct AnyOf(pub Vec<Box<dyn Authenticator>>);
+
+/// Gives the answers already given to the prompts asked again
+struct Remembered<'a> {
+    conversation: &'a mut dyn Conversation,
+    answers: HashMap<String, String>,
+}
+
+impl Conversation for Remembered<'_> {
+    fn ask(&mut self, prompts: &[Prompt]) -> io::Result<Vec<String>> {
+        let known: Option<Vec<String>> = prompts
+            .iter()
+            .map(|prompt| self.answers.get(&prompt.text).cloned())
+            .collect();
+        if let Some(answers) = known {
+            return Ok(answers);
+        }
+        let answers = self.conversation.ask(prompts)?;
+        for (prompt, answer) in prompts.iter().zip(&answers) {
+            self.answers.insert(prompt.text.clone(), answer.clone());
+        }
+        Ok(answers)
+    }
+}
+
+impl Authenticator for AnyOf {
+    fn password(&self, user: &str, password: &str) -> bool {
+        self.0.iter().any(|auth| auth.password(user, password))
+    }
+
+    fn public_key(&self, user: &str, key: &PublicKey) -> bool {
+        self.0.iter().any(|auth| auth.public_key(user, key))
+    }
+
+    fn public_key_options(&self, user: &str, key: &PublicKey) -> Option<KeyOptions> {
+        self.0
+            .iter()
+            .find_map(|auth| auth.public_key_options(user, key))
+    }
+
+    fn certificate(&self, user: &str, certificate: &Certificate) -> bool {
+        self.0.iter().any(|auth| auth.certificate(user, certificate))
+    }
+
+    fn keyboard_interactive(
+        &self,
+        user: &str,
+        conversation: &mut dyn Conversation,
+    ) -> io::Result<bool> {
+        let mut conversation = Remembered {
+            conversation,
+            answers: HashMap::new(),
+        };
+        for auth in &self.0 {
+            if auth.keyboard_interactive(user, &mut conversation)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Logins and passwords kept in memory.
+#[derive(Debug, Default, Clone)]
+pub struct StaticUsers {
+    users: HashMap<String, String>,
+}
+
+impl StaticUsers {
+    pub fn new() -> Self {
+        StaticUsers::default()
+    }
+
+    /// Add or replace the password of `user`
+    pub fn user(mut self, user: impl Into<String>, password: impl Into<String>) -> Self {
+        self.users.insert(user.into(), password.into());
+        self
+    }
+}
+
+impl Authenticator for StaticUsers {
+    fn password(&self, user: &str, password: &str) -> bool {
+        self.users.get(user).is_some_and(|p| p == password)
+    }
+}
+
+/// Number of PBKDF2 iterations used for new passwords
+pub const DEFAULT_ITERATIONS: u32 = 100_000;
+
+/// A password as stored in a [`PasswdFile`], the PBKDF2-HMAC-SHA256 of the
+/// password with a random salt. Written `pbkdf2-sha256:<iterations>:<salt>:<hash>`
+/// with the salt and the hash in hexadecimal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordHash {
+    pub iterations: u32,
+    pub salt: Vec<u8>,
+    pub hash: Vec<u8>,
+}
+
+impl PasswordHash {
+    /// Hash `password` with a new random salt
+    pub fn new(password: &str, iterations: u32) -> Self {
+        let salt: [u8; 16] = rand::random();
+        let hash = pbkdf2_hmac_sha256(password.as_bytes(), &salt, iterations, 32);
+        PasswordHash {
+            iterations,
+            salt: salt.to_vec(),
+            hash,
+        }
+    }
+
+    /// Whether `password` is the hashed password, compared in constant time
+    pub fn verify(&self, password: &str) -> bool {
+        let hash = pbkdf2_hmac_sha256(
+            password.as_bytes(),
+            &self.salt,
+            self.iterations,
+            self.hash.len(),
+        );
+        constant_time_eq(&hash, &self.hash)
+    }
+}
+
+impl FromStr for PasswordHash {
+    type Err = io::Error;
+
+    fn from_str(text: &str) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid password hash");
+
+        let mut fields = text.split(':');
+        if fields.next() != Some("pbkdf2-sha256") {
+            return Err(invalid());
+        }
+        let iterations = fields.next().and_then(|i| i.parse().ok());
+        let salt = fields.next().and_then(from_hex);
+        let hash = fields.next().and_then(from_hex);
+        match (iterations, salt, hash, fields.next()) {
+            (Some(iterations), Some(salt), Some(hash), None)
+                if iterations > 0 && !hash.is_empty() =>
+            {
+                Ok(PasswordHash {
+                    iterations,
+                    salt,
+                    hash,
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for PasswordHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "pbkdf2-sha256:{}:{}:{}",
+            self.iterations,
+            to_hex(&self.salt),
+            to_hex(&self.hash)
+        )
+    }
+}
+
+/// Whether `line` of a [`PasswdFile`] is the one of `user`
+fn is_entry_of(line: &str, user: &str) -> bool {
+    line.trim()
+        .split_once(':')
+        .is_some_and(|(login, _)| login == user)
+}
+
+/// A file of password hashes, one `login:<hash>` line per user where the
+/// hash is a [`PasswordHash`].
+///
+/// The file is read again at every attempt so that changes apply at once.
+#[derive(Debug, Clone)]
+pub struct PasswdFile {
+    path: PathBuf,
+}
+
+impl PasswdFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        PasswdFile { path: path.into() }
+    }
+
+    /// Hash stored for `user`
+    pub fn lookup(&self, user: &str) -> Option<PasswordHash> {
+        self.entries()
+            .into_iter()
+            .find(|(login, _)| login == user)
+            .map(|(_, hash)| hash)
+    }
+
+    /// Logins and hashes of the file, the lines that can not be parsed are
+    /// skipped
+    fn entries(&self) -> Vec<(String, PasswordHash)> {
+        let content = fs::read_to_string(&self.path).unwrap_or_default();
+        content
+            .lines()
+            .filter_map(|line| {
+                let (login, hash) = line.trim().split_once(':')?;
+                Some((login.to_string(), hash.parse().ok()?))
+            })
+            .collect()
+    }
+
+    /// Give `password` to `user`, adding the user if needed
+    pub fn set_password(&self, user: &str, password: &str, iterations: u32) -> io::Result<()> {
+        let hash = PasswordHash::new(password, iterations);
+        set_entry(&self.path, user, &hash.to_string())
+    }
+
+    /// Remove `user` from the file, false if it was not there
+    pub fn remove_user(&self, user: &str) -> io::Result<bool> {
+        remove_entry(&self.path, user)
+    }
+}
+
+/// Lines of a file of `login:<value>` entries, none if it does not exist yet
+fn read_lines(path: &Path) -> io::Result<Vec<String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(content.lines().map(String::from).collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Replace the content of a file, readable only by its owner
+fn write_lines(path: &Path, lines: &[String]) -> io::Result<()> {
+    let mut tmp_path = path.to_path_buf().into_os_string();
+    tmp_path.push(".tmp");
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&tmp_path)?;
+    for line in lines {
+        writeln!(file, "{line}")?;
+    }
+    file.sync_all()?;
+    fs::rename(tmp_path, path)
+}
+
+/// Write the `login:<value>` entry of `user`, replacing the one it had
+fn set_entry(path: &Path, user: &str, value: &str) -> io::Result<()> {
+    if user.is_empty() || user.contains([':', '\n', '\r']) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid login"));
+    }
+    let mut lines = read_lines(path)?;
+    lines.retain(|line| !is_entry_of(line, user));
+    lines.push(format!("{user}:{value}"));
+    write_lines(path, &lines)
+}
+
+/// Remove the entry of `user`, false if it had none
+fn remove_entry(path: &Path, user: &str) -> io::Result<bool> {
+    let mut lines = read_lines(path)?;
+    let before = lines.len();
+    lines.retain(|line| !is_entry_of(line, user));
+    if lines.len() == before {
+        return Ok(false);
+    }
+    write_lines(path, &lines)?;
+    Ok(true)
+}
+
+impl Authenticator for PasswdFile {
+    fn password(&self, user: &str, password: &str) -> bool {
+        let entries = self.entries();
+        let (iterations, accepted) = match entries.iter().find(|(login, _)| login == user) {
+            Some((_, hash)) => (hash.iterations, hash.verify(password)),
+            None => (0, false),
+        };
+
+        // every attempt costs as much as the slowest hash of the file, so
+        // that the time taken does not tell which logins exist
+        let cost = entries
+            .iter()
+            .map(|(_, hash)| hash.iterations)
+            .max()
+            .unwrap_or(DEFAULT_ITERATIONS);
+        if cost > iterations {
+            let padding = PasswordHash {
+                iterations: cost - iterations,
+                salt: vec![0; 16],
+                hash: vec![0; 32],
+            };
+            padding.verify(password);
+        }
+        accepted
+    }
+}
+
+/// What a key of an authorized_keys file can do, given by a comma separated
+/// list of options before the key:
+///
+/// ```text
+/// from="10.0.0.0/8",command="deploy --site \"www\"",no-pty ssh-ed25519 AAAA...
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyOptions {
+    /// `command="..."`: run instead of what the client asks, whose command
+    /// or subsystem is given to it in `NSSH_ORIGINAL_COMMAND`
+    pub command: Option<String>,
+    /// `from="..."`: networks the key can be used from, see
+    /// [`Cidr::parse_list`], any when `None`
+    pub from: Option<Vec<Cidr>>,
+    /// `no-pty`: no terminal, so no shell either
+    pub no_pty: bool,
+    /// `no-port-forwarding`: no forwarded port, which the server does not
+    /// offer anyway
+    pub no_port_forwarding: bool,
+    /// `no-agent-forwarding`: the agent of the client is not forwarded
+    pub no_agent_forwarding: bool,
+    /// `environment="NAME=value"`: variables set for the commands, whatever
+    /// the `AcceptEnv` of the server, if its `PermitUserEnvironment` allows
+    pub environment: Vec<(String, String)>,
+}
+
+/// Split `text` at the first character outside of double quotes for which
+/// `separator` is true: what is before it and what is after it
+fn split_unquoted(text: &str, separator: impl Fn(char) -> bool) -> (&str, Option<&str>) {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if !quoted && separator(c) => return (&text[..i], Some(&text[i + c.len_utf8()..])),
+            _ => {}
+        }
+    }
+    (text, None)
+}
+
+impl KeyOptions {
+    /// Parse a comma separated list of options, unknown options are errors
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let unquote = |value: &str| {
+            value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .map(|value| value.replace("\\\"", "\""))
+                .ok_or_else(|| invalid(format!("expected a quoted value: {value}")))
+        };
+
+        let mut options = KeyOptions::default();
+        let mut rest = Some(text);
+        while let Some(text) = rest {
+            let (option, next) = split_unquoted(text, |c| c == ',');
+            rest = next;
+            let (name, value) = match option.split_once('=') {
+                Some((name, value)) => (name, Some(unquote(value)?)),
+                None => (option, None),
+            };
+            match (name.to_ascii_lowercase().as_str(), value) {
+                ("command", Some(command)) => options.command = Some(command),
+                ("from", Some(networks)) => options.from = Some(Cidr::parse_list(&networks)?),
+                ("environment", Some(variable)) => {
+                    let (name, value) = variable
+                        .split_once('=')
+                        .ok_or_else(|| invalid(format!("expected NAME=value: {variable}")))?;
+                    options
+                        .environment
+                        .push((name.to_string(), value.to_string()));
+                }
+                ("no-pty", None) => options.no_pty = true,
+                ("no-port-forwarding", None) => options.no_port_forwarding = true,
+                ("no-agent-forwarding", None) => options.no_agent_forwarding = true,
+                _ => return Err(invalid(format!("unknown option {option}"))),
+            }
+        }
+        Ok(options)
+    }
+
+    /// Whether the `from` option, if any, allows `ip`
+    pub fn allows_source(&self, ip: IpAddr) -> bool {
+        self.from
+            .as_ref()
+            .is_none_or(|networks| networks.iter().any(|network| network.contains(ip)))
+    }
+}
+
+/// Files of the public keys allowed to log in, one file per user.
+///
+/// In the path `%u` is replaced by the login. Each line of a file is a public
+/// key as written by [`PublicKey::to_line`], after [`KeyOptions`] if it is
+/// restricted. Empty lines, lines starting with `#` and lines that can not be
+/// parsed are ignored.
+#[derive(Debug, Clone)]
+pub struct AuthorizedKeys {
+    path: String,
+}
+
+impl AuthorizedKeys {
+    pub fn new(path: impl Into<String>) -> Self {
+        AuthorizedKeys { path: path.into() }
+    }
+
+    /// Keys of the authorized_keys file of `user`, with their options
+    fn keys(&self, user: &str) -> Vec<(PublicKey, KeyOptions)> {
+        // a login must not be able to point to another file
+        if user.is_empty() || user.contains(['/', '\0']) || user.starts_with('.') {
+            return Vec::new();
+        }
+        let path = self.path.replace("%u", user);
+        let content = fs::read_to_string(path).unwrap_or_default();
+        content.lines().filter_map(parse_authorized_key).collect()
+    }
+}
+
+/// Parse one line of an authorized_keys file
+fn parse_authorized_key(line: &str) -> Option<(PublicKey, KeyOptions)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    if let Ok((key, _comment)) = PublicKey::parse_line(line) {
+        return Some((key, KeyOptions::default()));
+    }
+    let (options, key) = split_unquoted(line, char::is_whitespace);
+    let options = KeyOptions::parse(options).ok()?;
+    let (key, _comment) = PublicKey::parse_line(key?).ok()?;
+    Some((key, options))
+}
+
+impl Authenticator for AuthorizedKeys {
+    fn public_key(&self, user: &str, key: &PublicKey) -> bool {
+        self.public_key_options(user, key).is_some()
+    }
+
+    fn public_key_options(&self, user: &str, key: &PublicKey) -> Option<KeyOptions> {
+        self.keys(user)
+            .into_iter()
+            .find(|(allowed, _)| allowed == key)
+            .map(|(_, options)| options)
+    }
+}
+
+/// A file of the user certificate authorities trusted, one public key per
+/// line like in authorized_keys.
+///
+/// Any certificate signed by one of them is accepted for the logins it lists
+/// as principals, while it is valid.
+#[derive(Debug, Clone)]
+pub struct TrustedUserCa {
+    path: PathBuf,
+}
+
+impl TrustedUserCa {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        TrustedUserCa { path: path.into() }
+    }
+
+    fn keys(&self) -> Vec<PublicKey> {
+        let content = fs::read_to_string(&self.path).unwrap_or_default();
+        content
+            .lines()
+            .filter_map(parse_authorized_key)
+            .map(|(key, _options)| key)
+            .collect()
+    }
+}
+
+impl Authenticator for TrustedUserCa {
+    fn certificate(&self, user: &str, certificate: &Certificate) -> bool {
+        let trusted = certificate
+            .signature_key()
+            .is_some_and(|ca| self.keys().contains(ca));
+        trusted && certificate.check(CertificateType::User, user).is_ok()
+    }
+}
+
+/// Length of the new TOTP secrets, the one of the SHA1 secrets of most
+/// applications
+const TOTP_SECRET_LEN: usize = 20;
+
+/// A file of the secrets of the time based one time passwords of the users
+/// (see [`totp`]), one `login:<base32 secret>` line per user.
+///
+/// It is meant to be a second factor: its keyboard-interactive
+/// authentication asks for the current code, users without a secret are
+/// refused. The file is read again at every attempt.
+#[derive(Debug, Clone)]
+pub struct TotpFile {
+    path: PathBuf,
+}
+
+impl TotpFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        TotpFile { path: path.into() }
+    }
+
+    /// Secret of `user`
+    pub fn secret(&self, user: &str) -> Option<Vec<u8>> {
+        let content = fs::read_to_string(&self.path).ok()?;
+        let line = content.lines().find(|line| is_entry_of(line, user))?;
+        let (_, secret) = line.trim().split_once(':')?;
+        from_base32(secret).filter(|secret| !secret.is_empty())
+    }
+
+    /// Give a new random secret to `user` and return it
+    pub fn generate(&self, user: &str) -> io::Result<Vec<u8>> {
+        let secret: [u8; TOTP_SECRET_LEN] = rand::random();
+        set_entry(&self.path, user, &to_base32(&secret))?;
+        Ok(secret.to_vec())
+    }
+
+    /// Remove the secret of `user`, false if it had none
+    pub fn remove_user(&self, user: &str) -> io::Result<bool> {
+        remove_entry(&self.path, user)
+    }
+}
+
+impl Authenticator for TotpFile {
+    fn keyboard_interactive(
+        &self,
+        user: &str,
+        conversation: &mut dyn Conversation,
+    ) -> io::Result<bool> {
+        let Some(secret) = self.secret(user) else {
+            return Ok(false);
+        };
+        let prompt = Prompt {
+            text: String::from("Verification code: "),
+            echo: true,
+        };
+        let answers = conversation.ask(&[prompt])?;
+        Ok(answers.len() == 1 && totp::verify(&secret, &answers[0], unix_time()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys;
+    use std::env;
+
+    /// Answers every prompt with the same text
+    struct Answer<'a>(&'a str);
+
+    impl Conversation for Answer<'_> {
+        fn ask(&mut self, prompts: &[Prompt]) -> io::Result<Vec<String>> {
+            Ok(prompts.iter().map(|_| String::from(self.0)).collect())
+        }
+    }
+
+    #[test]
+    fn static_users() {
+        let users = StaticUsers::new().user("admin", "admin");
+        assert!(users.password("admin", "admin"));
+        assert!(!users.password("admin", "root"));
+        assert!(!users.password("root", "admin"));
+        assert!(users
+            .keyboard_interactive("admin", &mut Answer("admin"))
+            .unwrap());
+    }
+
+    #[test]
+    fn password_hash() {
+        let hash = PasswordHash::new("wonderland", 10);
+        assert!(hash.verify("wonderland"));
+        assert!(!hash.verify("Wonderland"));
+
+        let parsed: PasswordHash = hash.to_string().parse().unwrap();
+        assert_eq!(parsed, hash);
+        assert!("sha256:10:00:00".parse::<PasswordHash>().is_err());
+        assert!("pbkdf2-sha256:0:00:00".parse::<PasswordHash>().is_err());
+        assert!("pbkdf2-sha256:10:00".parse::<PasswordHash>().is_err());
+    }
+
+    #[test]
+    fn passwd_file() {
+        let path = env::temp_dir().join(format!("nssh-passwd-{}", std::process::id()));
+        let passwd = PasswdFile::new(&path);
+        passwd.set_password("alice", "wonderland", 10).unwrap();
+        passwd.set_password("bob", "builder", 10).unwrap();
+
+        assert!(passwd.password("alice", "wonderland"));
+        assert!(passwd.password("bob", "builder"));
+        assert!(!passwd.password("alice", "builder"));
+        assert!(!passwd.password("carol", "wonderland"));
+
+        passwd.set_password("alice", "looking-glass", 10).unwrap();
+        assert!(!passwd.password("alice", "wonderland"));
+        assert!(passwd.password("alice", "looking-glass"));
+
+        // the checks of cheaper hashes are padded to the cost of this one
+        passwd.set_password("carol", "tea party", 30).unwrap();
+        assert!(passwd.password("carol", "tea party"));
+        assert!(passwd.password("alice", "looking-glass"));
+        assert!(!passwd.password("dave", "looking-glass"));
+
+        assert!(passwd.remove_user("bob").unwrap());
+        assert!(!passwd.remove_user("bob").unwrap());
+        assert!(!passwd.password("bob", "builder"));
+        assert!(passwd.set_password("eve:x", "pass", 10).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn totp_file() {
+        let path = env::temp_dir().join(format!("nssh-totp-{}", std::process::id()));
+        let totp_file = TotpFile::new(&path);
+        let secret = totp_file.generate("alice").unwrap();
+        assert_eq!(secret.len(), TOTP_SECRET_LEN);
+        assert_eq!(totp_file.secret("alice"), Some(secret.clone()));
+
+        let code = format!("{:06}", totp::totp(&secret, unix_time()));
+        assert!(totp_file
+            .keyboard_interactive("alice", &mut Answer(&code))
+            .unwrap());
+        assert!(!totp_file
+            .keyboard_interactive("bob", &mut Answer(&code))
+            .unwrap());
+        assert!(!totp_file
+            .keyboard_interactive("alice", &mut Answer("000000x"))
+            .unwrap());
+
+        assert!(totp_file.remove_user("alice").unwrap());
+        assert_eq!(totp_file.secret("alice"), None);
+        fs::remove_file(path).unwrap();
+    }
+
+    /// Answers with the code of the prompts asking for one, and counts the
+    /// prompts
+    struct Codes<'a> {
+        password: &'a str,
+        code: &'a str,
+        asked: Vec<String>,
+    }
+
+    impl Conversation for Codes<'_> {
+        fn ask(&mut self, prompts: &[Prompt]) -> io::Result<Vec<String>> {
+            self.asked
+                .extend(prompts.iter().map(|prompt| prompt.text.clone()));
+            Ok(prompts
+                .iter()
+                .map(|prompt| match prompt.text.starts_with("Verification") {
+                    true => self.code.to_string(),
+                    false => self.password.to_string(),
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn any_of_keyboard_interactive() {
+        let path = env::temp_dir().join(format!("nssh-totp-any-{}", std::process::id()));
+        let totp_file = TotpFile::new(&path);
+        let secret = totp_file.generate("alice").unwrap();
+        let code = format!("{:06}", totp::totp(&secret, unix_time()));
+        let any = AnyOf(vec![
+            Box::new(StaticUsers::new().user("bob", "builder")),
+            Box::new(StaticUsers::new().user("carol", "tea")),
+            Box::new(TotpFile::new(&path)),
+        ]);
+
+        let mut answers = Codes {
+            password: "",
+            code: &code,
+            asked: Vec::new(),
+        };
+        assert!(any.keyboard_interactive("alice", &mut answers).unwrap());
+        // the password is asked once for both static backends
+        assert_eq!(answers.asked, ["Password: ", "Verification code: "]);
+
+        let mut answers = Codes {
+            password: "tea",
+            code: "000000",
+            asked: Vec::new(),
+        };
+        assert!(any.keyboard_interactive("carol", &mut answers).unwrap());
+        assert!(!any.keyboard_interactive("alice", &mut answers).unwrap());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn authorized_key_lines() {
+        let key = keys::test_ed25519_key().public_key();
+        assert_eq!(
+            parse_authorized_key(&key.to_line("léa@laptop")),
+            Some((key.clone(), KeyOptions::default()))
+        );
+        assert_eq!(parse_authorized_key(&format!("# {}", key.to_line(""))), None);
+        assert_eq!(parse_authorized_key("rsa 2436929723 5"), None);
+        assert_eq!(parse_authorized_key(""), None);
+    }
+
+    #[test]
+    fn authorized_key_options() {
+        let key = keys::test_ed25519_key().public_key();
+        let line = format!(
+            "from=\"10.0.0.0/8,::1\",command=\"echo \\\"a, b\\\"\",no-pty,no-port-forwarding,\
+             environment=\"STAGE=prod\" {}",
+            key.to_line("deploy")
+        );
+        let (parsed, options) = parse_authorized_key(&line).unwrap();
+        assert_eq!(parsed, key);
+        assert_eq!(options.command.as_deref(), Some("echo \"a, b\""));
+        assert!(options.no_pty && options.no_port_forwarding);
+        assert!(!options.no_agent_forwarding);
+        assert_eq!(
+            options.environment,
+            [(String::from("STAGE"), String::from("prod"))]
+        );
+        assert!(options.allows_source("10.1.2.3".parse().unwrap()));
+        assert!(options.allows_source("::1".parse().unwrap()));
+        assert!(!options.allows_source("192.168.1.1".parse().unwrap()));
+        assert!(KeyOptions::default().allows_source("192.168.1.1".parse().unwrap()));
+
+        // a key with an option it does not know is not allowed at all
+        assert_eq!(parse_authorized_key(&format!("restrict {}", key.to_line(""))), None);
+        assert_eq!(parse_authorized_key(&format!("from=10.0.0.1 {}", key.to_line(""))), None);
+        assert_eq!(parse_authorized_key("no-pty"), None);
+    }
+
+    #[test]
+    fn authorized_keys_per_user() {
+        let dir = env::temp_dir().join(format!("nssh-keys-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let key = keys::test_ed25519_key().public_key();
+        let other = keys::test_rsa_key().public_key();
+        fs::write(dir.join("alice"), format!("# alice\n{}\n", key.to_line(""))).unwrap();
+
+        let keys = AuthorizedKeys::new(format!("{}/%u", dir.display()));
+        assert!(keys.public_key("alice", &key));
+        assert!(!keys.public_key("alice", &other));
+        assert!(!keys.public_key("bob", &key));
+        assert!(!keys.public_key("../alice", &key));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn trusted_user_ca() {
+        let path = env::temp_dir().join(format!("nssh-user-ca-{}", std::process::id()));
+        let ca = keys::test_ed25519_key();
+        fs::write(&path, ca.public_key().to_line("ca") + "\n").unwrap();
+
+        let mut certificate =
+            Certificate::new(keys::test_rsa_key().public_key(), CertificateType::User);
+        certificate.principals = vec![String::from("alice")];
+        certificate.sign(&ca).unwrap();
+
+        let trusted = TrustedUserCa::new(&path);
+        assert!(trusted.certificate("alice", &certificate));
+        assert!(!trusted.certificate("bob", &certificate));
+        assert!(!trusted.public_key("alice", &certificate.key));
+
+        // signed by itself, not by the CA
+        let other = keys::test_rsa_key();
+        certificate.sign(&other).unwrap();
+        assert!(!trusted.certificate("alice", &certificate));
+        fs::remove_file(path).unwrap();
+    }
+}
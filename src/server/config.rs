@@ -0,0 +1,326 @@
+//! Server configuration file.
+//!
+//! One setting per line, a keyword followed by its value. Keywords are not
+//! case sensitive, empty lines and lines starting with `#` are ignored.
+//!
+//! ```text
+//! ListenAddress 0.0.0.0:7878
+//! # backends tried in turn to authenticate a user
+//! PasswordFile /etc/nssh/passwd
+//! User guest guest
+//! AuthorizedKeysFile /home/%u/.nssh/authorized_keys
+//! TrustedUserCAKeys /etc/nssh/user_ca.pub
+//! RevokedKeys /etc/nssh/revoked_keys
+//! # codes asked after the password or the key
+//! TotpFile /etc/nssh/totp
+//! # the key of the server and its certificate signed by a host CA
+//! HostKey /etc/nssh/host_ed25519
+//! HostCertificate /etc/nssh/host_ed25519-cert.pub
+//! AllowAgentForwarding no
+//! # 3 tries per connection, 10 failures of a login or an address ban it
+//! # for 5 minutes
+//! MaxAuthTries 3
+//! MaxFailures 10
+//! BanTime 300
+//! # environment variables the clients can set for their commands
+//! AcceptEnv LANG LC_*
+//! # apply the environment="..." options of the authorized keys
+//! PermitUserEnvironment yes
+//! ```
+
+use super::auth::{
+    AnyOf, Authenticator, AuthorizedKeys, PasswdFile, StaticUsers, TotpFile, TrustedUserCa,
+};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Password file used when the configuration does not give one
+pub const DEFAULT_PASSWORD_FILE: &str = "/etc/nssh/passwd";
+
+/// Settings of a server read from a configuration file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// `ListenAddress`: address to listen on
+    pub listen_address: String,
+    /// `User <login> <password>`: users checked against a static map
+    pub users: Vec<(String, String)>,
+    /// `PasswordFile`: file of password hashes, see [`PasswdFile`],
+    /// [`DEFAULT_PASSWORD_FILE`] by default and `none` to disable it
+    pub password_file: Option<PathBuf>,
+    /// `AuthorizedKeysFile`: per user public key files, see [`AuthorizedKeys`]
+    pub authorized_keys_file: Option<String>,
+    /// `TrustedUserCAKeys`: keys of the CAs signing user certificates, see
+    /// [`TrustedUserCa`]
+    pub trusted_user_ca_keys: Option<PathBuf>,
+    /// `RevokedKeys`: keys and certificates refused, see
+    /// [`RevokedKeys`](crate::keys::RevokedKeys)
+    pub revoked_keys: Option<PathBuf>,
+    /// `TotpFile`: secrets of the codes asked as a second factor, see
+    /// [`TotpFile`]
+    pub totp_file: Option<PathBuf>,
+    /// `HostKey`: private key identifying the server, a temporary one is
+    /// made at start when not given
+    pub host_key: Option<PathBuf>,
+    /// `HostCertificate`: certificate of the host key
+    pub host_certificate: Option<PathBuf>,
+    /// `AllowAgentForwarding yes|no`: whether clients can forward their
+    /// agent, yes by default
+    pub allow_agent_forwarding: bool,
+    /// `MaxAuthTries`: authentications a client can try before the
+    /// connection is closed, 6 by default
+    pub max_auth_tries: u32,
+    /// `MaxFailures`: failed authentications of a login or an address before
+    /// it is banned, 10 by default
+    pub max_failures: u32,
+    /// `BanTime`: seconds a login or an address stays banned, 10 minutes by
+    /// default
+    pub ban_time: Duration,
+    /// `AcceptEnv`: patterns of the environment variables the clients can
+    /// set, `*` matching any characters and `?` any one. None by default.
+    pub accept_env: Vec<String>,
+    /// `PermitUserEnvironment yes|no`: whether the `environment="..."`
+    /// options of the authorized keys, which their users can edit, are
+    /// applied, no by default
+    pub permit_user_environment: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen_address: String::from(crate::DEFAULT_ADDR),
+            users: Vec::new(),
+            password_file: Some(PathBuf::from(DEFAULT_PASSWORD_FILE)),
+            authorized_keys_file: None,
+            trusted_user_ca_keys: None,
+            revoked_keys: None,
+            totp_file: None,
+            host_key: None,
+            host_certificate: None,
+            allow_agent_forwarding: true,
+            max_auth_tries: 6,
+            max_failures: 10,
+            ban_time: Duration::from_secs(600),
+            accept_env: Vec::new(),
+            permit_user_environment: false,
+        }
+    }
+}
+
+/// Error for a bad line of the configuration
+fn invalid(line_number: usize, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {}: {}", line_number, message),
+    )
+}
+
+fn parse_yes_no(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// A number above 0
+fn parse_count(value: &str) -> Option<u32> {
+    value.parse().ok().filter(|&count| count > 0)
+}
+
+impl Config {
+    /// Read the configuration file at `path`
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Config::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parse the content of a configuration file
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut config = Config::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (keyword, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            if value.is_empty() {
+                return Err(invalid(line_number, "missing value"));
+            }
+
+            match keyword.to_ascii_lowercase().as_str() {
+                "listenaddress" => config.listen_address = value.to_string(),
+                "user" => {
+                    let (login, password) = value
+                        .split_once(char::is_whitespace)
+                        .ok_or_else(|| invalid(line_number, "expected User <login> <password>"))?;
+                    config
+                        .users
+                        .push((login.to_string(), password.trim().to_string()));
+                }
+                "passwordfile" if value == "none" => config.password_file = None,
+                "passwordfile" => config.password_file = Some(PathBuf::from(value)),
+                "authorizedkeysfile" => config.authorized_keys_file = Some(value.to_string()),
+                "trustedusercakeys" => config.trusted_user_ca_keys = Some(PathBuf::from(value)),
+                "revokedkeys" => config.revoked_keys = Some(PathBuf::from(value)),
+                "totpfile" => config.totp_file = Some(PathBuf::from(value)),
+                "hostkey" => config.host_key = Some(PathBuf::from(value)),
+                "hostcertificate" => config.host_certificate = Some(PathBuf::from(value)),
+                "allowagentforwarding" => {
+                    config.allow_agent_forwarding = parse_yes_no(value)
+                        .ok_or_else(|| invalid(line_number, "expected yes or no"))?
+                }
+                "maxauthtries" => {
+                    config.max_auth_tries = parse_count(value)
+                        .ok_or_else(|| invalid(line_number, "expected a number above 0"))?
+                }
+                "maxfailures" => {
+                    config.max_failures = parse_count(value)
+                        .ok_or_else(|| invalid(line_number, "expected a number above 0"))?
+                }
+                "bantime" => {
+                    let seconds = value
+                        .parse()
+                        .map_err(|_| invalid(line_number, "expected seconds"))?;
+                    config.ban_time = Duration::from_secs(seconds)
+                }
+                "acceptenv" => config
+                    .accept_env
+                    .extend(value.split_whitespace().map(str::to_string)),
+                "permituserenvironment" => {
+                    config.permit_user_environment = parse_yes_no(value)
+                        .ok_or_else(|| invalid(line_number, "expected yes or no"))?
+                }
+                _ => return Err(invalid(line_number, &format!("unknown keyword {keyword}"))),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// The authentication backends enabled by the configuration
+    pub fn authenticator(&self) -> Box<dyn Authenticator> {
+        let mut backends: Vec<Box<dyn Authenticator>> = Vec::new();
+
+        if !self.users.is_empty() {
+            let users = self
+                .users
+                .iter()
+                .fold(StaticUsers::new(), |users, (login, password)| {
+                    users.user(login, password)
+                });
+            backends.push(Box::new(users));
+        }
+        if let Some(path) = &self.password_file {
+            backends.push(Box::new(PasswdFile::new(path)));
+        }
+        if let Some(path) = &self.authorized_keys_file {
+            backends.push(Box::new(AuthorizedKeys::new(path)));
+        }
+        if let Some(path) = &self.trusted_user_ca_keys {
+            backends.push(Box::new(TrustedUserCa::new(path)));
+        }
+
+        Box::new(AnyOf(backends))
+    }
+
+    /// The second factor asked once the client is authenticated, if any
+    pub fn second_factor(&self) -> Option<Box<dyn Authenticator>> {
+        let path = self.totp_file.as_ref()?;
+        Some(Box::new(TotpFile::new(path)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let config = Config::parse(
+            "# comment\n\
+             ListenAddress 0.0.0.0:22\n\
+             \n\
+             user alice wonder land\n\
+             PasswordFile /etc/nssh/passwd\n\
+             AuthorizedKeysFile /home/%u/.nssh/authorized_keys\n\
+             TrustedUserCAKeys /etc/nssh/user_ca.pub\n\
+             RevokedKeys /etc/nssh/revoked_keys\n\
+             TotpFile /etc/nssh/totp\n\
+             HostKey /etc/nssh/host_ed25519\n\
+             AllowAgentForwarding No\n\
+             MaxAuthTries 3\n\
+             MaxFailures 5\n\
+             BanTime 60\n\
+             AcceptEnv LANG LC_*\n\
+             AcceptEnv CI_*\n\
+             PermitUserEnvironment yes\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.listen_address, "0.0.0.0:22");
+        assert_eq!(
+            config.users,
+            vec![(String::from("alice"), String::from("wonder land"))]
+        );
+        assert_eq!(
+            config.password_file,
+            Some(PathBuf::from("/etc/nssh/passwd"))
+        );
+        assert_eq!(
+            config.authorized_keys_file.as_deref(),
+            Some("/home/%u/.nssh/authorized_keys")
+        );
+        assert_eq!(
+            config.trusted_user_ca_keys,
+            Some(PathBuf::from("/etc/nssh/user_ca.pub"))
+        );
+        assert_eq!(
+            config.revoked_keys,
+            Some(PathBuf::from("/etc/nssh/revoked_keys"))
+        );
+        assert_eq!(config.totp_file, Some(PathBuf::from("/etc/nssh/totp")));
+        assert!(config.second_factor().is_some());
+        assert_eq!(config.host_key, Some(PathBuf::from("/etc/nssh/host_ed25519")));
+        assert_eq!(config.host_certificate, None);
+        assert!(!config.allow_agent_forwarding);
+        assert_eq!(config.max_auth_tries, 3);
+        assert_eq!(config.max_failures, 5);
+        assert_eq!(config.ban_time, Duration::from_secs(60));
+        assert_eq!(config.accept_env, ["LANG", "LC_*", "CI_*"]);
+        assert!(config.permit_user_environment);
+        assert!(config.authenticator().password("alice", "wonder land"));
+        assert!(!config.authenticator().password("admin", "admin"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Config::parse("ListenAddress").is_err());
+        assert!(Config::parse("User alice").is_err());
+        assert!(Config::parse("Port 22").is_err());
+        assert!(Config::parse("AllowAgentForwarding maybe").is_err());
+        assert!(Config::parse("MaxAuthTries 0").is_err());
+        assert!(Config::parse("BanTime soon").is_err());
+        assert!(Config::parse("PermitUserEnvironment sometimes").is_err());
+    }
+
+    #[test]
+    fn default_password_file() {
+        let config = Config::default();
+        assert_eq!(
+            config.password_file,
+            Some(PathBuf::from(DEFAULT_PASSWORD_FILE))
+        );
+        assert!(!config.authenticator().password("admin", "admin"));
+        assert!(config.allow_agent_forwarding);
+        assert!(!config.permit_user_environment);
+        assert!(config.second_factor().is_none());
+
+        let config = Config::parse("PasswordFile none").unwrap();
+        assert_eq!(config.password_file, None);
+    }
+}
@@ -0,0 +1,386 @@
+//! What the server does with the requests of an authenticated client.
+
+use super::account::{login_shell, switches_user, Account};
+use super::pty::Pty;
+use crate::agent::AUTH_SOCK_ENV;
+use crate::connection::{Channel, ChannelMessage};
+use crate::session::{signal_number, ExitStatus, SIGNAL};
+use crate::terminal::WindowSize;
+use crate::wire::Reader;
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Serves a session channel of a client.
+///
+/// The server creates a handler for every session channel the client opens
+/// and calls it for the command, shell or subsystem the client asks for.
+/// The handler writes the output to the channel and returns how the
+/// command ended, which the server sends to the client before closing the
+/// channel. An error returned by a handler is reported to the client on the
+/// error stream instead.
+pub trait SessionHandler: Send {
+    /// The client is logged in as `login`, called before anything else
+    fn on_login(&mut self, _login: &str) {}
+
+    /// The client wants the command or the shell to run on a terminal of
+    /// type `term` (its `TERM`) and of `size`, refused unless overridden
+    fn on_pty_request(&mut self, _term: &str, _size: WindowSize) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "terminals are not supported",
+        ))
+    }
+
+    /// The client wants the variable `name` set to `value` for its command,
+    /// refused unless overridden. The server only asks for the variables
+    /// its configuration accepts.
+    fn on_env(&mut self, name: &str, _value: &str) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("can not set {name}"),
+        ))
+    }
+
+    /// The client asked to run `command`
+    fn on_exec(&mut self, command: &str, channel: &mut Channel) -> io::Result<ExitStatus>;
+
+    /// The client asked for a shell, refused unless overridden
+    fn on_shell(&mut self, _channel: &mut Channel) -> io::Result<ExitStatus> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "shell requests are not supported",
+        ))
+    }
+
+    /// The client asked for the subsystem `name`, refused unless overridden
+    fn on_subsystem(&mut self, name: &str, _channel: &mut Channel) -> io::Result<ExitStatus> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("unknown subsystem {name}"),
+        ))
+    }
+
+    /// The client forwards its agent, reachable at `socket` for the rest of
+    /// the connection. Refused unless overridden.
+    fn on_agent_forwarding(&mut self, _socket: &Path) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "agent forwarding is not supported",
+        ))
+    }
+
+    /// The channel is about to be closed
+    fn on_eof(&mut self) {}
+}
+
+/// Default handler, runs every command with `sh -c` and the shell of the
+/// user on a terminal. When the server runs as root, they run as the Unix
+/// account of the user.
+#[derive(Debug, Default, Clone)]
+pub struct ShellHandler {
+    login: String,
+    /// Account the commands run as, when the server runs as root
+    account: Option<Account>,
+    /// Type and size of the terminal the client asked for
+    terminal: Option<(String, WindowSize)>,
+    /// Socket of the forwarded agent, given to the commands
+    agent_socket: Option<PathBuf>,
+    /// Variables set by the client
+    env: Vec<(String, String)>,
+}
+
+impl ShellHandler {
+    /// `program` with the environment of the session, as the account of
+    /// the user if the server switches user
+    fn command(&self, program: impl AsRef<std::ffi::OsStr>) -> io::Result<Command> {
+        let mut command = Command::new(program);
+        if switches_user() {
+            let account = self.account.as_ref().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("no account for {}", self.login),
+                )
+            })?;
+            account.apply(&mut command)?;
+        }
+        command.envs(self.env.iter().map(|(name, value)| (name, value)));
+        if let Some(socket) = &self.agent_socket {
+            command.env(AUTH_SOCK_ENV, socket);
+        }
+        if let Some((term, _)) = &self.terminal {
+            if !term.is_empty() {
+                command.env("TERM", term);
+            }
+        }
+        Ok(command)
+    }
+}
+
+/// Send the signal named in the data of a signal request to the process
+/// group `pid`, false if it is unknown or could not be sent
+fn deliver_signal(pid: u32, data: &[u8]) -> bool {
+    let Some(signal) = Reader::new(data).str().ok().and_then(signal_number) else {
+        return false;
+    };
+    unsafe { libc::kill(-(pid as i32), signal) == 0 }
+}
+
+/// Run `command` and send its output and errors to the channel as they
+/// come. The data of the channel is its input, closed at the end of file.
+fn run(mut command: Command, channel: &mut Channel) -> io::Result<ExitStatus> {
+    // in its own process group, the signals go to all its processes
+    let mut child = command
+        .process_group(0)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let pid = child.id();
+    let mut stdin = child.stdin.take();
+    let mut stdout = child.stdout.take().unwrap();
+    let mut stderr = child.stderr.take().unwrap();
+
+    let mut input = channel.clone();
+    thread::spawn(move || {
+        while let Some(message) = input.recv() {
+            match message {
+                ChannelMessage::Data(data) => {
+                    // the rest is dropped once the command stops reading
+                    if let Some(Err(_)) = stdin.as_mut().map(|pipe| pipe.write_all(&data)) {
+                        stdin = None;
+                    }
+                }
+                ChannelMessage::Eof => stdin = None,
+                ChannelMessage::Request {
+                    kind,
+                    want_reply,
+                    data,
+                } => {
+                    let delivered = kind == SIGNAL && deliver_signal(pid, &data);
+                    if want_reply {
+                        let _ = input.reply(delivered);
+                    }
+                }
+                _ => {}
+            }
+        }
+    });
+
+    let mut errors = channel.clone();
+    let stderr_thread = thread::spawn(move || -> io::Result<()> {
+        let mut buffer = [0; 8192];
+        loop {
+            match stderr.read(&mut buffer)? {
+                0 => return Ok(()),
+                read => errors.write_stderr(&buffer[..read])?,
+            }
+        }
+    });
+    let result = io::copy(&mut stdout, channel);
+    // the client is gone if sending failed, the command gets a broken pipe
+    drop(stdout);
+    let stderr_result = stderr_thread.join().unwrap();
+    let status = child.wait()?;
+    result?;
+    stderr_result?;
+    Ok(status.into())
+}
+
+/// Run `command` on a new terminal of `size`, owned by `owner` if given, and
+/// pass its bytes to the channel both ways until it exits. The terminal is
+/// resized when the client asks.
+fn run_on_pty(
+    command: Command,
+    size: WindowSize,
+    owner: Option<&Account>,
+    channel: &mut Channel,
+) -> io::Result<ExitStatus> {
+    let pty = Pty::open(owner)?;
+    pty.set_size(size)?;
+    let (mut child, mut terminal) = pty.spawn(command)?;
+    let pid = child.id();
+
+    let mut keyboard = terminal.try_clone()?;
+    let mut input = channel.clone();
+    thread::spawn(move || {
+        while let Some(message) = input.recv() {
+            match message {
+                ChannelMessage::Data(data) if keyboard.write_all(&data).is_err() => break,
+                ChannelMessage::Request { kind, data, .. } if kind == "window-change" => {
+                    if let Ok(size) = WindowSize::read(&mut Reader::new(&data)) {
+                        let _ = size.apply(keyboard.as_raw_fd());
+                    }
+                }
+                ChannelMessage::Request {
+                    kind,
+                    want_reply,
+                    data,
+                } => {
+                    let delivered = kind == SIGNAL && deliver_signal(pid, &data);
+                    if want_reply {
+                        let _ = input.reply(delivered);
+                    }
+                }
+                _ => {}
+            }
+        }
+    });
+
+    // reading the terminal fails once the program exited, and writing to
+    // the channel once the client is gone: the program then gets a hang up
+    let _ = io::copy(&mut terminal, channel);
+    drop(terminal);
+    Ok(child.wait()?.into())
+}
+
+impl SessionHandler for ShellHandler {
+    fn on_login(&mut self, login: &str) {
+        self.login = login.to_string();
+        if switches_user() {
+            self.account = Account::by_name(login).ok().flatten();
+        }
+    }
+
+    fn on_pty_request(&mut self, term: &str, size: WindowSize) -> io::Result<()> {
+        self.terminal = Some((term.to_string(), size));
+        Ok(())
+    }
+
+    fn on_env(&mut self, name: &str, value: &str) -> io::Result<()> {
+        self.env.push((name.to_string(), value.to_string()));
+        Ok(())
+    }
+
+    fn on_exec(&mut self, command: &str, channel: &mut Channel) -> io::Result<ExitStatus> {
+        let mut command_line = self.command("sh")?;
+        command_line.arg("-c").arg(command);
+        if let Some((_, size)) = self.terminal {
+            return run_on_pty(command_line, size, self.account.as_ref(), channel);
+        }
+        run(command_line, channel)
+    }
+
+    fn on_shell(&mut self, channel: &mut Channel) -> io::Result<ExitStatus> {
+        let Some((_, size)) = self.terminal else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "a shell needs a terminal",
+            ));
+        };
+        // a dash in front of its name makes it a login shell
+        let shell = match &self.account {
+            Some(account) => account.shell.clone(),
+            None => login_shell(&self.login),
+        };
+        let name = shell.file_name().unwrap_or(shell.as_os_str()).to_string_lossy();
+        let mut command = self.command(&shell)?;
+        command.arg0(format!("-{name}"));
+        run_on_pty(command, size, self.account.as_ref(), channel)
+    }
+
+    fn on_agent_forwarding(&mut self, socket: &Path) -> io::Result<()> {
+        self.agent_socket = Some(socket.to_path_buf());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::{connected_pair, Incoming, EXTENDED_DATA_STDERR, SESSION};
+    use crate::wire::Writer;
+    use std::env;
+    use std::fs;
+
+    /// A session channel of a client, and `command` running on the server
+    /// end of it
+    fn start(command: Command) -> (Channel, thread::JoinHandle<ExitStatus>) {
+        let (client, server) = connected_pair();
+        let running = thread::spawn(move || {
+            let Some(Incoming::Open(open)) = server.next_incoming() else {
+                panic!("expected a channel");
+            };
+            let mut channel = open.accept().unwrap();
+            let status = run(command, &mut channel).unwrap();
+            channel.close().unwrap();
+            status
+        });
+        (client.open(SESSION, &[]).unwrap(), running)
+    }
+
+    fn sh(script: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        command
+    }
+
+    #[test]
+    fn output_is_streamed() {
+        let flag = env::temp_dir().join(format!("nssh-streamed-{}", std::process::id()));
+        let (mut channel, running) = start(sh(&format!(
+            "echo first; echo oops >&2; until [ -e {0} ]; do sleep 0.05; done; echo last",
+            flag.display()
+        )));
+
+        // both streams come while the command still runs
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            received.push(channel.recv().unwrap());
+        }
+        assert!(received.contains(&ChannelMessage::Data(b"first\n".to_vec())));
+        assert!(received.contains(&ChannelMessage::ExtendedData(
+            EXTENDED_DATA_STDERR,
+            b"oops\n".to_vec()
+        )));
+        assert!(!running.is_finished());
+
+        fs::write(&flag, "").unwrap();
+        assert_eq!(
+            channel.recv(),
+            Some(ChannelMessage::Data(b"last\n".to_vec()))
+        );
+        assert_eq!(running.join().unwrap(), ExitStatus::Code(0));
+        fs::remove_file(flag).unwrap();
+    }
+
+    #[test]
+    fn input_until_eof() {
+        let (mut channel, running) = start(sh("wc -c; exit 3"));
+        let input = vec![b'x'; 100_000];
+        channel.write_all(&input).unwrap();
+        channel.eof().unwrap();
+
+        let mut output = Vec::new();
+        channel.read_to_end(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap().trim(), "100000");
+        assert_eq!(running.join().unwrap(), ExitStatus::Code(3));
+    }
+
+    #[test]
+    fn signals() {
+        let signal = |name: &str| {
+            let mut data = Writer::new();
+            data.string(name);
+            data.into_bytes()
+        };
+        // the signal goes to the whole group, the sleep included
+        let (mut channel, running) = start(sh("sleep 30; echo survived"));
+        assert!(!channel.request(SIGNAL, true, &signal("NOPE")).unwrap());
+        assert!(channel.request(SIGNAL, true, &signal("TERM")).unwrap());
+
+        let mut output = Vec::new();
+        channel.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"");
+        assert_eq!(
+            running.join().unwrap(),
+            ExitStatus::Signal {
+                name: String::from("TERM"),
+                core_dumped: false,
+            }
+        );
+    }
+}
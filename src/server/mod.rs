@@ -0,0 +1,847 @@
+//! NSSH server.
+
+mod account;
+mod agent_forwarding;
+mod auth;
+mod config;
+mod handler;
+mod pty;
+mod throttle;
+
+pub use auth::{
+    AnyOf, Authenticator, AuthorizedKeys, Conversation, KeyOptions, PasswdFile, PasswordHash,
+    Prompt, StaticUsers, TotpFile, TrustedUserCa, DEFAULT_ITERATIONS,
+};
+pub use config::{Config, DEFAULT_PASSWORD_FILE};
+pub use handler::{SessionHandler, ShellHandler};
+
+use crate::connection::{Channel, ChannelMessage, Connection, Incoming, SESSION};
+use crate::encoding::{from_hex, to_hex};
+use crate::keys::{Certificate, CertificateType, PrivateKey, PublicKey, RevokedKeys};
+use crate::transport::{
+    hostkey_auth_data, publickey_auth_data, Transport, AGENT_FORWARDING_REQUEST, INFO_REQUEST,
+    INFO_RESPONSE, KEYBOARD_INTERACTIVE, TOO_MANY_FAILURES,
+};
+use crate::session::{env_matches, ENV, ORIGINAL_COMMAND};
+use crate::terminal::WindowSize;
+use crate::wire::Reader;
+use account::{can_log_in, switches_user, Account};
+use agent_forwarding::AgentForwarding;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use throttle::Throttle;
+
+/// Creates the handler of each new client
+type HandlerFactory = dyn Fn() -> Box<dyn SessionHandler> + Send + Sync;
+
+/// The key identifying the server, sent to the clients with its certificate
+/// if it has one
+struct HostKey {
+    key: PrivateKey,
+    certificate: Option<Certificate>,
+}
+
+impl HostKey {
+    /// `hostkey\n<key or certificate blob>\n<signature>` with the blobs in
+    /// hex, the signature of the session proves the server owns the key
+    fn message(&self, session_id: &[u8; 32]) -> io::Result<String> {
+        let blob = match &self.certificate {
+            Some(certificate) => certificate.to_blob(),
+            None => self.key.public_key().to_blob(),
+        };
+        let signature = self.key.sign(&hostkey_auth_data(session_id, &blob))?;
+        Ok(format!("hostkey\n{}\n{}", to_hex(&blob), to_hex(&signature)))
+    }
+}
+
+/// Check the credentials sent by the client, either `<login>\n<password>`
+/// or `publickey\n<login>\n<key or certificate blob>\n<signature>` with the
+/// blobs in hex. Returns the login if they are accepted, with what the key
+/// can do.
+///
+/// `revoked` is `None` when the revocation file could not be read, every key
+/// is refused then.
+fn check_credentials<'a>(
+    authenticator: &dyn Authenticator,
+    session_id: &[u8; 32],
+    peer: IpAddr,
+    revoked: Option<&RevokedKeys>,
+    credentials: &'a str,
+) -> Option<(&'a str, KeyOptions)> {
+    let fields: Vec<&str> = credentials.split('\n').collect();
+    match fields.as_slice() {
+        ["publickey", login, blob, signature] => {
+            let (Some(blob), Some(signature)) = (from_hex(blob), from_hex(signature)) else {
+                return None;
+            };
+            // the signature proves the client owns the private key
+            let data = publickey_auth_data(session_id, login, &blob);
+            if Certificate::is_certificate(&blob) {
+                let Ok(certificate) = Certificate::from_blob(&blob) else {
+                    return None;
+                };
+                if revoked.is_none_or(|revoked| revoked.is_certificate_revoked(&certificate)) {
+                    println!(
+                        "certificate {} (serial {}) refused for {}: revoked",
+                        certificate.key_id, certificate.serial, login
+                    );
+                    return None;
+                }
+                let accepted = authenticator.certificate(login, &certificate)
+                    && certificate.allows_source(peer)
+                    && certificate.key.verify(&data, &signature);
+                if accepted {
+                    println!(
+                        "certificate {} (serial {}) accepted for {}",
+                        certificate.key_id, certificate.serial, login
+                    );
+                }
+                return accepted.then(|| (*login, KeyOptions::default()));
+            }
+            let Ok(key) = PublicKey::from_blob(&blob) else {
+                return None;
+            };
+            if revoked.is_none_or(|revoked| revoked.is_key_revoked(&key)) {
+                println!("key {} refused for {}: revoked", key.fingerprint(), login);
+                return None;
+            }
+            let options = authenticator.public_key_options(login, &key)?;
+            if !options.allows_source(peer) {
+                println!("key {} refused for {login} from {peer}", key.fingerprint());
+                return None;
+            }
+            key.verify(&data, &signature).then_some((*login, options))
+        }
+        [login, password] => authenticator
+            .password(login, password)
+            .then(|| (*login, KeyOptions::default())),
+        _ => None,
+    }
+}
+
+/// The login the client tries to log in as, whatever the way
+fn claimed_login(credentials: &str) -> &str {
+    let fields: Vec<&str> = credentials.split('\n').collect();
+    match fields.as_slice() {
+        ["publickey", login, ..] | [KEYBOARD_INTERACTIVE, login] | [login, ..] => login,
+        [] => "",
+    }
+}
+
+/// Check the credentials of the client, asking the questions of
+/// keyboard-interactive and of the second factor if needed. Returns what the
+/// client can do if they are accepted.
+fn authenticate(
+    transport: &mut Transport,
+    settings: &Settings,
+    peer: IpAddr,
+    revoked: Option<&RevokedKeys>,
+    credentials: &str,
+) -> io::Result<Option<KeyOptions>> {
+    let authenticator = &*settings.authenticator;
+    let login = match credentials
+        .strip_prefix(KEYBOARD_INTERACTIVE)
+        .and_then(|login| login.strip_prefix('\n'))
+    {
+        Some(login) => authenticator
+            .keyboard_interactive(login, &mut RemoteConversation(transport))?
+            .then(|| (login, KeyOptions::default())),
+        None => {
+            let session_id = *transport.session_id();
+            check_credentials(authenticator, &session_id, peer, revoked, credentials)
+        }
+    };
+    // the second factor is only asked once the first one is accepted
+    match (login, &settings.second_factor) {
+        (Some((login, options)), Some(second_factor)) => Ok(second_factor
+            .keyboard_interactive(login, &mut RemoteConversation(transport))?
+            .then_some(options)),
+        (login, _) => Ok(login.map(|(_, options)| options)),
+    }
+}
+
+/// Asks the questions of a keyboard-interactive authentication to the
+/// client: `info-request` followed by a `<echo> <prompt>` line per question,
+/// with echo 0 or 1 and the prompt in hex, answered by `info-response`
+/// followed by an answer in hex per line
+struct RemoteConversation<'a>(&'a mut Transport);
+
+impl Conversation for RemoteConversation<'_> {
+    fn ask(&mut self, prompts: &[Prompt]) -> io::Result<Vec<String>> {
+        let mut request = String::from(INFO_REQUEST);
+        for prompt in prompts {
+            let echo = if prompt.echo { 1 } else { 0 };
+            request += &format!("\n{echo} {}", to_hex(prompt.text.as_bytes()));
+        }
+        self.0.send(&request)?;
+
+        let response = self.0.receive()?;
+        let mut lines = response.split('\n');
+        if lines.next() != Some(INFO_RESPONSE) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected the answers of the client",
+            ));
+        }
+        lines
+            .map(|answer| {
+                from_hex(answer)
+                    .and_then(|answer| String::from_utf8(answer).ok())
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bad answer"))
+            })
+            .collect()
+    }
+}
+
+/// Serve a session channel: wait for the command, shell or subsystem the
+/// client asks for, run it with `handler`, send how it ended and close the
+/// channel. Only the variables matching `accept_env` can be set, and
+/// `options` restrict what the client can ask for, their variables are only
+/// set if `user_environment`.
+fn serve_session(
+    mut channel: Channel,
+    mut handler: Box<dyn SessionHandler>,
+    accept_env: &[String],
+    options: &KeyOptions,
+    user_environment: bool,
+) -> io::Result<()> {
+    while let Some(message) = channel.recv() {
+        let ChannelMessage::Request {
+            kind,
+            want_reply,
+            data,
+        } = message
+        else {
+            continue;
+        };
+        if kind == ENV {
+            let mut reader = Reader::new(&data);
+            let result = match (reader.str(), reader.str()) {
+                (Ok(name), Ok(value)) if env_matches(accept_env, name) => {
+                    handler.on_env(name, value)
+                }
+                _ => Err(io::Error::from(io::ErrorKind::PermissionDenied)),
+            };
+            if want_reply {
+                channel.reply(result.is_ok())?;
+            }
+            continue;
+        }
+        if kind == "pty-req" {
+            let mut reader = Reader::new(&data);
+            let result = match (reader.str(), WindowSize::read(&mut reader)) {
+                _ if options.no_pty => Err(io::Error::from(io::ErrorKind::PermissionDenied)),
+                (Ok(term), Ok(size)) => handler.on_pty_request(term, size),
+                (Err(e), _) | (_, Err(e)) => Err(e),
+            };
+            if want_reply {
+                channel.reply(result.is_ok())?;
+            }
+            continue;
+        }
+        let argument = match kind.as_str() {
+            "exec" | "subsystem" => Reader::new(&data).str().map(str::to_string).ok(),
+            "shell" => Some(String::new()),
+            _ => None,
+        };
+        let Some(argument) = argument else {
+            if want_reply {
+                channel.reply(false)?;
+            }
+            continue;
+        };
+        if want_reply {
+            channel.reply(true)?;
+        }
+
+        // the variables of the key win over the ones of the client
+        let mut environment = match user_environment {
+            true => options.environment.clone(),
+            false => Vec::new(),
+        };
+        if options.command.is_some() && kind != "shell" {
+            environment.push((ORIGINAL_COMMAND.to_string(), argument.clone()));
+        }
+        for (name, value) in &environment {
+            if let Err(e) = handler.on_env(name, value) {
+                println!("Failed to set {name}: {e}");
+            }
+        }
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| match kind.as_str() {
+            _ if options.command.is_some() => {
+                handler.on_exec(options.command.as_deref().unwrap(), &mut channel)
+            }
+            "exec" => handler.on_exec(argument.trim(), &mut channel),
+            "shell" => handler.on_shell(&mut channel),
+            _ => handler.on_subsystem(argument.trim(), &mut channel),
+        }))
+        .unwrap_or_else(|_| Err(io::Error::other("the handler panicked")));
+        match result {
+            Ok(status) => {
+                let (kind, data) = status.to_request();
+                channel.request(kind, false, &data)?;
+            }
+            Err(e) => {
+                channel.write_stderr(format!("Failed to execute command: {e}\n").as_bytes())?
+            }
+        }
+        break;
+    }
+    handler.on_eof();
+    channel.eof()?;
+    channel.close()
+}
+
+/// Serve the channels and global requests of an authenticated client, each
+/// session channel in its own thread with its own handler. `options` are the
+/// restrictions of the key the client logged in with.
+fn serve_connection<'scope>(
+    scope: &'scope thread::Scope<'scope, '_>,
+    connection: &Connection,
+    settings: &'scope Settings,
+    login: &str,
+    options: &'scope KeyOptions,
+    handler_factory: &'scope HandlerFactory,
+) -> io::Result<()> {
+    let account = match switches_user() {
+        true => Account::by_name(login)?,
+        false => None,
+    };
+    let mut agent_forwarding = None;
+    while let Some(incoming) = connection.next_incoming() {
+        match incoming {
+            Incoming::Request(request) if request.kind() == AGENT_FORWARDING_REQUEST => {
+                let result = if !settings.allow_agent_forwarding {
+                    Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        "agent forwarding is disabled",
+                    ))
+                } else if options.no_agent_forwarding {
+                    Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        "agent forwarding is not allowed for this key",
+                    ))
+                } else if agent_forwarding.is_some() {
+                    Ok(())
+                } else {
+                    AgentForwarding::start(connection.clone(), account.as_ref())
+                        .map(|forwarding| agent_forwarding = Some(forwarding))
+                };
+                if let Err(e) = &result {
+                    println!("Failed to forward the agent: {e}");
+                }
+                request.reply(result.is_ok())?;
+            }
+            Incoming::Request(request) => request.reply(false)?,
+            Incoming::Open(open) if open.kind() == SESSION => {
+                let channel = open.accept()?;
+                let mut handler = handler_factory();
+                handler.on_login(login);
+                if let Some(forwarding) = &agent_forwarding {
+                    if let Err(e) = handler.on_agent_forwarding(forwarding.socket()) {
+                        println!("Failed to forward the agent: {e}");
+                    }
+                }
+                scope.spawn(move || {
+                    let accept_env = &settings.accept_env;
+                    let user_environment = settings.permit_user_environment;
+                    serve_session(channel, handler, accept_env, options, user_environment)
+                });
+            }
+            Incoming::Open(open) => {
+                let reason = format!("unknown channel type {}", open.kind());
+                open.reject(&reason)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Use by thread to communicate with one client
+/// args:
+///     :stream: the stream to communicate with the client
+///     :settings: how the client is authenticated and what it can do
+///     :handler_factory: makes the handlers of the session channels
+fn handle_client(
+    stream: TcpStream,
+    settings: &Settings,
+    handler_factory: &HandlerFactory,
+) -> io::Result<SocketAddr> {
+    let addr = stream.peer_addr()?;
+    println!("connection received: {}", addr);
+    if settings.throttle.is_address_banned(addr.ip()) {
+        println!("connection of {} refused: banned", addr.ip());
+        return Ok(addr);
+    }
+
+    let mut transport = Transport::server_handshake(stream)?;
+
+    // ==============================================
+    // Authentification
+    // =============================================
+
+    // send the host key, signed to prove it is ours
+    transport.send(&settings.host_key.message(transport.session_id())?)?;
+
+    // receive ko if the connection is refused
+    // receive the credentials of the user if the connection is accepted
+    let revoked = match &settings.revoked_keys {
+        Some(path) => RevokedKeys::load(path)
+            .map_err(|e| eprintln!("{}: {e}, every key is refused", path.display()))
+            .ok(),
+        None => Some(RevokedKeys::default()),
+    };
+    let throttle = &settings.throttle;
+    let mut tries = 0;
+    let (login, options) = loop {
+        let response = transport.receive()?;
+        if response == "KO" && tries == 0 {
+            transport.shutdown()?;
+            return Ok(addr);
+        }
+        tries += 1;
+
+        // a banned login is refused whatever its credentials
+        let login = claimed_login(&response);
+        let mut accepted = match throttle.is_user_banned(login) {
+            true => None,
+            false => {
+                authenticate(&mut transport, settings, addr.ip(), revoked.as_ref(), &response)?
+            }
+        };
+        if accepted.is_some() && !can_log_in(login) {
+            println!("{login} refused: no account with a valid shell");
+            accepted = None;
+        }
+        if let Some(options) = accepted {
+            throttle.success(login);
+            break (login.to_string(), options);
+        }
+
+        // each failure is answered later than the previous one
+        thread::sleep(throttle.failure(login, addr.ip()));
+        if tries >= settings.max_auth_tries || throttle.is_address_banned(addr.ip()) {
+            println!("{addr}: {TOO_MANY_FAILURES}");
+            transport.send(TOO_MANY_FAILURES)?;
+            transport.shutdown()?;
+            return Ok(addr);
+        }
+        transport.send("login or password unknown")?;
+    };
+
+    transport.send("connected")?;
+
+    println!("Client authenticated successfully");
+
+    // ========================================
+    // Main communication loop
+    // ========================================
+
+    // the client opens channels on the connection, several commands can
+    // run at once
+    let connection = Connection::new(transport)?;
+    thread::scope(|scope| {
+        let result = serve_connection(
+            scope,
+            &connection,
+            settings,
+            &login,
+            &options,
+            handler_factory,
+        );
+        // wake the sessions still running once the client is gone
+        let _ = connection.disconnect();
+        result
+    })?;
+
+    Ok(addr)
+}
+
+/// How the clients are authenticated and what they can do, shared by their
+/// threads
+struct Settings {
+    authenticator: Box<dyn Authenticator>,
+    /// Asked after the credentials of the client, with keyboard-interactive
+    second_factor: Option<Box<dyn Authenticator>>,
+    host_key: HostKey,
+    /// File of the keys refused, read again for each client
+    revoked_keys: Option<PathBuf>,
+    allow_agent_forwarding: bool,
+    /// Authentications a client can try on a connection
+    max_auth_tries: u32,
+    throttle: Throttle,
+    /// Patterns of the variables the clients can set
+    accept_env: Vec<String>,
+    /// Whether the variables of the authorized keys are set
+    permit_user_environment: bool,
+}
+
+/// A NSSH server listening for clients, built with [`Server::builder`].
+///
+/// ```no_run
+/// let server = nssh::server::Server::builder()
+///     .bind("127.0.0.1:7878")
+///     .build()
+///     .expect("failed to bind");
+/// server.run();
+/// ```
+pub struct Server {
+    listener: TcpListener,
+    settings: Arc<Settings>,
+    handler_factory: Arc<HandlerFactory>,
+}
+
+impl Server {
+    /// Start configuring a new server
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+
+    /// Address the server is listening on
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accept clients forever, each one in its own thread
+    pub fn run(&self) {
+        for stream in self.listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let settings = Arc::clone(&self.settings);
+                    let handler_factory = Arc::clone(&self.handler_factory);
+                    thread::spawn(move || {
+                        match handle_client(stream, &settings, &*handler_factory) {
+                            Ok(addr) => println!("Client {} disconnected", addr),
+                            Err(e) => eprintln!("Error handling client: {}", e),
+                        }
+                    });
+                }
+                Err(e) => {
+                    eprintln!("Failed to establish a connection: {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Configuration of a [`Server`] before it starts listening.
+pub struct ServerBuilder {
+    addr: String,
+    authenticator: Box<dyn Authenticator>,
+    second_factor: Option<Box<dyn Authenticator>>,
+    host_key: Option<PrivateKey>,
+    host_certificate: Option<Certificate>,
+    /// Files of the configuration, read by `build`
+    host_key_file: Option<PathBuf>,
+    host_certificate_file: Option<PathBuf>,
+    revoked_keys: Option<PathBuf>,
+    allow_agent_forwarding: bool,
+    max_auth_tries: u32,
+    max_failures: u32,
+    ban_time: Duration,
+    accept_env: Vec<String>,
+    permit_user_environment: bool,
+    handler_factory: Arc<HandlerFactory>,
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        let config = Config::default();
+        ServerBuilder {
+            addr: config.listen_address.clone(),
+            authenticator: config.authenticator(),
+            second_factor: None,
+            host_key: None,
+            host_certificate: None,
+            host_key_file: None,
+            host_certificate_file: None,
+            revoked_keys: None,
+            allow_agent_forwarding: config.allow_agent_forwarding,
+            max_auth_tries: config.max_auth_tries,
+            max_failures: config.max_failures,
+            ban_time: config.ban_time,
+            accept_env: config.accept_env,
+            permit_user_environment: config.permit_user_environment,
+            handler_factory: Arc::new(|| Box::new(ShellHandler::default())),
+        }
+    }
+}
+
+/// Add the path of a file to the errors reading it
+fn read_error(path: &Path, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {e}", path.display()))
+}
+
+impl ServerBuilder {
+    /// Address to listen on, `127.0.0.1:7878` by default
+    pub fn bind(mut self, addr: impl Into<String>) -> Self {
+        self.addr = addr.into();
+        self
+    }
+
+    /// Apply the settings of a configuration file
+    pub fn config(self, config: &Config) -> Self {
+        let builder = self.bind(config.listen_address.as_str());
+        ServerBuilder {
+            authenticator: config.authenticator(),
+            second_factor: config.second_factor(),
+            host_key_file: config.host_key.clone(),
+            host_certificate_file: config.host_certificate.clone(),
+            revoked_keys: config.revoked_keys.clone(),
+            allow_agent_forwarding: config.allow_agent_forwarding,
+            max_auth_tries: config.max_auth_tries,
+            max_failures: config.max_failures,
+            ban_time: config.ban_time,
+            accept_env: config.accept_env.clone(),
+            permit_user_environment: config.permit_user_environment,
+            ..builder
+        }
+    }
+
+    /// Identify the server with `key`, a temporary key is made by default
+    pub fn host_key(mut self, key: PrivateKey) -> Self {
+        self.host_key = Some(key);
+        self
+    }
+
+    /// Send `certificate` of the host key to the clients so that those
+    /// trusting its CA accept the server
+    pub fn host_certificate(mut self, certificate: Certificate) -> Self {
+        self.host_certificate = Some(certificate);
+        self
+    }
+
+    /// Check the credentials of the clients with `authenticator`
+    pub fn authenticator(mut self, authenticator: impl Authenticator + 'static) -> Self {
+        self.authenticator = Box::new(authenticator);
+        self
+    }
+
+    /// Once their credentials are accepted, ask the clients more questions
+    /// with the keyboard-interactive authentication of `authenticator`, like
+    /// a code of a [`TotpFile`]
+    pub fn second_factor(mut self, authenticator: impl Authenticator + 'static) -> Self {
+        self.second_factor = Some(Box::new(authenticator));
+        self
+    }
+
+    /// Serve each session channel of the clients with a handler made by
+    /// `factory`, a [`ShellHandler`] by default
+    pub fn handler<H, F>(mut self, factory: F) -> Self
+    where
+        H: SessionHandler + 'static,
+        F: Fn() -> H + Send + Sync + 'static,
+    {
+        self.handler_factory = Arc::new(move || Box::new(factory()));
+        self
+    }
+
+    /// Refuse the keys and certificates revoked in the file at `path`, read
+    /// again for each client so that new entries apply at once
+    pub fn revoked_keys(mut self, path: impl Into<PathBuf>) -> Self {
+        self.revoked_keys = Some(path.into());
+        self
+    }
+
+    /// Whether clients can forward their agent, allowed by default
+    pub fn agent_forwarding(mut self, allow: bool) -> Self {
+        self.allow_agent_forwarding = allow;
+        self
+    }
+
+    /// Close the connection of a client after `tries` failed
+    /// authentications, 6 by default
+    pub fn max_auth_tries(mut self, tries: u32) -> Self {
+        self.max_auth_tries = tries.max(1);
+        self
+    }
+
+    /// Ban a login or an address for `ban_time` after `max_failures` failed
+    /// authentications, 10 failures and 10 minutes by default. The failures
+    /// before are answered after a delay that doubles each time.
+    pub fn ban(mut self, max_failures: u32, ban_time: Duration) -> Self {
+        self.max_failures = max_failures.max(1);
+        self.ban_time = ban_time;
+        self
+    }
+
+    /// Let the clients set the environment variables matching `patterns`
+    /// for their commands, `*` matching any characters and `?` any one.
+    /// None by default.
+    pub fn accept_env(mut self, patterns: &[&str]) -> Self {
+        self.accept_env = patterns.iter().map(|pattern| pattern.to_string()).collect();
+        self
+    }
+
+    /// Whether the `environment="..."` options of the authorized keys are
+    /// applied. Their users can edit them, so they could give a forced
+    /// command an `LD_PRELOAD` or a `PATH` of their own: refused by default.
+    pub fn user_environment(mut self, permit: bool) -> Self {
+        self.permit_user_environment = permit;
+        self
+    }
+
+    /// Read the host key and bind the listening socket
+    pub fn build(self) -> io::Result<Server> {
+        let key = match (self.host_key, &self.host_key_file) {
+            (Some(key), _) => key,
+            (None, Some(path)) => PrivateKey::from_file(path).map_err(|e| read_error(path, e))?,
+            (None, None) => {
+                println!("No host key configured, using a temporary one");
+                PrivateKey::generate_ed25519()
+            }
+        };
+        let certificate = match (self.host_certificate, &self.host_certificate_file) {
+            (Some(certificate), _) => Some(certificate),
+            (None, Some(path)) => {
+                let text = fs::read_to_string(path).map_err(|e| read_error(path, e))?;
+                let (certificate, _) =
+                    Certificate::parse_line(&text).map_err(|e| read_error(path, e))?;
+                Some(certificate)
+            }
+            (None, None) => None,
+        };
+        if let Some(certificate) = &certificate {
+            if certificate.key != key.public_key()
+                || certificate.certificate_type != CertificateType::Host
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the host certificate is not the one of the host key",
+                ));
+            }
+        }
+
+        let listener = TcpListener::bind(self.addr.as_str())?;
+        let settings = Settings {
+            authenticator: self.authenticator,
+            second_factor: self.second_factor,
+            host_key: HostKey { key, certificate },
+            revoked_keys: self.revoked_keys,
+            allow_agent_forwarding: self.allow_agent_forwarding,
+            max_auth_tries: self.max_auth_tries,
+            throttle: Throttle::new(self.max_failures, self.ban_time),
+            accept_env: self.accept_env,
+            permit_user_environment: self.permit_user_environment,
+        };
+        Ok(Server {
+            listener,
+            settings: Arc::new(settings),
+            handler_factory: self.handler_factory,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::connected_pair;
+    use crate::session::ExitStatus;
+    use crate::wire::Writer;
+    use std::io::Read;
+
+    /// Writes the command it runs and the variables it was given
+    #[derive(Default)]
+    struct Recorder {
+        environment: Vec<(String, String)>,
+    }
+
+    impl SessionHandler for Recorder {
+        fn on_env(&mut self, name: &str, value: &str) -> io::Result<()> {
+            self.environment.push((name.to_string(), value.to_string()));
+            Ok(())
+        }
+
+        fn on_exec(&mut self, command: &str, channel: &mut Channel) -> io::Result<ExitStatus> {
+            let output = format!("{command} {:?}", self.environment);
+            io::Write::write_all(channel, output.as_bytes())?;
+            Ok(ExitStatus::Code(0))
+        }
+    }
+
+    /// A client whose sessions are served with a [`Recorder`] as if it
+    /// logged in with a key of `options`
+    fn serve(options: KeyOptions, user_environment: bool) -> (Connection, thread::JoinHandle<()>) {
+        let (client, server) = connected_pair();
+        let server = thread::spawn(move || {
+            while let Some(incoming) = server.next_incoming() {
+                if let Incoming::Open(open) = incoming {
+                    let channel = open.accept().unwrap();
+                    let handler = Box::new(Recorder::default());
+                    serve_session(channel, handler, &[], &options, user_environment).unwrap();
+                }
+            }
+        });
+        (client, server)
+    }
+
+    /// Send a request of `kind` on a new session and read all its output
+    fn request(client: &Connection, kind: &str, argument: Option<&str>) -> String {
+        let mut channel = client.open(SESSION, &[]).unwrap();
+        let mut data = Writer::new();
+        if let Some(argument) = argument {
+            data.string(argument);
+        }
+        assert!(channel.request(kind, true, &data.into_bytes()).unwrap());
+        let mut output = String::new();
+        channel.read_to_string(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn forced_command() {
+        let options = KeyOptions {
+            command: Some("deploy".to_string()),
+            ..KeyOptions::default()
+        };
+        let (client, server) = serve(options, false);
+        for (kind, argument, output) in [
+            (
+                "exec",
+                Some("ls -l"),
+                r#"deploy [("NSSH_ORIGINAL_COMMAND", "ls -l")]"#,
+            ),
+            (
+                "subsystem",
+                Some("sftp"),
+                r#"deploy [("NSSH_ORIGINAL_COMMAND", "sftp")]"#,
+            ),
+            ("shell", None, "deploy []"),
+        ] {
+            assert_eq!(request(&client, kind, argument), output, "{kind}");
+        }
+        client.disconnect().unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn key_environment() {
+        let options = KeyOptions {
+            environment: vec![("LD_PRELOAD".to_string(), "/tmp/evil.so".to_string())],
+            ..KeyOptions::default()
+        };
+
+        // ignored unless the server permits it
+        let (client, server) = serve(options.clone(), false);
+        assert_eq!(request(&client, "exec", Some("ls")), "ls []");
+        client.disconnect().unwrap();
+        server.join().unwrap();
+        assert!(!Config::default().permit_user_environment);
+        assert!(!ServerBuilder::default().permit_user_environment);
+
+        let (client, server) = serve(options, true);
+        assert_eq!(
+            request(&client, "exec", Some("ls")),
+            r#"ls [("LD_PRELOAD", "/tmp/evil.so")]"#
+        );
+        client.disconnect().unwrap();
+        server.join().unwrap();
+    }
+}
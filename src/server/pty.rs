@@ -0,0 +1,115 @@
+//! Pseudo-terminals for the shells of the clients.
+
+use super::account::Account;
+use crate::terminal::WindowSize;
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::ptr;
+
+/// A new pseudo-terminal: the program runs on the slave side and the server
+/// reads and writes the master side
+pub(crate) struct Pty {
+    master: File,
+    slave: OwnedFd,
+}
+
+/// Keep `fd` out of the programs the server runs
+fn set_cloexec(fd: &OwnedFd) -> io::Result<()> {
+    if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Id of the `tty` group, `None` if there is none
+fn tty_group() -> Option<libc::gid_t> {
+    let mut buffer = vec![0; 1024];
+    loop {
+        // SAFETY: group is plain data filled by the lookup
+        let mut entry: libc::group = unsafe { std::mem::zeroed() };
+        let mut result = ptr::null_mut();
+        let error = unsafe {
+            libc::getgrnam_r(
+                c"tty".as_ptr(),
+                &mut entry,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        };
+        match error {
+            0 if result.is_null() => return None,
+            0 => return Some(entry.gr_gid),
+            libc::ERANGE if buffer.len() < 1 << 20 => buffer.resize(buffer.len() * 2, 0),
+            _ => return None,
+        }
+    }
+}
+
+impl Pty {
+    /// Open a new pseudo-terminal, whose slave side belongs to `owner` if
+    /// given: they can read and write it, and the `tty` group, or theirs if
+    /// there is none, can only write to it, for `write` and `wall`
+    pub(crate) fn open(owner: Option<&Account>) -> io::Result<Self> {
+        let (mut master, mut slave) = (0, 0);
+        let result = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                ptr::null_mut(),
+                ptr::null(),
+                ptr::null(),
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: openpty just opened both descriptors for us
+        let (master, slave) =
+            unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+        set_cloexec(&master)?;
+        set_cloexec(&slave)?;
+        if let Some(owner) = owner {
+            let group = tty_group().unwrap_or(owner.gid);
+            unix::fs::fchown(&slave, Some(owner.uid), Some(group))?;
+            if unsafe { libc::fchmod(slave.as_raw_fd(), 0o620) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(Pty {
+            master: File::from(master),
+            slave,
+        })
+    }
+
+    pub(crate) fn set_size(&self, size: WindowSize) -> io::Result<()> {
+        size.apply(self.master.as_raw_fd())
+    }
+
+    /// Run `command` on the terminal, as the leader of a new session whose
+    /// controlling terminal it is. Returns the process and the master side,
+    /// whose reads fail once the program exited.
+    pub(crate) fn spawn(self, mut command: Command) -> io::Result<(Child, File)> {
+        command
+            .stdin(Stdio::from(self.slave.try_clone()?))
+            .stdout(Stdio::from(self.slave.try_clone()?))
+            .stderr(Stdio::from(self.slave));
+        // SAFETY: only async-signal-safe calls between fork and exec
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = command.spawn()?;
+        // the slave side must be closed here for the reads to fail at the end
+        drop(command);
+        Ok((child, self.master))
+    }
+}
@@ -0,0 +1,208 @@
+//! Slows down and bans the clients guessing passwords.
+//!
+//! Failed authentications are counted per login and per address. Each
+//! failure is answered after a delay that doubles with the count, and once
+//! the count reaches the maximum the login or the address is banned for a
+//! while. Counts are forgotten after a ban time without failure, and the one
+//! of a login when it logs in. The one of the address is kept then, so that
+//! logging in with a valid account between guesses does not reset it.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Delay before answering the first failure, doubled at each new one
+const BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest delay before answering a failure
+const MAX_DELAY: Duration = Duration::from_secs(16);
+
+#[derive(Debug, Clone, Copy)]
+struct Failures {
+    count: u32,
+    last: Instant,
+    banned_until: Option<Instant>,
+}
+
+impl Failures {
+    fn is_banned(&self, now: Instant) -> bool {
+        self.banned_until.is_some_and(|until| now < until)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    users: HashMap<String, Failures>,
+    addresses: HashMap<IpAddr, Failures>,
+}
+
+/// Failure counters shared by the clients of a server
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    max_failures: u32,
+    ban_time: Duration,
+    counters: Mutex<Counters>,
+}
+
+impl Throttle {
+    /// Ban for `ban_time` after `max_failures` failures
+    pub(crate) fn new(max_failures: u32, ban_time: Duration) -> Self {
+        Throttle {
+            max_failures,
+            ban_time,
+            counters: Mutex::new(Counters::default()),
+        }
+    }
+
+    pub(crate) fn is_address_banned(&self, ip: IpAddr) -> bool {
+        let counters = self.counters.lock().unwrap();
+        counters
+            .addresses
+            .get(&ip.to_canonical())
+            .is_some_and(|failures| failures.is_banned(Instant::now()))
+    }
+
+    pub(crate) fn is_user_banned(&self, user: &str) -> bool {
+        let counters = self.counters.lock().unwrap();
+        counters
+            .users
+            .get(user)
+            .is_some_and(|failures| failures.is_banned(Instant::now()))
+    }
+
+    /// Count a failure of `user` from `ip`, returns how long to wait before
+    /// answering the client
+    pub(crate) fn failure(&self, user: &str, ip: IpAddr) -> Duration {
+        self.failure_at(user, ip, Instant::now())
+    }
+
+    fn failure_at(&self, user: &str, ip: IpAddr, now: Instant) -> Duration {
+        let mut counters = self.counters.lock().unwrap();
+        let ip = ip.to_canonical();
+
+        // forget the clients that stopped failing long ago
+        let ban_time = self.ban_time;
+        let expired = |failures: &Failures| {
+            !failures.is_banned(now) && now.duration_since(failures.last) >= ban_time
+        };
+        counters.users.retain(|_, failures| !expired(failures));
+        counters.addresses.retain(|_, failures| !expired(failures));
+
+        let user_count = self.count(
+            counters.users.entry(user.to_string()).or_insert(Failures {
+                count: 0,
+                last: now,
+                banned_until: None,
+            }),
+            now,
+            &format!("user {user}"),
+        );
+        let address_count = self.count(
+            counters.addresses.entry(ip).or_insert(Failures {
+                count: 0,
+                last: now,
+                banned_until: None,
+            }),
+            now,
+            &format!("address {ip}"),
+        );
+
+        let count = user_count.max(address_count);
+        BASE_DELAY
+            .saturating_mul(1 << (count - 1).min(16))
+            .min(MAX_DELAY)
+    }
+
+    /// Add a failure to `failures`, banning `who` if it has too many.
+    /// Returns the count before the ban.
+    fn count(&self, failures: &mut Failures, now: Instant, who: &str) -> u32 {
+        failures.count += 1;
+        failures.last = now;
+        let count = failures.count;
+        if count >= self.max_failures {
+            println!(
+                "{who} banned for {} s after {count} failed authentications",
+                self.ban_time.as_secs()
+            );
+            failures.count = 0;
+            failures.banned_until = Some(now + self.ban_time);
+        }
+        count
+    }
+
+    /// `user` logged in, their failures are forgotten
+    pub(crate) fn success(&self, user: &str) {
+        let mut counters = self.counters.lock().unwrap();
+        counters.users.remove(user);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn back_off_and_ban() {
+        let throttle = Throttle::new(4, Duration::from_secs(60));
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "::ffff:10.0.0.2".parse().unwrap();
+        let now = Instant::now();
+
+        assert_eq!(throttle.failure_at("alice", ip, now), BASE_DELAY);
+        assert_eq!(throttle.failure_at("alice", ip, now), BASE_DELAY * 2);
+        // a new address guessing the same login is slowed down too
+        assert_eq!(throttle.failure_at("alice", other, now), BASE_DELAY * 4);
+        assert!(!throttle.is_user_banned("alice"));
+
+        throttle.failure_at("alice", ip, now);
+        assert!(throttle.is_user_banned("alice"));
+        assert!(!throttle.is_user_banned("bob"));
+        assert!(!throttle.is_address_banned(ip));
+
+        throttle.success("bob");
+        assert!(throttle.is_user_banned("alice"));
+        throttle.success("alice");
+        assert!(!throttle.is_user_banned("alice"));
+
+        // the address is banned whatever the logins tried
+        for user in ["a", "b", "c", "d"] {
+            throttle.failure_at(user, ip, now);
+        }
+        assert!(throttle.is_address_banned(ip));
+        assert!(throttle.is_address_banned("::ffff:10.0.0.1".parse().unwrap()));
+        assert!(!throttle.is_address_banned(other));
+    }
+
+    #[test]
+    fn failures_expire() {
+        let throttle = Throttle::new(3, Duration::from_secs(60));
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let now = Instant::now();
+
+        throttle.failure_at("alice", ip, now);
+        throttle.failure_at("alice", ip, now);
+        let later = now + Duration::from_secs(61);
+        assert_eq!(throttle.failure_at("alice", ip, later), BASE_DELAY);
+
+        for _ in 0..20 {
+            throttle.failure_at("bob", "10.0.0.3".parse().unwrap(), later);
+        }
+        let delay = throttle.failure_at("carol", "10.0.0.3".parse().unwrap(), later);
+        assert!(delay <= MAX_DELAY);
+    }
+
+    #[test]
+    fn success_keeps_address_failures() {
+        let throttle = Throttle::new(3, Duration::from_secs(60));
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let now = Instant::now();
+
+        throttle.failure_at("alice", ip, now);
+        throttle.failure_at("bob", ip, now);
+        // logging in with a valid account does not reset the guesses
+        throttle.success("mallory");
+        assert_eq!(throttle.failure_at("carol", ip, now), BASE_DELAY * 4);
+        assert!(throttle.is_address_banned(ip));
+    }
+}
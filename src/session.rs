@@ -0,0 +1,192 @@
+//! Requests of the session channels that are not about starting something,
+//! with the same names and formats as SSH (RFC 4254 section 6).
+
+use crate::wire::{Reader, Writer};
+use std::process;
+
+/// Request telling the client the exit code of its command
+pub const EXIT_STATUS: &str = "exit-status";
+/// Request telling the client the signal that killed its command
+pub const EXIT_SIGNAL: &str = "exit-signal";
+
+/// Request setting an environment variable of the command, the data is its
+/// name and its value
+pub const ENV: &str = "env";
+
+/// Variable holding the command the client asked for, when the key it logged
+/// in with forces another one
+pub const ORIGINAL_COMMAND: &str = "NSSH_ORIGINAL_COMMAND";
+
+/// Whether the name of a variable matches one of `patterns`, where `*`
+/// matches any characters and `?` any one character
+pub fn env_matches(patterns: &[String], name: &str) -> bool {
+    fn matches(pattern: &[u8], name: &[u8]) -> bool {
+        match (pattern.split_first(), name.split_first()) {
+            (None, _) => name.is_empty(),
+            (Some((b'*', rest)), _) => {
+                matches(rest, name) || (!name.is_empty() && matches(pattern, &name[1..]))
+            }
+            (Some((b'?', rest)), Some((_, name))) => matches(rest, name),
+            (Some((c, rest)), Some((n, name))) => c == n && matches(rest, name),
+            (Some(_), None) => false,
+        }
+    }
+    patterns
+        .iter()
+        .any(|pattern| matches(pattern.as_bytes(), name.as_bytes()))
+}
+
+/// Request sending a signal to the command, the data is the name of the
+/// signal
+pub const SIGNAL: &str = "signal";
+
+/// Signals with a name in the messages, written without `SIG`
+const SIGNALS: &[(&str, i32)] = &[
+    ("ABRT", libc::SIGABRT),
+    ("ALRM", libc::SIGALRM),
+    ("FPE", libc::SIGFPE),
+    ("HUP", libc::SIGHUP),
+    ("ILL", libc::SIGILL),
+    ("INT", libc::SIGINT),
+    ("KILL", libc::SIGKILL),
+    ("PIPE", libc::SIGPIPE),
+    ("QUIT", libc::SIGQUIT),
+    ("SEGV", libc::SIGSEGV),
+    ("TERM", libc::SIGTERM),
+    ("USR1", libc::SIGUSR1),
+    ("USR2", libc::SIGUSR2),
+];
+
+/// Name of the signal `number`, `None` if it has none in the messages
+pub fn signal_name(number: i32) -> Option<&'static str> {
+    SIGNALS
+        .iter()
+        .find(|(_, n)| *n == number)
+        .map(|(name, _)| *name)
+}
+
+/// Number of the signal called `name`
+pub fn signal_number(name: &str) -> Option<i32> {
+    SIGNALS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, number)| *number)
+}
+
+/// How the command of a session ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// It exited with this code
+    Code(u32),
+    /// It was killed by the signal of this name
+    Signal { name: String, core_dumped: bool },
+}
+
+impl ExitStatus {
+    /// Exit code a shell would give: the code itself, or 128 plus the
+    /// number of the signal (255 for an unknown signal)
+    pub fn code(&self) -> i32 {
+        match self {
+            ExitStatus::Code(code) => *code as i32,
+            ExitStatus::Signal { name, .. } => signal_number(name).map_or(255, |n| 128 + n),
+        }
+    }
+
+    /// Kind and data of the request sent to the client
+    pub fn to_request(&self) -> (&'static str, Vec<u8>) {
+        let mut data = Writer::new();
+        match self {
+            ExitStatus::Code(code) => {
+                data.u32(*code);
+                (EXIT_STATUS, data.into_bytes())
+            }
+            ExitStatus::Signal { name, core_dumped } => {
+                // then an error message and its language, left empty
+                data.string(name).bool(*core_dumped).string("").string("");
+                (EXIT_SIGNAL, data.into_bytes())
+            }
+        }
+    }
+
+    /// Read an exit request, `None` if it is another request or is invalid
+    pub fn from_request(kind: &str, data: &[u8]) -> Option<Self> {
+        let mut reader = Reader::new(data);
+        match kind {
+            EXIT_STATUS => reader.u32().ok().map(ExitStatus::Code),
+            EXIT_SIGNAL => Some(ExitStatus::Signal {
+                name: reader.str().ok()?.to_string(),
+                core_dumped: reader.bool().ok()?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl From<process::ExitStatus> for ExitStatus {
+    fn from(status: process::ExitStatus) -> Self {
+        use std::os::unix::process::ExitStatusExt;
+
+        match (status.code(), status.signal()) {
+            (Some(code), _) => ExitStatus::Code(code as u32),
+            (None, Some(signal)) => ExitStatus::Signal {
+                name: signal_name(signal).map_or_else(|| signal.to_string(), str::to_string),
+                core_dumped: status.core_dumped(),
+            },
+            // stopped or continued, not an end
+            (None, None) => ExitStatus::Code(255),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_requests() {
+        for status in [
+            ExitStatus::Code(0),
+            ExitStatus::Code(3),
+            ExitStatus::Signal {
+                name: "KILL".to_string(),
+                core_dumped: false,
+            },
+        ] {
+            let (kind, data) = status.to_request();
+            assert_eq!(ExitStatus::from_request(kind, &data), Some(status));
+        }
+        assert_eq!(ExitStatus::from_request("exec", &[]), None);
+        assert_eq!(ExitStatus::from_request(EXIT_STATUS, &[0, 1]), None);
+    }
+
+    #[test]
+    fn env_patterns() {
+        let patterns = ["LANG".to_string(), "LC_*".to_string(), "CI_?D".to_string()];
+        for name in ["LANG", "LC_ALL", "LC_", "CI_ID"] {
+            assert!(env_matches(&patterns, name), "{name}");
+        }
+        for name in ["LANGUAGE", "PATH", "CI_D", "CI_IDS", ""] {
+            assert!(!env_matches(&patterns, name), "{name}");
+        }
+        assert!(env_matches(&["*".to_string()], ""));
+        assert!(!env_matches(&[], "LANG"));
+    }
+
+    #[test]
+    fn codes() {
+        use std::process::Command;
+
+        let status = Command::new("sh").args(["-c", "exit 3"]).status().unwrap();
+        assert_eq!(ExitStatus::from(status), ExitStatus::Code(3));
+        let status = Command::new("sh")
+            .args(["-c", "kill -TERM $$"])
+            .status()
+            .unwrap();
+        let status = ExitStatus::from(status);
+        assert_eq!(status.code(), 128 + libc::SIGTERM);
+        assert_eq!(
+            signal_number(signal_name(libc::SIGHUP).unwrap()),
+            Some(libc::SIGHUP)
+        );
+    }
+}
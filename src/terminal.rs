@@ -0,0 +1,289 @@
+//! Helpers for the terminal the programs are run from.
+
+use crate::wire::{Reader, Writer};
+use std::fs::File;
+use std::io::{self, BufRead, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Size of a terminal, in characters and in pixels (0 when unknown)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WindowSize {
+    pub columns: u32,
+    pub rows: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl WindowSize {
+    /// Size of the terminal `fd`, `None` if `fd` is not a terminal
+    pub fn of(fd: i32) -> Option<Self> {
+        // SAFETY: winsize is plain data filled by the ioctl
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } != 0 {
+            return None;
+        }
+        Some(WindowSize {
+            columns: size.ws_col.into(),
+            rows: size.ws_row.into(),
+            width: size.ws_xpixel.into(),
+            height: size.ws_ypixel.into(),
+        })
+    }
+
+    /// Resize the terminal `fd`, its foreground programs get a SIGWINCH
+    pub fn apply(&self, fd: i32) -> io::Result<()> {
+        let clamp = |value: u32| value.min(u16::MAX.into()) as u16;
+        let size = libc::winsize {
+            ws_col: clamp(self.columns),
+            ws_row: clamp(self.rows),
+            ws_xpixel: clamp(self.width),
+            ws_ypixel: clamp(self.height),
+        };
+        if unsafe { libc::ioctl(fd, libc::TIOCSWINSZ, &size) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, writer: &mut Writer) {
+        writer
+            .u32(self.columns)
+            .u32(self.rows)
+            .u32(self.width)
+            .u32(self.height);
+    }
+
+    pub fn read(reader: &mut Reader) -> io::Result<Self> {
+        Ok(WindowSize {
+            columns: reader.u32()?,
+            rows: reader.u32()?,
+            width: reader.u32()?,
+            height: reader.u32()?,
+        })
+    }
+}
+
+/// Callbacks of [`on_signal`] and their signal, dropped once they return
+/// false
+type SignalCallback = (i32, Box<dyn FnMut() -> bool + Send>);
+static SIGNAL_CALLBACKS: Mutex<Vec<SignalCallback>> = Mutex::new(Vec::new());
+/// What the signals with callbacks did before, put back once they have none
+static PREVIOUS_HANDLERS: Mutex<Vec<(i32, libc::sighandler_t)>> = Mutex::new(Vec::new());
+/// Write end of the pipe the signal handler writes the signals to, -1 until
+/// the first callback is registered
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn caught(signal: libc::c_int) {
+    let fd = SIGNAL_PIPE.load(Ordering::Relaxed);
+    // only async-signal-safe calls in a signal handler
+    unsafe { libc::write(fd, [signal as u8].as_ptr().cast(), 1) };
+}
+
+/// Give `signal` back what it did before its callbacks, and deliver it again
+/// since none of them wanted it
+fn restore_signal(signal: i32) {
+    let mut previous = PREVIOUS_HANDLERS.lock().unwrap();
+    if let Some(index) = previous.iter().position(|(number, _)| *number == signal) {
+        let (_, handler) = previous.swap_remove(index);
+        unsafe {
+            libc::signal(signal, handler);
+            libc::raise(signal);
+        }
+    }
+}
+
+/// Call `callback` from another thread every time the program gets
+/// `signal`, until it returns false. Meanwhile the signal loses its usual
+/// effect, which comes back with the signal that the last callback of
+/// `signal` turns down.
+pub fn on_signal(signal: i32, callback: impl FnMut() -> bool + Send + 'static) -> io::Result<()> {
+    let mut callbacks = SIGNAL_CALLBACKS.lock().unwrap();
+    if SIGNAL_PIPE.load(Ordering::Relaxed) == -1 {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: pipe2 just opened the read end for us, the write end is
+        // kept open for good
+        let mut signals = unsafe { File::from_raw_fd(fds[0]) };
+        SIGNAL_PIPE.store(fds[1], Ordering::Relaxed);
+
+        thread::spawn(move || {
+            let mut byte = [0];
+            while let Ok(1) = signals.read(&mut byte) {
+                let signal = i32::from(byte[0]);
+                let mut callbacks = SIGNAL_CALLBACKS.lock().unwrap();
+                callbacks.retain_mut(|(number, callback)| *number != signal || callback());
+                if callbacks.iter().all(|(number, _)| *number != signal) {
+                    restore_signal(signal);
+                }
+            }
+        });
+    }
+    if callbacks.iter().all(|(number, _)| *number != signal) {
+        let previous = unsafe { libc::signal(signal, caught as *const () as libc::sighandler_t) };
+        if previous == libc::SIG_ERR {
+            return Err(io::Error::last_os_error());
+        }
+        PREVIOUS_HANDLERS.lock().unwrap().push((signal, previous));
+    }
+    callbacks.push((signal, Box::new(callback)));
+    Ok(())
+}
+
+/// Call `callback` from another thread every time the terminal of the
+/// program is resized (SIGWINCH), until it returns false
+pub fn on_resize(callback: impl FnMut() -> bool + Send + 'static) -> io::Result<()> {
+    on_signal(libc::SIGWINCH, callback)
+}
+
+/// Restores the terminal settings when dropped
+struct EchoGuard {
+    fd: i32,
+    settings: libc::termios,
+}
+
+impl EchoGuard {
+    /// Stop echoing what is typed on the terminal `fd`, `None` if `fd` is
+    /// not a terminal
+    fn disable(fd: i32) -> Option<Self> {
+        // SAFETY: termios is plain data filled by tcgetattr
+        let mut settings: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut settings) } != 0 {
+            return None;
+        }
+
+        let mut silent = settings;
+        silent.c_lflag &= !libc::ECHO;
+        silent.c_lflag |= libc::ECHONL;
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &silent) } != 0 {
+            return None;
+        }
+        Some(EchoGuard { fd, settings })
+    }
+}
+
+impl Drop for EchoGuard {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.settings) };
+    }
+}
+
+/// Puts a terminal in raw mode, the settings are restored when dropped.
+///
+/// Every key is then passed on as is, without echo nor line editing, and
+/// Ctrl-C is a byte rather than a signal.
+pub struct RawMode {
+    fd: i32,
+    settings: libc::termios,
+}
+
+impl RawMode {
+    /// Put the terminal `fd` in raw mode, `None` if `fd` is not a terminal
+    pub fn enable(fd: i32) -> Option<Self> {
+        // SAFETY: termios is plain data filled by tcgetattr
+        let mut settings: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut settings) } != 0 {
+            return None;
+        }
+
+        let mut raw = settings;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+            return None;
+        }
+        Some(RawMode { fd, settings })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.settings) };
+    }
+}
+
+/// Print `prompt` on stderr and read a line from stdin without echoing what
+/// is typed, so that the prompt never ends up in the output of a command.
+///
+/// The trailing new line is removed. Reaching the end of the input is an
+/// error.
+pub fn read_password(prompt: &str) -> io::Result<String> {
+    eprint!("{prompt}");
+    io::stderr().flush()?;
+
+    let stdin = io::stdin();
+    let _guard = EchoGuard::disable(stdin.as_raw_fd());
+
+    let mut password = String::new();
+    if stdin.lock().read_line(&mut password)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "no password given",
+        ));
+    }
+    while password.ends_with(['\n', '\r']) {
+        password.pop();
+    }
+    Ok(password)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn window_size_round_trip() {
+        let size = WindowSize {
+            columns: 80,
+            rows: 24,
+            width: 640,
+            height: 480,
+        };
+        let mut writer = Writer::new();
+        size.write(&mut writer);
+        let bytes = writer.into_bytes();
+        let mut reader = Reader::new(&bytes);
+        assert_eq!(WindowSize::read(&mut reader).unwrap(), size);
+        assert!(reader.is_empty());
+        assert!(WindowSize::read(&mut Reader::new(&bytes[..12])).is_err());
+    }
+
+    #[test]
+    fn signal_given_back() {
+        static HANDLED: AtomicBool = AtomicBool::new(false);
+        extern "C" fn handled(_: libc::c_int) {
+            HANDLED.store(true, Ordering::SeqCst);
+        }
+        let handler = handled as *const () as libc::sighandler_t;
+        unsafe { libc::signal(libc::SIGUSR2, handler) };
+
+        let (calls, called) = mpsc::channel();
+        let mut answers = vec![false, true];
+        on_signal(libc::SIGUSR2, move || {
+            calls.send(()).unwrap();
+            answers.pop().unwrap()
+        })
+        .unwrap();
+        let timeout = Duration::from_secs(5);
+        unsafe { libc::raise(libc::SIGUSR2) };
+        called.recv_timeout(timeout).unwrap();
+        assert!(!HANDLED.load(Ordering::SeqCst));
+
+        // the callback turns this one down, which the old handler gets
+        unsafe { libc::raise(libc::SIGUSR2) };
+        called.recv_timeout(timeout).unwrap();
+        let start = Instant::now();
+        while !HANDLED.load(Ordering::SeqCst) {
+            assert!(start.elapsed() < timeout, "the signal was not given back");
+            thread::sleep(Duration::from_millis(10));
+        }
+        let current = unsafe { libc::signal(libc::SIGUSR2, libc::SIG_DFL) };
+        assert_eq!(current, handler);
+    }
+}
@@ -0,0 +1,325 @@
+//! Encrypted channel shared by the client and the server.
+//!
+//! A connection starts with a hand shake where both sides exchange a RSA
+//! session key and agree on an AES key. Every message is then sent cyphered
+//! with AES, preceded by its length and followed by the SHA256 hash of the
+//! message concatenated with the AES key so that the receiver can check its
+//! integrity.
+
+use crate::crypto::aes;
+use crate::crypto::rsa;
+use crate::crypto::sha;
+use crate::wire::{Reader, Writer};
+use rand::Rng;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+
+/// Credentials of a client logging in by answering questions,
+/// `keyboard-interactive\n<login>`
+pub(crate) const KEYBOARD_INTERACTIVE: &str = "keyboard-interactive";
+
+/// Sent by the server in place of `login or password unknown` when the
+/// client failed too many times, it closes the connection then
+pub(crate) const TOO_MANY_FAILURES: &str = "too many authentication failures";
+
+/// Questions of the server during the authentication and answers of the
+/// client, see `RemoteConversation` in the server
+pub(crate) const INFO_REQUEST: &str = "info-request";
+pub(crate) const INFO_RESPONSE: &str = "info-response";
+
+/// Global request of the client for agent forwarding
+pub(crate) const AGENT_FORWARDING_REQUEST: &str = "auth-agent-req";
+
+/// Type of the channels the server opens to the forwarded agent, one per
+/// local client of its socket
+pub(crate) const AGENT_CHANNEL: &str = "auth-agent@nssh";
+
+/// Generate a random string containing letters and digits.
+/// args:
+/// :lenght: the number of char of the string
+pub(crate) fn generate_random_string(length: usize) -> String {
+    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
+                             abcdefghijklmnopqrstuvwxyz\
+                             0123456789";
+    let mut rng = rand::rng();
+
+    (0..length)
+        .map(|_| {
+            let idx = rng.random_range(0..CHARSET.len());
+            CHARSET[idx] as char
+        })
+        .collect()
+}
+
+/// Biggest message accepted, to not allocate whatever the peer announces
+const MAX_MESSAGE_LEN: usize = 16 << 20;
+
+/// Hash of the concatenation of the message and the aes_key
+fn integrity_hash(message: &[u8], aes_key: &[u8; 16]) -> [u8; 32] {
+    let mut message_and_key = Vec::from(message);
+    message_and_key.extend_from_slice(aes_key);
+    sha::sha256_bytes(message_and_key)
+}
+
+/// Data a client signs to prove it owns a key: it is tied to the session so
+/// that a signature can not be replayed on another connection
+pub(crate) fn publickey_auth_data(session_id: &[u8; 32], login: &str, key_blob: &[u8]) -> Vec<u8> {
+    let mut writer = Writer::new();
+    writer
+        .string(session_id)
+        .string("publickey")
+        .string(login)
+        .string(key_blob);
+    writer.into_bytes()
+}
+
+/// Data a server signs with its host key to prove who it is, `key_blob` is
+/// its public key or its certificate
+pub(crate) fn hostkey_auth_data(session_id: &[u8; 32], key_blob: &[u8]) -> Vec<u8> {
+    let mut writer = Writer::new();
+    writer.string(session_id).string("hostkey").string(key_blob);
+    writer.into_bytes()
+}
+
+/// Write bytes preceded by their length
+fn write_frame(stream: &mut TcpStream, bytes: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(4 + bytes.len());
+    frame.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    frame.extend_from_slice(bytes);
+    stream.write_all(&frame)
+}
+
+/// Read bytes written by write_frame
+fn read_frame(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut length = [0; 4];
+    stream.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_MESSAGE_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message too long",
+        ));
+    }
+
+    let mut bytes = vec![0; length];
+    stream.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// A RSA public key as sent during the hand shake
+fn public_key_blob(key: &rsa::PublicKey) -> Vec<u8> {
+    let mut writer = Writer::new();
+    writer.mpint(&key.e).mpint(&key.n);
+    writer.into_bytes()
+}
+
+/// Read a RSA public key sent with public_key_blob, returns the blob too
+fn read_public_key(stream: &mut TcpStream) -> io::Result<(rsa::PublicKey, Vec<u8>)> {
+    let blob = read_frame(stream)?;
+    let mut reader = Reader::new(&blob);
+    let e = reader.mpint()?;
+    let n = reader.mpint()?;
+    reader.finish()?;
+    if n.bits() < 16 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "RSA session key too small",
+        ));
+    }
+    Ok((rsa::PublicKey::new(n, e), blob))
+}
+
+/// Read an AES key cyphered with our RSA session key
+fn read_aes_key(stream: &mut TcpStream, rsa_session_key: &rsa::PrivateKey) -> io::Result<String> {
+    let buffer = rsa::decypher_message(read_frame(stream)?, rsa_session_key);
+    String::from_utf8(buffer).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Convert the agreed AES key to the [u8; 16] used by the cypher
+fn aes_key_bytes(aes_session_key: &str) -> io::Result<[u8; 16]> {
+    aes_session_key.as_bytes().try_into().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "AES session key must be 16 bytes",
+        )
+    })
+}
+
+/// Identifier of a session, the hash of what was exchanged during the hand
+/// shake
+fn session_id(client_key: &[u8], server_key: &[u8], aes_key: &[u8; 16]) -> [u8; 32] {
+    let mut writer = Writer::new();
+    writer.string(client_key).string(server_key).string(aes_key);
+    sha::sha256_bytes(writer.into_bytes())
+}
+
+/// An established, encrypted connection between a client and a server.
+pub struct Transport {
+    stream: TcpStream,
+    aes_key: [u8; 16],
+    session_id: [u8; 32],
+}
+
+impl Transport {
+    /// Run the client side of the hand shake on a freshly connected stream.
+    ///
+    /// The server chooses the AES key, the client sends it back cyphered with
+    /// the server public key so the server can check it was not altered.
+    pub fn client_handshake(mut stream: TcpStream) -> io::Result<Self> {
+        // generate a rsa session key
+        let rsa_session_key = rsa::PrivateKey::generate();
+
+        // send the rsa public key
+        let client_key = public_key_blob(&rsa_session_key.pub_key);
+        write_frame(&mut stream, &client_key)?;
+
+        // receive the public key of the server
+        let (server_pub_key, server_key) = read_public_key(&mut stream)?;
+
+        // receive the server aes key
+        let aes_session_key = read_aes_key(&mut stream, &rsa_session_key)?;
+
+        // send the received aes key back to the server
+        let message = rsa::cypher_message(Vec::from(aes_session_key.clone()), &server_pub_key);
+        write_frame(&mut stream, &message)?;
+
+        let aes_key = aes_key_bytes(&aes_session_key)?;
+        let session_id = session_id(&client_key, &server_key, &aes_key);
+        Ok(Transport {
+            stream,
+            aes_key,
+            session_id,
+        })
+    }
+
+    /// Run the server side of the hand shake on an accepted stream.
+    pub fn server_handshake(mut stream: TcpStream) -> io::Result<Self> {
+        // generate a rsa session key + 16 char long string for aes
+        let rsa_session_key = rsa::PrivateKey::generate();
+        let aes_session_key = generate_random_string(16);
+
+        // send the rsa public key
+        let server_key = public_key_blob(&rsa_session_key.pub_key);
+        write_frame(&mut stream, &server_key)?;
+
+        // receive the public key of the client
+        let (client_pub_key, client_key) = read_public_key(&mut stream)?;
+
+        // send the crypted aes key to the client
+        let message = rsa::cypher_message(Vec::from(aes_session_key.clone()), &client_pub_key);
+        write_frame(&mut stream, &message)?;
+
+        // receive the aes key as understood by the client
+        let client_aes_key = read_aes_key(&mut stream, &rsa_session_key)?;
+
+        // verifie that the key receive correspond to the key sended
+        if client_aes_key != aes_session_key {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Key verification failed",
+            ));
+        }
+
+        let aes_key = aes_key_bytes(&aes_session_key)?;
+        let session_id = session_id(&client_key, &server_key, &aes_key);
+        Ok(Transport {
+            stream,
+            aes_key,
+            session_id,
+        })
+    }
+
+    /// Identifier of the session, the same on both sides and different for
+    /// every connection
+    pub fn session_id(&self) -> &[u8; 32] {
+        &self.session_id
+    }
+
+    /// Send bytes to the peer, cyphered with aes
+    pub fn send_bytes(&mut self, message: &[u8]) -> io::Result<()> {
+        // the length goes first so that the zeros at the end of the message
+        // are not mistaken for padding
+        let mut clear = Vec::with_capacity(4 + message.len());
+        clear.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        clear.extend_from_slice(message);
+
+        // Send the message, followed by the hash of the concatenation of the
+        // message and the aes_key
+        let encrypted_message = aes::cypher_message(clear, self.aes_key);
+        let mut frame = Vec::with_capacity(encrypted_message.len() + 36);
+        frame.extend_from_slice(&(encrypted_message.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&encrypted_message);
+        frame.extend_from_slice(&integrity_hash(message, &self.aes_key));
+        self.stream.write_all(&frame)
+    }
+
+    /// Receive bytes sent with send_bytes
+    pub fn receive_bytes(&mut self) -> io::Result<Vec<u8>> {
+        // Read the encrypted message from the stream
+        let encrypted_message = read_frame(&mut self.stream).map_err(|e| {
+            if e.kind() == io::ErrorKind::UnexpectedEof {
+                io::Error::new(io::ErrorKind::UnexpectedEof, "Connection closed")
+            } else {
+                e
+            }
+        })?;
+
+        // Decrypt the message using AES and give it back its trailing zeros
+        let mut clear = aes::decypher_message(encrypted_message, self.aes_key);
+        clear.resize(clear.len().max(4), 0);
+        let length = u32::from_be_bytes([clear[0], clear[1], clear[2], clear[3]]) as usize;
+        if length > MAX_MESSAGE_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "message too long",
+            ));
+        }
+        let mut message = clear.split_off(4);
+        message.resize(length, 0);
+
+        // Read the hash from the stream
+        let mut hash_buffer = [0; 32]; // SHA-256 produces a 32-byte hash
+        self.stream.read_exact(&mut hash_buffer)?;
+
+        // Compare the received hash with the one of the decrypted message
+        if integrity_hash(&message, &self.aes_key) != hash_buffer {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Hash verification failed",
+            ));
+        }
+
+        Ok(message)
+    }
+
+    /// Send a string to the peer using aes to cypher the string
+    pub fn send(&mut self, message: &str) -> io::Result<()> {
+        self.send_bytes(message.as_bytes())
+    }
+
+    /// Receive a cyphered string and decypher it before returning a string
+    pub fn receive(&mut self) -> io::Result<String> {
+        String::from_utf8(self.receive_bytes()?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Another handle on the same connection, so that a thread can send
+    /// while another one receives
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(Transport {
+            stream: self.stream.try_clone()?,
+            aes_key: self.aes_key,
+            session_id: self.session_id,
+        })
+    }
+
+    /// Address of the peer at the other end of the connection
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
+    /// Close both directions of the connection
+    pub fn shutdown(&self) -> io::Result<()> {
+        self.stream.shutdown(Shutdown::Both)
+    }
+}
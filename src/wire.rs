@@ -0,0 +1,195 @@
+//! Binary encoding of keys and signatures, the same as SSH (RFC 4251).
+//!
+//! Integers are big endian u32, strings are prefixed by their length and
+//! big numbers are strings of their big endian two's complement bytes.
+
+use crate::crypto::bigint::BigUint;
+use std::io;
+
+/// Build a binary message field by field.
+#[derive(Debug, Default, Clone)]
+pub struct Writer {
+    buffer: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        Writer::default()
+    }
+
+    pub fn u8(&mut self, value: u8) -> &mut Self {
+        self.buffer.push(value);
+        self
+    }
+
+    /// A boolean as a byte, 1 for true
+    pub fn bool(&mut self, value: bool) -> &mut Self {
+        self.u8(value as u8)
+    }
+
+    pub fn u32(&mut self, value: u32) -> &mut Self {
+        self.buffer.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn u64(&mut self, value: u64) -> &mut Self {
+        self.buffer.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    /// Bytes written as they are, without their length
+    pub fn raw(&mut self, bytes: &[u8]) -> &mut Self {
+        self.buffer.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn string(&mut self, bytes: impl AsRef<[u8]>) -> &mut Self {
+        let bytes = bytes.as_ref();
+        self.u32(bytes.len() as u32).raw(bytes)
+    }
+
+    pub fn mpint(&mut self, number: &BigUint) -> &mut Self {
+        let mut bytes = number.to_bytes_be();
+        // a leading 1 bit would make the number negative
+        if bytes.first().is_some_and(|&byte| byte & 0x80 != 0) {
+            bytes.insert(0, 0);
+        }
+        self.string(bytes)
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "truncated message")
+}
+
+/// Read a binary message field by field.
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    /// The next `length` bytes
+    pub fn raw(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < length {
+            return Err(truncated());
+        }
+        let (bytes, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.raw(1)?[0])
+    }
+
+    /// A boolean, any byte but 0 is true
+    pub fn bool(&mut self) -> io::Result<bool> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u32(&mut self) -> io::Result<u32> {
+        let bytes = self.raw(4)?;
+        Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> io::Result<u64> {
+        let bytes = self.raw(8)?;
+        Ok(u64::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn string(&mut self) -> io::Result<&'a [u8]> {
+        let length = self.u32()? as usize;
+        self.raw(length)
+    }
+
+    /// A string that must be valid UTF-8
+    pub fn str(&mut self) -> io::Result<&'a str> {
+        std::str::from_utf8(self.string()?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn mpint(&mut self) -> io::Result<BigUint> {
+        let bytes = self.string()?;
+        if bytes.first().is_some_and(|&byte| byte & 0x80 != 0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "negative number",
+            ));
+        }
+        Ok(BigUint::from_bytes_be(bytes))
+    }
+
+    /// Everything not read yet
+    pub fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.data)
+    }
+
+    /// Whether everything has been read
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Fail if some bytes were not read
+    pub fn finish(&self) -> io::Result<()> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected data at the end of the message",
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut writer = Writer::new();
+        writer
+            .u8(2)
+            .bool(true)
+            .u32(7)
+            .u64(1 << 40)
+            .string("ssh-rsa")
+            .mpint(&BigUint::from(0x80u32))
+            .mpint(&BigUint::zero());
+        let bytes = writer.into_bytes();
+        assert_eq!(
+            bytes,
+            b"\x02\x01\0\0\0\x07\0\0\x01\0\0\0\0\0\0\0\0\x07ssh-rsa\0\0\0\x02\0\x80\0\0\0\0".to_vec()
+        );
+
+        let mut reader = Reader::new(&bytes);
+        assert_eq!(reader.u8().unwrap(), 2);
+        assert!(reader.bool().unwrap());
+        assert_eq!(reader.u32().unwrap(), 7);
+        assert_eq!(reader.u64().unwrap(), 1 << 40);
+        assert_eq!(reader.str().unwrap(), "ssh-rsa");
+        assert_eq!(reader.mpint().unwrap(), BigUint::from(0x80u32));
+        assert_eq!(reader.mpint().unwrap(), BigUint::zero());
+        assert!(reader.finish().is_ok());
+    }
+
+    #[test]
+    fn truncated_message() {
+        let mut reader = Reader::new(b"\0\0\0\x05abc");
+        assert!(reader.string().is_err());
+        assert!(Reader::new(b"").u8().is_err());
+        assert!(Reader::new(b"\0\0").u32().is_err());
+        assert!(Reader::new(b"\0\0\0\0").u64().is_err());
+        assert!(Reader::new(b"\0\0\0\x01\x80").mpint().is_err());
+    }
+}